@@ -0,0 +1,395 @@
+// GROUP BY, a hash aggregation over the filtered records.
+// A record belongs to one group for every combination of the distinct values
+// of the group keys, so grouping by a repeatable field counts facets. Records
+// without a value for a key go to a group where that key is missing.
+use crate::field_expression::FieldExpression;
+use crate::filter::Filter;
+use crate::ownedrecord::OwnedRecord;
+use crate::record::{OwnedRecordField, Record};
+use crate::sort::{compare_keys, compare_value, KeyValues};
+use crate::util::TriStateBool;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFn {
+    // count(*) counts records, count(field) counts values
+    Count,
+    CountDistinct,
+    Min,
+    Max,
+}
+
+impl AggregateFn {
+    // names are case-insensitive, like keywords
+    pub fn from_name(name: &str, distinct: bool) -> Option<AggregateFn> {
+        match (name.to_ascii_lowercase().as_str(), distinct) {
+            ("count", false) => Some(AggregateFn::Count),
+            ("count", true) => Some(AggregateFn::CountDistinct),
+            ("min", false) => Some(AggregateFn::Min),
+            ("max", false) => Some(AggregateFn::Max),
+            _ => None,
+        }
+    }
+}
+
+pub struct Aggregate {
+    pub func: AggregateFn,
+    // None for count(*)
+    pub expr: Option<Box<dyn FieldExpression>>,
+    // the field type of the output column
+    pub field_type: usize,
+}
+
+impl Aggregate {
+    fn describe(&self) -> String {
+        let argument = self.expr.as_ref().map_or("*".to_string(), |x| x.describe());
+        match self.func {
+            AggregateFn::Count => format!("count({})", argument),
+            AggregateFn::CountDistinct => format!("count(distinct {})", argument),
+            AggregateFn::Min => format!("min({})", argument),
+            AggregateFn::Max => format!("max({})", argument),
+        }
+    }
+}
+
+enum AggregateState {
+    Count(usize),
+    Distinct(HashSet<Vec<u8>>),
+    Extreme(Option<Vec<u8>>),
+}
+
+impl AggregateState {
+    fn new(func: AggregateFn) -> AggregateState {
+        match func {
+            AggregateFn::Count => AggregateState::Count(0),
+            AggregateFn::CountDistinct => AggregateState::Distinct(HashSet::new()),
+            AggregateFn::Min | AggregateFn::Max => AggregateState::Extreme(None),
+        }
+    }
+
+    fn update(&mut self, func: AggregateFn, values: &Option<Vec<Vec<u8>>>) {
+        match (self, values) {
+            (AggregateState::Count(n), None) => *n += 1,
+            (AggregateState::Count(n), Some(values)) => *n += values.len(),
+            (AggregateState::Distinct(set), Some(values)) => {
+                for v in values {
+                    if !set.contains(v) {
+                        set.insert(v.clone());
+                    }
+                }
+            }
+            (AggregateState::Extreme(current), Some(values)) => {
+                let wanted = if func == AggregateFn::Min {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                };
+                for v in values {
+                    let replace = match current {
+                        None => true,
+                        Some(c) => compare_value(Some(v), Some(c)) == wanted,
+                    };
+                    if replace {
+                        *current = Some(v.clone());
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn value(&self) -> Option<Vec<u8>> {
+        match self {
+            AggregateState::Count(n) => Some(n.to_string().into_bytes()),
+            AggregateState::Distinct(set) => Some(set.len().to_string().into_bytes()),
+            AggregateState::Extreme(v) => v.clone(),
+        }
+    }
+}
+
+// an output column is either a group key or an aggregate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    Key(usize),
+    Aggregate(usize),
+}
+
+type GroupKey = Vec<Option<(usize, Vec<u8>)>>;
+// the field type and value of every output column
+type Row = Vec<Option<(usize, Vec<u8>)>>;
+
+pub struct HashAggregation {
+    keys: Vec<Box<dyn FieldExpression>>,
+    aggregates: Vec<Aggregate>,
+    columns: Vec<Column>,
+    // (column, descending)
+    order: Vec<(usize, bool)>,
+    // evaluated on the group record, see group_record
+    having: Option<Box<dyn Filter>>,
+    index: HashMap<GroupKey, usize>,
+    // in the order the groups were first seen
+    groups: Vec<(GroupKey, Vec<AggregateState>)>,
+}
+
+impl HashAggregation {
+    pub fn new(
+        keys: Vec<Box<dyn FieldExpression>>,
+        aggregates: Vec<Aggregate>,
+        columns: Vec<Column>,
+        order: Vec<(usize, bool)>,
+    ) -> HashAggregation {
+        HashAggregation {
+            keys,
+            aggregates,
+            columns,
+            order,
+            having: None,
+            index: HashMap::new(),
+            groups: Vec::new(),
+        }
+    }
+
+    // only groups for which the filter is true are output
+    pub fn with_having(mut self, having: Box<dyn Filter>) -> HashAggregation {
+        self.having = Some(having);
+        self
+    }
+
+    // the group keys are the fields 0..n, the aggregates follow
+    fn group_record(&self, key: &GroupKey, states: &[AggregateState]) -> OwnedRecord {
+        let mut record = OwnedRecord::new();
+        for (i, value) in key.iter().enumerate() {
+            if let Some((_, data)) = value {
+                record.add_field(OwnedRecordField {
+                    field_type: i,
+                    data: data.clone(),
+                });
+            }
+        }
+        for (i, state) in states.iter().enumerate() {
+            if let Some(data) = state.value() {
+                record.add_field(OwnedRecordField {
+                    field_type: key.len() + i,
+                    data,
+                });
+            }
+        }
+        record
+    }
+
+    fn group(&mut self, key: GroupKey) -> &mut Vec<AggregateState> {
+        let i = match self.index.get(&key) {
+            Some(i) => *i,
+            None => {
+                let states = self
+                    .aggregates
+                    .iter()
+                    .map(|x| AggregateState::new(x.func))
+                    .collect();
+                self.groups.push((key.clone(), states));
+                self.index.insert(key, self.groups.len() - 1);
+                self.groups.len() - 1
+            }
+        };
+        &mut self.groups[i].1
+    }
+
+    pub fn add(&mut self, record: &dyn Record) {
+        // the groups of the record, the cross product of the key values
+        let mut group_keys: Vec<GroupKey> = vec![Vec::new()];
+        for key in &self.keys {
+            let mut values: Vec<Option<(usize, Vec<u8>)>> = Vec::new();
+            for field in key.compute(record) {
+                let value = Some((field.field_type, field.data.to_vec()));
+                if !values.contains(&value) {
+                    values.push(value);
+                }
+            }
+            if values.is_empty() {
+                values.push(None);
+            }
+            group_keys = group_keys
+                .into_iter()
+                .flat_map(|prefix| {
+                    values.iter().map(move |v| {
+                        let mut k = prefix.clone();
+                        k.push(v.clone());
+                        k
+                    })
+                })
+                .collect();
+        }
+        let inputs: Vec<Option<Vec<Vec<u8>>>> = self
+            .aggregates
+            .iter()
+            .map(|x| {
+                x.expr
+                    .as_ref()
+                    .map(|e| e.compute(record).map(|f| f.data.to_vec()).collect())
+            })
+            .collect();
+        let funcs: Vec<AggregateFn> = self.aggregates.iter().map(|x| x.func).collect();
+        for key in group_keys {
+            for (i, state) in self.group(key).iter_mut().enumerate() {
+                state.update(funcs[i], &inputs[i]);
+            }
+        }
+    }
+
+    // the group keys and the expression of every column, for explain
+    pub fn describe(&self) -> (Vec<String>, Vec<String>) {
+        let keys = self.keys.iter().map(|x| x.describe()).collect();
+        let columns = self
+            .columns
+            .iter()
+            .map(|column| match column {
+                Column::Key(i) => self.keys[*i].describe(),
+                Column::Aggregate(i) => self.aggregates[*i].describe(),
+            })
+            .collect();
+        (keys, columns)
+    }
+
+    pub fn having(&self) -> Option<&dyn Filter> {
+        self.having.as_deref()
+    }
+
+    pub fn having_mut(&mut self) -> &mut Option<Box<dyn Filter>> {
+        &mut self.having
+    }
+
+    // one row per group, the columns that have no value are left out
+    pub fn rows(mut self) -> Vec<OwnedRecord> {
+        // without group by there is exactly one group, even for no records
+        if self.keys.is_empty() && self.groups.is_empty() {
+            self.group(Vec::new());
+        }
+        let mut rows: Vec<Row> = self
+            .groups
+            .iter()
+            .filter(|(key, states)| match &self.having {
+                Some(having) => {
+                    having.evaluate_predicate(&self.group_record(key, states)) == TriStateBool::True
+                }
+                None => true,
+            })
+            .map(|(key, states)| {
+                self.columns
+                    .iter()
+                    .map(|c| match c {
+                        Column::Key(i) => key[*i].clone(),
+                        Column::Aggregate(i) => states[*i]
+                            .value()
+                            .map(|v| (self.aggregates[*i].field_type, v)),
+                    })
+                    .collect()
+            })
+            .collect();
+        if !self.order.is_empty() {
+            let descending: Vec<bool> = self.order.iter().map(|x| x.1).collect();
+            let mut keyed: Vec<(KeyValues, Row)> = rows
+                .into_iter()
+                .map(|row| {
+                    let keys = self
+                        .order
+                        .iter()
+                        .map(|(c, _)| row[*c].as_ref().map(|x| x.1.clone()))
+                        .collect();
+                    (keys, row)
+                })
+                .collect();
+            keyed.sort_by(|a, b| compare_keys(&descending, &a.0, &b.0));
+            rows = keyed.into_iter().map(|x| x.1).collect();
+        }
+        rows.into_iter()
+            .map(|row| {
+                let mut record = OwnedRecord::new();
+                for (column, value) in row.into_iter().enumerate() {
+                    if let Some((field_type, data)) = value {
+                        record.add_field(OwnedRecordField { field_type, data });
+                        record.set_column(column);
+                    }
+                }
+                record
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::aggregate::*;
+    use crate::field_expression::*;
+    use crate::ownedrecord::test_record;
+
+    fn field(field_type: &str) -> Box<dyn FieldExpression> {
+        Box::new(FieldRefExpr::new(None, Some(field_type), None))
+    }
+
+    fn values(r: &OwnedRecord) -> Vec<String> {
+        r.field_iter(None)
+            .map(|x| format!("{}:{}", x.field_type, x.utf8_data()))
+            .collect()
+    }
+
+    #[test]
+    fn test_group_by() {
+        let aggregates = vec![
+            Aggregate {
+                func: AggregateFn::Count,
+                expr: None,
+                field_type: 0,
+            },
+            Aggregate {
+                func: AggregateFn::CountDistinct,
+                expr: Some(field("2")),
+                field_type: 2,
+            },
+            Aggregate {
+                func: AggregateFn::Max,
+                expr: Some(field("2")),
+                field_type: 2,
+            },
+        ];
+        let columns = vec![
+            Column::Key(0),
+            Column::Aggregate(0),
+            Column::Aggregate(1),
+            Column::Aggregate(2),
+        ];
+        let mut aggregation = HashAggregation::new(vec![field("1")], aggregates, columns, vec![]);
+        aggregation.add(&test_record(&[(1, "x"), (2, "9"), (2, "10")]));
+        aggregation.add(&test_record(&[(1, "y"), (1, "x"), (2, "9")]));
+        aggregation.add(&test_record(&[(2, "b")]));
+        let rows: Vec<Vec<String>> = aggregation.rows().iter().map(values).collect();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["1:x", "0:2", "2:2", "2:10"],
+                vec!["1:y", "0:1", "2:1", "2:9"],
+                vec!["0:1", "2:1", "2:b"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_group_by() {
+        let aggregates = vec![
+            Aggregate {
+                func: AggregateFn::Count,
+                expr: None,
+                field_type: 0,
+            },
+            Aggregate {
+                func: AggregateFn::Min,
+                expr: Some(field("2")),
+                field_type: 2,
+            },
+        ];
+        let columns = vec![Column::Aggregate(0), Column::Aggregate(1)];
+        let aggregation = HashAggregation::new(vec![], aggregates, columns, vec![(0, true)]);
+        let rows: Vec<Vec<String>> = aggregation.rows().iter().map(values).collect();
+        assert_eq!(rows, vec![vec!["0:0"]]);
+    }
+}
@@ -1,14 +1,91 @@
+use crate::aggregate::*;
+use crate::distinct::{DistinctOn, HashDistinct};
 use crate::field_expression::*;
 use crate::filter::*;
+use crate::join::Join;
 use crate::parser::*;
+use crate::profile::ReaderStats;
 use crate::projection::*;
+use crate::record::RecordType;
+use crate::scalar::*;
+use crate::sort::{SortKey, DEFAULT_MEMORY_BUDGET};
+use crate::table::TableRef;
 use std::any::TypeId;
+use std::cell::OnceCell;
+use std::rc::Rc;
+
+// the table aliases of the from clause. The qualifier of a field ref is an
+// alias or a record type, aliases win. In a join every field ref but * needs
+// the alias of its table.
+#[derive(Default)]
+struct Scope {
+    // the alias and the side of the join, 0 for the tables of the from clause
+    aliases: Vec<(String, usize)>,
+    join: bool,
+}
+
+impl Scope {
+    fn field_ref(&self, node: &ParseNode) -> Result<FieldRefExpr, QueryError> {
+        Ok(match self.resolve(node)? {
+            (Some(side), expr) => expr.with_side(side),
+            (None, expr) => expr,
+        })
+    }
+
+    // the side of a join the field ref belongs to and the expression for
+    // the records of that side
+    fn resolve(&self, node: &ParseNode) -> Result<(Option<usize>, FieldRefExpr), QueryError> {
+        let (qualifier, field_type, subfield_type) = match node.entry {
+            LexItem::FieldRef(qualifier, field_type, subfield_type) => {
+                (qualifier, field_type, subfield_type)
+            }
+            _ => unreachable!(),
+        };
+        let alias = qualifier.and_then(|q| self.aliases.iter().find(|(a, _)| a == q));
+        let (side, record_type) = match (alias, qualifier) {
+            (Some((_, side)), _) => (Some(*side), None),
+            (None, Some(q)) if q != "*" && RecordType::from_str(q).is_none() => {
+                return Err(QueryError::invalid(
+                    &node.context,
+                    format!("unknown table alias or record type {}", q),
+                ))
+            }
+            (None, _) if self.join && field_type != Some("*") => {
+                return Err(QueryError::invalid(
+                    &node.context,
+                    format!("{} needs the alias of its table in a join", node.text()),
+                ))
+            }
+            (None, q) => (None, q),
+        };
+        let expr = FieldRefExpr::new(record_type, field_type, subfield_type);
+        let expr = match node.children.first().map(|x| &x.entry) {
+            Some(LexItem::Slice(start, end)) => expr.with_range(*start, *end),
+            _ => expr,
+        };
+        Ok((side.filter(|_| self.join), expr))
+    }
+}
 
 struct TranslationVisitor {
     filter_exprs: Vec<Box<dyn Filter>>,
     field_exprs: Vec<Box<dyn FieldExpression>>,
-    projection_exprs: Vec<Box<dyn FieldExpression>>,
-    table_name: String,
+    projection_exprs: Vec<Box<dyn ScalarExpression>>,
+    sort_keys: Vec<SortKey>,
+    aggregation: Option<HashAggregation>,
+    distinct: Option<HashDistinct>,
+    column_names: Vec<String>,
+    count_columns: Vec<usize>,
+    limit: Option<usize>,
+    offset: usize,
+    tables: Vec<TableRef>,
+    join: Option<Join>,
+    scope: Scope,
+    subqueries: Vec<Subquery>,
+    error: Option<QueryError>,
+    // the nodes of having that are group keys or aggregates, by their
+    // position in the query, with their field of the group record
+    group_columns: Vec<(usize, usize)>,
 }
 
 impl TranslationVisitor {
@@ -17,19 +94,391 @@ impl TranslationVisitor {
             filter_exprs: Vec::new(),
             field_exprs: Vec::new(),
             projection_exprs: Vec::new(),
-            table_name: "".to_string(),
+            sort_keys: Vec::new(),
+            aggregation: None,
+            distinct: None,
+            column_names: Vec::new(),
+            count_columns: Vec::new(),
+            limit: None,
+            offset: 0,
+            tables: Vec::new(),
+            join: None,
+            scope: Scope::default(),
+            subqueries: Vec::new(),
+            error: None,
+            group_columns: Vec::new(),
         }
     }
 
-    fn flatten(mut x: Box<dyn Filter>, arguments: &mut Vec<Box<dyn Filter>>) {
-        if x.type_id() == TypeId::of::<OrFilter>() {
+    // the field of the group record a node of having stands for
+    fn group_column(&self, node: &ParseNode) -> Option<usize> {
+        self.group_columns
+            .iter()
+            .find(|(position, _)| *position == node.context.0)
+            .map(|(_, column)| *column)
+    }
+
+    // the clauses of the select, the where clause is translated by visiting it
+    fn translate_select(&mut self, node: &ParseNode) -> Result<(), QueryError> {
+        // the select list comes before the from
+        let table_pos = node
+            .children
+            .iter()
+            .position(|x| x.entry == LexItem::KW(Keyword::FromKW))
+            .unwrap();
+        self.translate_from(&node.children[table_pos])?;
+        let mut columns = &node.children[..table_pos];
+        let mut distinct = None;
+        if let Some(c) = columns
+            .first()
+            .filter(|c| c.entry == LexItem::KW(Keyword::Distinct))
+        {
+            distinct = Some(c);
+            columns = &columns[1..];
+        }
+        // aliased columns are the first child of an as node
+        let columns: Vec<&ParseNode> = columns
+            .iter()
+            .map(|c| match (&c.entry, c.children.get(1).map(|x| &x.entry)) {
+                (
+                    LexItem::KW(Keyword::As),
+                    Some(LexItem::Identifier(name) | LexItem::StringLit(name)),
+                ) => {
+                    self.column_names.push(unescape_str(name));
+                    &c.children[0]
+                }
+                _ => {
+                    self.column_names.push(c.text());
+                    c
+                }
+            })
+            .collect();
+        let mut group_by = None;
+        let mut having = None;
+        let mut order_by = None;
+        for c in &node.children[table_pos + 1..] {
+            match c.entry {
+                LexItem::KW(Keyword::GroupBy) => group_by = Some(c),
+                LexItem::KW(Keyword::Having) => having = Some(c),
+                LexItem::KW(Keyword::OrderBy) => order_by = Some(c),
+                LexItem::KW(Keyword::Limit) => {
+                    self.limit = Some(count(c)?);
+                    if let Some(offset) = c.children.get(1) {
+                        self.offset = count(offset)?;
+                    }
+                }
+                // the condition of the where clause
+                _ => {
+                    c.visit(self);
+                    if let Some(e) = self.error.take() {
+                        return Err(e);
+                    }
+                    let filter = self.condition(c)?;
+                    self.filter_exprs.push(filter);
+                }
+            }
+        }
+        let sort_keys: Vec<(&ParseNode, bool)> = order_by
+            .map(|x| {
+                x.children
+                    .iter()
+                    .map(|k| (&k.children[0], k.entry == LexItem::KW(Keyword::Desc)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let grouped = group_by.is_some() || columns.iter().any(|x| is_aggregate(x));
+        if let Some(distinct) = distinct {
+            let on = if !distinct.children.is_empty() {
+                if grouped {
+                    return Err(QueryError::invalid(
+                        &distinct.context,
+                        "distinct on can't be used with a group by".to_string(),
+                    ));
+                }
+                let mut fields: Vec<Box<dyn FieldExpression>> = Vec::new();
+                for x in &distinct.children {
+                    fields.push(Box::new(self.scope.field_ref(x)?));
+                }
+                DistinctOn::Fields(fields)
+            } else if !grouped
+                && columns.len() == 1
+                && columns[0].entry == LexItem::FieldRef(None, Some("*"), None)
+                && columns[0].children.is_empty()
+            {
+                // whole records, the projection would drop the leader
+                DistinctOn::Record
+            } else {
+                DistinctOn::Row
+            };
+            self.distinct = Some(HashDistinct::new(on, DEFAULT_MEMORY_BUDGET));
+        }
+        if !grouped {
+            for c in &columns {
+                self.projection_exprs.push(scalar_expr(c, &self.scope)?);
+            }
+            for (key, descending) in sort_keys {
+                match key.entry {
+                    LexItem::FieldRef(..) => self.sort_keys.push(SortKey {
+                        expr: Box::new(self.scope.field_ref(key)?),
+                        descending,
+                    }),
+                    _ if is_aggregate(key) => {
+                        return Err(QueryError::invalid(
+                            &key.context,
+                            "aggregates in order by need a group by".to_string(),
+                        ))
+                    }
+                    _ => {
+                        return Err(QueryError::invalid(
+                            &key.context,
+                            format!("can't order by {}, only by fields", key.text()),
+                        ))
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        let keys: Vec<&ParseNode> = group_by
+            .map(|x| x.children.iter().collect())
+            .unwrap_or_default();
+        let mut aggregates = Vec::new();
+        let mut aggregate_nodes: Vec<ParseNode> = Vec::new();
+        let mut output = Vec::new();
+        for (i, c) in columns.iter().copied().enumerate() {
+            if is_aggregate(c) {
+                let aggregate = aggregate(c, &self.scope)?;
+                if matches!(
+                    aggregate.func,
+                    AggregateFn::Count | AggregateFn::CountDistinct
+                ) {
+                    self.count_columns.push(i);
+                }
+                aggregates.push(aggregate);
+                aggregate_nodes.push(c.clone());
+                output.push(Column::Aggregate(aggregates.len() - 1));
+            } else if let Some(i) = keys.iter().position(|k| k.same_as(c)) {
+                output.push(Column::Key(i));
+            } else {
+                return Err(QueryError::invalid(
+                    &c.context,
+                    format!(
+                        "{} must appear in the group by or in an aggregate",
+                        c.text()
+                    ),
+                ));
+            }
+        }
+        let mut order = Vec::new();
+        for (key, descending) in sort_keys {
+            match columns.iter().position(|c| c.same_as(key)) {
+                Some(i) => order.push((i, descending)),
+                None => {
+                    return Err(QueryError::invalid(
+                        &key.context,
+                        format!(
+                            "order by {} must be a column of the grouped select",
+                            key.text()
+                        ),
+                    ))
+                }
+            }
+        }
+        let mut having_filter = None;
+        if let Some(having) = having {
+            let condition = &having.children[0];
+            let selected = aggregate_nodes.len();
+            having_aggregates(condition, &keys, &mut aggregate_nodes)?;
+            for node in &aggregate_nodes[selected..] {
+                aggregates.push(aggregate(node, &self.scope)?);
+            }
+            // the group keys and aggregates are the fields of the group record
+            let mut visitor = TranslationVisitor::new();
+            let group_record: Vec<&ParseNode> =
+                keys.iter().copied().chain(aggregate_nodes.iter()).collect();
+            find_group_columns(condition, &group_record, &mut visitor.group_columns);
+            condition.visit(&mut visitor);
+            if let Some(e) = visitor.error {
+                return Err(e);
+            }
+            self.subqueries.append(&mut visitor.subqueries);
+            having_filter = Some(visitor.condition(condition)?);
+        }
+        let mut key_exprs: Vec<Box<dyn FieldExpression>> = Vec::new();
+        for x in &keys {
+            key_exprs.push(Box::new(self.scope.field_ref(x)?));
+        }
+        let mut aggregation = HashAggregation::new(key_exprs, aggregates, output, order);
+        if let Some(filter) = having_filter {
+            aggregation = aggregation.with_having(filter);
+        }
+        self.aggregation = Some(aggregation);
+        Ok(())
+    }
+
+    // the tables, their aliases and the join. Without an alias the name of a
+    // table is its alias in a join.
+    fn translate_from(&mut self, node: &ParseNode) -> Result<(), QueryError> {
+        let join = node
+            .children
+            .iter()
+            .find(|x| x.entry == LexItem::KW(Keyword::Join));
+        self.scope.join = join.is_some();
+        let add_table = |scope: &mut Scope, table: &ParseNode, side| {
+            let alias_node = table.children.first().unwrap_or(table);
+            let alias = match (table.children.first().map(|x| &x.entry), &table.entry) {
+                (Some(LexItem::Identifier(alias)), _) => Some(alias),
+                (_, LexItem::Identifier(name)) if scope.join => Some(name),
+                _ => None,
+            };
+            if let Some(alias) = alias {
+                if scope.aliases.iter().any(|(a, _)| a == alias) {
+                    return Err(QueryError::invalid(
+                        &alias_node.context,
+                        format!("the table alias {} is used twice", alias),
+                    ));
+                }
+                scope.aliases.push((alias.to_string(), side));
+            }
+            Ok(match table.entry {
+                LexItem::StringLit(path) => TableRef::Path(unescape_str(path)),
+                LexItem::Identifier(name) => TableRef::Name(name.to_string()),
+                _ => unreachable!(),
+            })
+        };
+        for table in &node.children {
+            if table.entry != LexItem::KW(Keyword::Join) {
+                self.tables.push(add_table(&mut self.scope, table, 0)?);
+            }
+        }
+        let join = match join {
+            Some(join) => join,
+            None => return Ok(()),
+        };
+        let table = add_table(&mut self.scope, &join.children[0], 1)?;
+        // the condition is an equality of a field of each side, in any order
+        let condition = &join.children[1];
+        let keys = match (&condition.entry, condition.children.as_slice()) {
+            (LexItem::InfixFunction(InfixFn::EqOp), [a, b])
+                if matches!(a.entry, LexItem::FieldRef(..))
+                    && matches!(b.entry, LexItem::FieldRef(..)) =>
+            {
+                match (self.scope.resolve(a)?, self.scope.resolve(b)?) {
+                    ((Some(0), left), (Some(1), right)) | ((Some(1), right), (Some(0), left)) => {
+                        Some((left, right))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let (left_key, right_key) = keys.ok_or_else(|| {
+            QueryError::invalid(
+                &condition.context,
+                "the condition of a join must be an equality of a field of each table".to_string(),
+            )
+        })?;
+        self.join = Some(Join {
+            table,
+            left_key: Box::new(left_key),
+            right_key: Box::new(right_key),
+        });
+        Ok(())
+    }
+
+    // the translation of a child node, field refs and filters are on different stacks
+    // and literals are translated in place
+    fn get_input(&mut self, child: &ParseNode) -> Result<FilterInput, QueryError> {
+        let input = match child.entry {
+            _ if self.group_column(child).is_some() => {
+                self.field_exprs.pop().map(FilterInput::FieldRef)
+            }
+            LexItem::FieldRef(..) => self.field_exprs.pop().map(FilterInput::FieldRef),
+            LexItem::StringLit(s) => Some(FilterInput::Literal(Literal::Str(
+                unescape_str(s).into_bytes(),
+            ))),
+            LexItem::NumberLit(n) => n
+                .parse::<f64>()
+                .ok()
+                .map(|x| FilterInput::Literal(Literal::Number(x))),
+            _ => self.filter_exprs.pop().map(FilterInput::Filter),
+        };
+        input.ok_or_else(|| {
+            QueryError::invalid(&child.context, format!("{} has no value", child.text()))
+        })
+    }
+
+    // the translation of an argument of and, or and not
+    fn condition(&mut self, child: &ParseNode) -> Result<Box<dyn Filter>, QueryError> {
+        let filter = match child.entry {
+            _ if self.group_column(child).is_some() => None,
+            LexItem::FieldRef(..) | LexItem::StringLit(_) | LexItem::NumberLit(_) => None,
+            _ => self.filter_exprs.pop(),
+        };
+        filter.ok_or_else(|| {
+            QueryError::expected(
+                "a condition",
+                Some(&(child.context.clone(), child.entry.clone())),
+            )
+        })
+    }
+
+    // the sides of a comparison, a condition only compares with a condition
+    fn operands(&mut self, node: &ParseNode) -> Result<(FilterInput, FilterInput), QueryError> {
+        let rhs = self.get_input(&node.children[1])?;
+        let lhs = self.get_input(&node.children[0])?;
+        match (&lhs, &rhs) {
+            (FilterInput::Filter(_), FilterInput::Filter(_)) => Ok((lhs, rhs)),
+            (FilterInput::Filter(_), _) | (_, FilterInput::Filter(_)) => Err(QueryError::invalid(
+                &node.context,
+                format!("{} can't compare a condition with a value", node.entry),
+            )),
+            _ => Ok((lhs, rhs)),
+        }
+    }
+
+    // the values of the only column of a subquery, known once it has run
+    fn in_subquery(&mut self, node: &ParseNode) -> Result<Rc<OnceCell<ValueSet>>, QueryError> {
+        let query = compile_select(node)?;
+        if query.columns.len() != 1 {
+            return Err(QueryError::invalid(
+                &node.context,
+                "a subquery of in must select one column".to_string(),
+            ));
+        }
+        let values = Rc::new(OnceCell::new());
+        self.subqueries.push(Subquery {
+            query,
+            result: SubqueryResult::Values(values.clone()),
+        });
+        Ok(values)
+    }
+
+    // one row is enough to know that there is one
+    fn exists_subquery(&mut self, node: &ParseNode) -> Result<Rc<OnceCell<bool>>, QueryError> {
+        let mut query = compile_select(node)?;
+        query.limit = Some(query.limit.map_or(1, |x| std::cmp::min(x, 1)));
+        let result = Rc::new(OnceCell::new());
+        self.subqueries.push(Subquery {
+            query,
+            result: SubqueryResult::Exists(result.clone()),
+        });
+        Ok(result)
+    }
+
+    // an argument of the same kind as the parent is merged into it, an and
+    // below an or stays an argument of its own
+    fn flatten(mut x: Box<dyn Filter>, parent: TypeId, arguments: &mut Vec<Box<dyn Filter>>) {
+        if x.type_id() != parent {
+            arguments.push(x);
+        } else if parent == TypeId::of::<OrFilter>() {
             match x.as_any().downcast_mut::<OrFilter>() {
                 Some(b) => {
                     arguments.extend(std::mem::take(&mut b.children));
                 }
                 None => panic!("&x isn't a OrFilter!"),
             };
-        } else if x.type_id() == TypeId::of::<AndFilter>() {
+        } else if parent == TypeId::of::<AndFilter>() {
             match x.as_any().downcast_mut::<AndFilter>() {
                 Some(b) => {
                     arguments.extend(std::mem::take(&mut b.children));
@@ -43,37 +492,45 @@ impl TranslationVisitor {
 }
 
 impl<'a> ParseTreeVisitor<'a> for TranslationVisitor {
-    fn pre(&mut self, _node: &ParseNode) -> bool {
-        true
+    // subqueries are compiled by their parent, the columns of having are
+    // fields of the group record
+    fn pre(&mut self, node: &ParseNode) -> bool {
+        node.entry != LexItem::KW(Keyword::Select) && self.group_column(node).is_none()
     }
 
     fn post(&mut self, node: &ParseNode) -> bool {
-        match node.entry {
-            LexItem::KW(Keyword::Select) => {
-                // FieldRef children of select are projections
-                for c in &node.children {
-                    if let LexItem::FieldRef(r, f, s) = c.entry {
-                        self.projection_exprs
-                            .push(Box::new(FieldRefExpr::new(r, f, s)));
-                    }
-                }
-                true
+        match self.translate_node(node) {
+            Ok(()) => true,
+            Err(e) => {
+                self.error = Some(e);
+                false
             }
+        }
+    }
+}
+
+impl TranslationVisitor {
+    // the children of the node are translated already
+    fn translate_node(&mut self, node: &ParseNode) -> Result<(), QueryError> {
+        if let Some(i) = self.group_column(node) {
+            let field_type = i.to_string();
+            let expr = FieldRefExpr::new(None, Some(&field_type), None);
+            self.field_exprs.push(Box::new(expr));
+            return Ok(());
+        }
+        match node.entry {
+            // the clauses of a select are translated by translate_select
+            LexItem::KW(Keyword::Select) => {}
+            LexItem::KW(_) => unreachable!(),
             LexItem::Punctuation(Punctuation::Comma) => {
                 unreachable!()
             }
-            LexItem::KW(Keyword::FromKW) => {
-                unreachable!()
-            }
-            LexItem::KW(Keyword::Where) => {
-                unreachable!()
-            }
             LexItem::InfixFunction(InfixFn::Or) => {
-                let second: Box<dyn Filter> = self.filter_exprs.pop().unwrap();
-                let first: Box<dyn Filter> = self.filter_exprs.pop().unwrap();
+                let second = self.condition(&node.children[1])?;
+                let first = self.condition(&node.children[0])?;
                 let mut arguments = Vec::new();
-                TranslationVisitor::flatten(first, &mut arguments);
-                TranslationVisitor::flatten(second, &mut arguments);
+                TranslationVisitor::flatten(first, TypeId::of::<OrFilter>(), &mut arguments);
+                TranslationVisitor::flatten(second, TypeId::of::<OrFilter>(), &mut arguments);
                 self.filter_exprs.push(Box::new(OrFilter::new(arguments)));
                 /*
                 todo it would be nice if code like this compiled
@@ -91,108 +548,410 @@ impl<'a> ParseTreeVisitor<'a> for TranslationVisitor {
                                                 )));
                                                 true
                 */
-                true
             }
             LexItem::InfixFunction(InfixFn::And) => {
-                let second: Box<dyn Filter> = self.filter_exprs.pop().unwrap();
-                let first: Box<dyn Filter> = self.filter_exprs.pop().unwrap();
+                let second = self.condition(&node.children[1])?;
+                let first = self.condition(&node.children[0])?;
                 let mut arguments = Vec::new();
-                TranslationVisitor::flatten(first, &mut arguments);
-                TranslationVisitor::flatten(second, &mut arguments);
+                TranslationVisitor::flatten(first, TypeId::of::<AndFilter>(), &mut arguments);
+                TranslationVisitor::flatten(second, TypeId::of::<AndFilter>(), &mut arguments);
                 self.filter_exprs.push(Box::new(AndFilter::new(arguments)));
-                true
             }
             LexItem::InfixFunction(InfixFn::MatchOp) => {
-                let children: Vec<LexItem> =
-                    node.children.iter().map(|x| x.entry.clone()).collect();
-                assert!(children.len() == 2);
-
-                if let Some(field_expr) = self.field_exprs.pop() {
-                    if let LexItem::RegexStr(regexstr) = children[1] {
-                        self.filter_exprs
-                            .push(Box::new(RegexFilter::new(field_expr, regexstr)));
-                    } else {
-                        unreachable!();
-                    }
-                } else {
-                    unreachable!();
-                }
-                true
+                // the parser only puts a field ref and a regex here
+                let regex = &node.children[1];
+                let (field_expr, regexstr) = match (self.field_exprs.pop(), &regex.entry) {
+                    (Some(field_expr), LexItem::RegexStr(regexstr)) => (field_expr, regexstr),
+                    _ => unreachable!(),
+                };
+                let filter = RegexFilter::new(field_expr, regexstr)
+                    .map_err(|e| QueryError::invalid(&regex.context, regex_error(e)))?;
+                self.filter_exprs.push(Box::new(filter));
             }
             LexItem::InfixFunction(InfixFn::EqOp) => {
-                assert!(self.filter_exprs.len() + self.field_exprs.len() == 2);
-                let rhs = get_input(&mut self.filter_exprs, &mut self.field_exprs).unwrap();
-                let lhs = get_input(&mut self.filter_exprs, &mut self.field_exprs).unwrap();
+                let (lhs, rhs) = self.operands(node)?;
                 self.filter_exprs.push(Box::new(EqFilter::new(lhs, rhs)));
-
-                true
+            }
+            LexItem::InfixFunction(InfixFn::NotEqOp) => {
+                let (lhs, rhs) = self.operands(node)?;
+                self.filter_exprs
+                    .push(Box::new(NotFilter::new(Box::new(EqFilter::new(lhs, rhs)))));
+            }
+            LexItem::InfixFunction(
+                ref op @ (InfixFn::LtOp | InfixFn::LeOp | InfixFn::GtOp | InfixFn::GeOp),
+            ) => {
+                let op = match op {
+                    InfixFn::LtOp => CompareOp::Lt,
+                    InfixFn::LeOp => CompareOp::Le,
+                    InfixFn::GtOp => CompareOp::Gt,
+                    _ => CompareOp::Ge,
+                };
+                let (lhs, rhs) = self.operands(node)?;
+                self.filter_exprs
+                    .push(Box::new(CompareFilter::new(lhs, op, rhs)));
+            }
+            LexItem::InfixFunction(InfixFn::In) => {
+                let field_expr = self.field_exprs.pop().unwrap();
+                let filter = if node.children[1].entry == LexItem::KW(Keyword::Select) {
+                    let values = self.in_subquery(&node.children[1])?;
+                    InFilter::with_values(field_expr, values)
+                } else {
+                    InFilter::new(field_expr, in_literals(&node.children[1..])?)
+                };
+                self.filter_exprs.push(Box::new(filter));
+            }
+            // read by the parent in
+            LexItem::Identifier("file") => {}
+            LexItem::Identifier("exists") => {
+                let result = self.exists_subquery(&node.children[0])?;
+                self.filter_exprs.push(Box::new(ExistsFilter::new(result)));
+            }
+            LexItem::Identifier(name @ ("not" | "not_null" | "is_null"))
+                if node.children.len() != 1 =>
+            {
+                return Err(QueryError::invalid(
+                    &node.context,
+                    format!("{} takes exactly one argument", name),
+                ));
             }
             LexItem::Identifier("not") => {
-                let argument = self.filter_exprs.pop().unwrap();
+                let argument = self.condition(&node.children[0])?;
                 self.filter_exprs.push(Box::new(NotFilter::new(argument)));
-                true
             }
             LexItem::Identifier("not_null") => {
-                let argument = get_input(&mut self.filter_exprs, &mut self.field_exprs).unwrap();
+                let argument = self.get_input(&node.children[0])?;
                 self.filter_exprs
                     .push(Box::new(NotNullFilter::new(argument)));
-                true
             }
             LexItem::Identifier("is_null") => {
-                let argument = get_input(&mut self.filter_exprs, &mut self.field_exprs).unwrap();
+                let argument = self.get_input(&node.children[0])?;
                 self.filter_exprs
                     .push(Box::new(IsNullFilter::new(argument)));
-                true
             }
-            LexItem::Identifier(table_name) => {
-                self.table_name = table_name.to_string();
-                true
+            LexItem::Identifier(name) if AggregateFn::from_name(name, false).is_some() => {
+                return Err(QueryError::invalid(
+                    &node.context,
+                    format!("aggregate {} is not allowed in where", name),
+                ));
+            }
+            LexItem::Identifier(name) => {
+                return Err(QueryError::invalid(
+                    &node.context,
+                    format!("unknown function {} in where", name),
+                ));
             }
             LexItem::Punctuation(Punctuation::Paren) => {
                 unreachable!();
             }
-            LexItem::RegexStr(_) => true,
-            LexItem::FieldRef(record_type, field_type, subfield_type) => {
-                self.field_exprs.push(Box::new(FieldRefExpr::new(
-                    record_type,
-                    field_type,
-                    subfield_type,
-                )));
+            LexItem::RegexStr(_) => {}
+            // translated by the parent
+            LexItem::StringLit(_) | LexItem::NumberLit(_) => {}
+            // handled by the parent field ref
+            LexItem::Slice(..) => {}
+            LexItem::FieldRef(..) => {
+                let expr = self.scope.field_ref(node)?;
+                self.field_exprs.push(Box::new(expr));
+            }
+        }
+        Ok(())
+    }
+}
 
-                true
+// the literals of an in list, a file has one value per line
+fn in_literals(nodes: &[ParseNode]) -> Result<Vec<Literal>, QueryError> {
+    let mut literals = Vec::new();
+    for node in nodes {
+        match node.entry {
+            LexItem::StringLit(s) => literals.push(Literal::Str(unescape_str(s).into_bytes())),
+            LexItem::NumberLit(n) => {
+                literals.push(Literal::Number(n.parse::<f64>().map_err(|_| {
+                    QueryError::invalid(&node.context, format!("invalid number {}", n))
+                })?))
             }
+            LexItem::Identifier("file") => {
+                let path = match node.children[0].entry {
+                    LexItem::StringLit(path) => unescape_str(path),
+                    _ => unreachable!(),
+                };
+                let data = std::fs::read(&path).map_err(|x| {
+                    QueryError::invalid(&node.children[0].context, format!("{}: {}", path, x))
+                })?;
+                for line in data.split(|x| *x == b'\n') {
+                    let line = line.strip_suffix(b"\r").unwrap_or(line);
+                    if !line.is_empty() {
+                        literals.push(Literal::Str(line.to_vec()));
+                    }
+                }
+            }
+            _ => unreachable!(),
         }
     }
+    Ok(literals)
 }
 
-fn get_input(
-    filters: &mut Vec<Box<dyn Filter>>,
-    field_exprs: &mut Vec<Box<dyn FieldExpression>>,
-) -> Option<FilterInput> {
-    if let Some(f) = filters.pop() {
-        Some(FilterInput::filter(f))
-    } else if let Some(f) = field_exprs.pop() {
-        Some(FilterInput::field_ref(f))
+// the number of a limit or offset node
+fn count(node: &ParseNode) -> Result<usize, QueryError> {
+    let number = &node.children[0];
+    match number.entry {
+        LexItem::NumberLit(n) => n.parse::<usize>().map_err(|e| {
+            QueryError::invalid(&number.context, format!("invalid number {}: {}", n, e))
+        }),
+        _ => unreachable!(),
+    }
+}
+
+// the reason of a regex error is its last line, the others repeat the regex
+fn regex_error(e: regex::Error) -> String {
+    let text = e.to_string();
+    let reason = text.lines().last().unwrap_or_default();
+    format!("invalid regex, {}", reason.trim_start_matches("error: "))
+}
+
+// the nodes of having that are the same tokens as a column of the group
+// record, a number is never the same as a field ref
+fn find_group_columns(node: &ParseNode, columns: &[&ParseNode], found: &mut Vec<(usize, usize)>) {
+    if let Some(i) = columns.iter().position(|c| c.same_as(node)) {
+        found.push((node.context.0, i));
+        return;
+    }
+    // subqueries don't see the group record
+    if node.entry == LexItem::KW(Keyword::Select) {
+        return;
+    }
+    for c in &node.children {
+        find_group_columns(c, columns, found);
+    }
+}
+
+// the aggregates of having that are not selected are added to aggregates,
+// other field refs must be group keys
+fn having_aggregates<'n>(
+    node: &ParseNode<'n>,
+    keys: &[&ParseNode<'n>],
+    aggregates: &mut Vec<ParseNode<'n>>,
+) -> Result<(), QueryError> {
+    if keys.iter().any(|k| k.same_as(node)) {
+        return Ok(());
+    }
+    match node.entry {
+        // subqueries don't see the group record
+        LexItem::KW(Keyword::Select) => Ok(()),
+        LexItem::Identifier(name) if AggregateFn::from_name(name, false).is_some() => {
+            if !aggregates.iter().any(|x| x.same_as(node)) {
+                aggregates.push(node.clone());
+            }
+            Ok(())
+        }
+        LexItem::FieldRef(..) => Err(QueryError::invalid(
+            &node.context,
+            format!(
+                "{} in having must appear in the group by or in an aggregate",
+                node.text()
+            ),
+        )),
+        _ => {
+            for c in &node.children {
+                having_aggregates(c, keys, aggregates)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+// count(*), count(field), count(distinct field), min(field) or max(field)
+fn aggregate(node: &ParseNode, scope: &Scope) -> Result<Aggregate, QueryError> {
+    let name = match node.entry {
+        LexItem::Identifier(name) => name,
+        _ => unreachable!(),
+    };
+    let distinct = node.children[0].entry == LexItem::KW(Keyword::Distinct);
+    let arguments = if distinct {
+        &node.children[0].children
     } else {
-        None
+        &node.children
+    };
+    let invalid = |node: &ParseNode, message: String| QueryError::invalid(&node.context, message);
+    let func = AggregateFn::from_name(name, distinct)
+        .ok_or_else(|| invalid(node, format!("unknown aggregate function {}", name)))?;
+    match arguments.as_slice() {
+        [argument] => match argument.entry {
+            LexItem::FieldRef(None, Some("*"), None) if argument.children.is_empty() => {
+                if func == AggregateFn::Count {
+                    Ok(Aggregate {
+                        func,
+                        expr: None,
+                        field_type: 0,
+                    })
+                } else if func == AggregateFn::CountDistinct {
+                    Err(invalid(
+                        argument,
+                        "count(distinct *) is not supported, use count(distinct <field>)"
+                            .to_string(),
+                    ))
+                } else {
+                    Err(invalid(
+                        argument,
+                        format!("{}(*) is not supported, only count(*)", name),
+                    ))
+                }
+            }
+            LexItem::FieldRef(_, field_type, _) => Ok(Aggregate {
+                func,
+                expr: Some(Box::new(scope.field_ref(argument)?)),
+                field_type: field_type.and_then(|x| x.parse().ok()).unwrap_or(0),
+            }),
+            _ => Err(invalid(
+                argument,
+                format!("the argument of {} must be a field ref", name),
+            )),
+        },
+        _ => Err(invalid(
+            node,
+            format!("{} takes exactly one argument", name),
+        )),
     }
 }
 
+fn is_aggregate(node: &ParseNode) -> bool {
+    match node.entry {
+        LexItem::Identifier(name) => AggregateFn::from_name(name, false).is_some(),
+        _ => false,
+    }
+}
+
+// a column of the select list, field refs, literals and scalar function calls
+fn scalar_expr(node: &ParseNode, scope: &Scope) -> Result<Box<dyn ScalarExpression>, QueryError> {
+    let invalid = |message: String| QueryError::invalid(&node.context, message);
+    match node.entry {
+        LexItem::FieldRef(..) => Ok(Box::new(FieldValues::new(Box::new(scope.field_ref(node)?)))),
+        LexItem::StringLit(s) => Ok(Box::new(ConstantValue::new(unescape_str(s).into_bytes()))),
+        LexItem::NumberLit(n) => Ok(Box::new(ConstantValue::new(
+            number_literal(n).map_err(invalid)?,
+        ))),
+        LexItem::Identifier(name) => {
+            if is_aggregate(node) {
+                return Err(invalid(format!(
+                    "aggregate {} can't be an argument of a function",
+                    name
+                )));
+            }
+            let func = ScalarFn::from_name(name)
+                .ok_or_else(|| invalid(format!("unknown function {}", name)))?;
+            if node
+                .children
+                .iter()
+                .any(|x| x.entry == LexItem::KW(Keyword::Distinct))
+            {
+                return Err(invalid(format!("{} doesn't take distinct", name)));
+            }
+            let (min, max) = func.arity();
+            let n = node.children.len();
+            if n < min || max.is_some_and(|max| n > max) {
+                return Err(invalid(format!("wrong number of arguments for {}", name)));
+            }
+            let mut arguments: Vec<Box<dyn ScalarExpression>> = Vec::new();
+            for argument in &node.children {
+                arguments.push(scalar_expr(argument, scope)?);
+            }
+            let call = FunctionCall::new(func, arguments);
+            if func != ScalarFn::RegexReplace {
+                return Ok(Box::new(call));
+            }
+            let pattern = &node.children[1];
+            match pattern.entry {
+                LexItem::StringLit(s) => {
+                    let regex = regex::bytes::Regex::new(&unescape_str(s))
+                        .map_err(|x| QueryError::invalid(&pattern.context, regex_error(x)))?;
+                    Ok(Box::new(call.with_regex(regex)))
+                }
+                _ => Err(QueryError::invalid(
+                    &pattern.context,
+                    format!("the pattern of {} must be a string", name),
+                )),
+            }
+        }
+        _ => Err(invalid(format!("{} can't be selected", node.entry))),
+    }
+}
+
+// numbers are written without sign and trailing zeros
+fn number_literal(n: &str) -> Result<Vec<u8>, String> {
+    n.parse::<f64>()
+        .map(|x| Literal::Number(x).to_bytes().into_owned())
+        .map_err(|_| format!("invalid number {}", n))
+}
+
 pub struct CompilationResult {
     pub projection: Projection,
     pub filter_expr: Option<Box<dyn Filter>>,
-    pub table_name: String,
+    pub order_by: Vec<SortKey>,
+    // grouped queries output the rows of the aggregation instead of the projection
+    pub aggregation: Option<HashAggregation>,
+    pub distinct: Option<HashDistinct>,
+    // the alias of every column of the select list, or its text
+    pub columns: Vec<String>,
+    // the columns that are counts, the json output writes them as numbers
+    pub count_columns: Vec<usize>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+    // read one after the other, union all
+    pub tables: Vec<TableRef>,
+    // joined with the records of the tables
+    pub join: Option<Join>,
+    // run before the query, their results are used by the filters
+    pub subqueries: Vec<Subquery>,
+    // the parse tree of explain select ..., the query is described instead of run
+    pub explain: Option<String>,
+    // explain analyze runs the query and reports what it took
+    pub analyze: bool,
+    // counts the records read, set for explain analyze
+    pub reader_stats: Option<Rc<ReaderStats>>,
 }
 
-pub fn compile(input: &str) -> Result<CompilationResult, String> {
+// a subquery doesn't refer to the query it is part of, so it runs only once
+pub struct Subquery {
+    pub query: CompilationResult,
+    pub result: SubqueryResult,
+}
+
+pub enum SubqueryResult {
+    // the values of the column for in
+    Values(Rc<OnceCell<ValueSet>>),
+    // whether there is a row for exists
+    Exists(Rc<OnceCell<bool>>),
+}
+
+pub fn compile(input: &str) -> Result<CompilationResult, QueryError> {
     let parsetree = parse(input)?;
+    if parsetree.entry == LexItem::KW(Keyword::Explain) {
+        let select = parsetree.children.last().unwrap();
+        let mut result = compile_select(select)?;
+        result.explain = Some(select.tree());
+        result.analyze = parsetree.children.len() == 2;
+        return Ok(result);
+    }
+    compile_select(&parsetree)
+}
+
+fn compile_select(node: &ParseNode) -> Result<CompilationResult, QueryError> {
     let mut visitor = TranslationVisitor::new();
-    parsetree.visit(&mut visitor);
-    dbg!(visitor.filter_exprs.len());
+    visitor.translate_select(node)?;
     assert!(visitor.filter_exprs.len() <= 1);
     Ok(CompilationResult {
         projection: Projection::new(visitor.projection_exprs),
         filter_expr: visitor.filter_exprs.pop(),
-        table_name: visitor.table_name,
+        order_by: visitor.sort_keys,
+        aggregation: visitor.aggregation,
+        distinct: visitor.distinct,
+        columns: visitor.column_names,
+        count_columns: visitor.count_columns,
+        limit: visitor.limit,
+        offset: visitor.offset,
+        tables: visitor.tables,
+        join: visitor.join,
+        subqueries: visitor.subqueries,
+        explain: None,
+        analyze: false,
+        reader_stats: None,
     })
 }
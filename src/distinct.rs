@@ -0,0 +1,294 @@
+// SELECT DISTINCT, rows are compared on the bytes of what they are distinct
+// on, a 128 bit digest of them finds the candidates.
+// Rows are passed on as soon as they are first seen until the digests exceed
+// the memory budget. After that new rows go to hash partitions on disk and
+// every partition is de-duplicated on its own at the end, so the output is
+// no longer in input order.
+use crate::field_expression::FieldExpression;
+use crate::ownedrecord::OwnedRecord;
+use crate::record::Record;
+use crate::sort::KeyValues;
+use crate::spill::{SpillItem, SpillWriter};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+pub type Digest = u128;
+
+const PARTITIONS: usize = 16;
+// a digest and its share of the hash map, the row bytes come on top
+const DIGEST_SIZE: usize = 48;
+
+// the bytes a row is compared on and their digest
+pub struct RowKey {
+    digest: Digest,
+    bytes: Vec<u8>,
+}
+
+impl RowKey {
+    fn new(bytes: Vec<u8>) -> RowKey {
+        RowKey {
+            digest: digest_of(&bytes),
+            bytes,
+        }
+    }
+}
+
+// the rows seen so far by digest, more than one if digests collide
+type Seen = HashMap<Digest, Vec<Vec<u8>>>;
+
+fn seen_before(seen: &Seen, key: &RowKey) -> bool {
+    seen.get(&key.digest)
+        .is_some_and(|rows| rows.contains(&key.bytes))
+}
+
+// values are prefixed with their length so they can't run into each other
+fn push_len(bytes: &mut Vec<u8>, len: usize) {
+    bytes.extend_from_slice(&(len as u64).to_le_bytes());
+}
+
+fn push_bytes(bytes: &mut Vec<u8>, data: &[u8]) {
+    push_len(bytes, data.len());
+    bytes.extend_from_slice(data);
+}
+
+pub enum DistinctOn {
+    // the output rows, for value projections
+    Row,
+    // the whole source record including the leader, for select distinct *
+    Record,
+    // the values of some fields of the source record, e.g. distinct on (001)
+    Fields(Vec<Box<dyn FieldExpression>>),
+}
+
+// the downstream operator, gets the sort keys along with the row and
+// returns false once it doesn't want more rows
+pub type Sink<'a> = dyn FnMut(KeyValues, &dyn Record) -> std::io::Result<bool> + 'a;
+
+pub struct HashDistinct {
+    on: DistinctOn,
+    memory_budget: usize,
+    seen: Seen,
+    // the bytes in seen
+    seen_size: usize,
+    // created when seen exceeds the memory budget
+    partitions: Vec<SpillWriter>,
+}
+
+fn digest_of<T: Hash>(value: &T) -> Digest {
+    // two differently seeded hashes, so collisions are rare and only cost a
+    // comparison of the bytes
+    let mut low = DefaultHasher::new();
+    0_u8.hash(&mut low);
+    value.hash(&mut low);
+    let mut high = DefaultHasher::new();
+    1_u8.hash(&mut high);
+    value.hash(&mut high);
+    ((high.finish() as u128) << 64) | low.finish() as u128
+}
+
+impl HashDistinct {
+    pub fn new(on: DistinctOn, memory_budget: usize) -> HashDistinct {
+        HashDistinct {
+            on,
+            memory_budget,
+            seen: HashMap::new(),
+            seen_size: 0,
+            partitions: Vec::new(),
+        }
+    }
+
+    // what rows are compared on, for explain
+    pub fn describe(&self) -> String {
+        match &self.on {
+            DistinctOn::Row => "the selected columns".to_string(),
+            DistinctOn::Record => "whole records".to_string(),
+            DistinctOn::Fields(fields) => {
+                let fields: Vec<String> = fields.iter().map(|x| x.describe()).collect();
+                format!("on {}", fields.join(", "))
+            }
+        }
+    }
+
+    // Some if the key has to be computed on the record before projection
+    pub fn source_key(&self, record: &dyn Record) -> Option<RowKey> {
+        match &self.on {
+            DistinctOn::Row => None,
+            DistinctOn::Record => Some(HashDistinct::record_key(record)),
+            DistinctOn::Fields(exprs) => {
+                let mut bytes = Vec::new();
+                for expr in exprs {
+                    let values: Vec<&[u8]> = expr.compute(record).map(|f| f.data).collect();
+                    push_len(&mut bytes, values.len());
+                    for value in values {
+                        push_bytes(&mut bytes, value);
+                    }
+                }
+                Some(RowKey::new(bytes))
+            }
+        }
+    }
+
+    // the leader and all fields in order, and their columns for rows
+    pub fn record_key(record: &dyn Record) -> RowKey {
+        let mut bytes = Vec::new();
+        push_bytes(&mut bytes, record.header());
+        for field in record.field_iter(None) {
+            push_len(&mut bytes, field.field_type);
+            push_bytes(&mut bytes, field.data);
+        }
+        if let Some(columns) = record.columns() {
+            push_len(&mut bytes, columns.len());
+            for column in columns {
+                push_len(&mut bytes, *column);
+            }
+        }
+        RowKey::new(bytes)
+    }
+
+    pub fn spilled(&self) -> bool {
+        !self.partitions.is_empty()
+    }
+
+    // hands the row to sink unless a row with the same key was seen before
+    pub fn insert(
+        &mut self,
+        key: RowKey,
+        sort_keys: KeyValues,
+        record: &dyn Record,
+        sink: &mut Sink,
+    ) -> std::io::Result<bool> {
+        if seen_before(&self.seen, &key) {
+            return Ok(true);
+        }
+        if self.spilled() {
+            let partition = &mut self.partitions[(key.digest % PARTITIONS as u128) as usize];
+            partition.write_raw(&key.digest.to_le_bytes())?;
+            partition.write_raw(&(key.bytes.len() as u64).to_le_bytes())?;
+            partition.write_raw(&key.bytes)?;
+            partition.write_item(&SpillItem {
+                keys: sort_keys,
+                record: OwnedRecord::from_record(record),
+            })?;
+            return Ok(true);
+        }
+        self.seen_size += DIGEST_SIZE + key.bytes.len();
+        self.seen.entry(key.digest).or_default().push(key.bytes);
+        if self.seen_size > self.memory_budget {
+            for _ in 0..PARTITIONS {
+                self.partitions.push(SpillWriter::create()?);
+            }
+        }
+        sink(sort_keys, record)
+    }
+
+    // passes on the rows that were spilled
+    pub fn finish(self, sink: &mut Sink) -> std::io::Result<()> {
+        for partition in self.partitions {
+            let mut reader = partition.finish()?;
+            let mut seen = Seen::new();
+            let mut digest = [0_u8; 16];
+            let mut len = [0_u8; 8];
+            while !reader.is_empty() {
+                reader.read_raw(&mut digest)?;
+                reader.read_raw(&mut len)?;
+                let mut bytes = vec![0; u64::from_le_bytes(len) as usize];
+                reader.read_raw(&mut bytes)?;
+                let key = RowKey {
+                    digest: u128::from_le_bytes(digest),
+                    bytes,
+                };
+                if let Some(item) = reader.next() {
+                    let item = item?;
+                    if seen_before(&seen, &key) {
+                        continue;
+                    }
+                    seen.entry(key.digest).or_default().push(key.bytes);
+                    if !sink(item.keys, &item.record)? {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::distinct::*;
+    use crate::field_expression::*;
+    use crate::ownedrecord::test_record;
+
+    fn distinct(on: DistinctOn, memory_budget: usize) -> (Vec<String>, bool) {
+        let data = [
+            ("1", "a"),
+            ("2", "b"),
+            ("1", "a"),
+            ("3", "a"),
+            ("2", "c"),
+            ("4", "d"),
+            ("4", "d"),
+        ];
+        let mut distinct = HashDistinct::new(on, memory_budget);
+        let mut rows = Vec::new();
+        let mut sink = |_: KeyValues, r: &dyn Record| -> std::io::Result<bool> {
+            let values: Vec<String> = r
+                .field_iter(None)
+                .map(|f| f.utf8_data().to_string())
+                .collect();
+            rows.push(values.join(" "));
+            Ok(true)
+        };
+        for (id, value) in data.iter() {
+            let r = test_record(&[(1, id), (150, value)]);
+            let key = distinct
+                .source_key(&r)
+                .unwrap_or_else(|| HashDistinct::record_key(&r));
+            distinct.insert(key, Vec::new(), &r, &mut sink).unwrap();
+        }
+        let spilled = distinct.spilled();
+        distinct.finish(&mut sink).unwrap();
+        rows.sort();
+        (rows, spilled)
+    }
+
+    #[test]
+    fn test_distinct() {
+        let (rows, spilled) = distinct(DistinctOn::Record, 1024);
+        assert!(!spilled);
+        assert_eq!(rows, vec!["1 a", "2 b", "2 c", "3 a", "4 d"]);
+        let id = Box::new(FieldRefExpr::new(None, Some("001"), None));
+        let (rows, _) = distinct(DistinctOn::Fields(vec![id]), 1024);
+        assert_eq!(rows, vec!["1 a", "2 b", "3 a", "4 d"]);
+    }
+
+    #[test]
+    fn test_distinct_spilled() {
+        // spills after the second row
+        let (rows, spilled) = distinct(DistinctOn::Record, 200);
+        assert!(spilled);
+        assert_eq!(rows, vec!["1 a", "2 b", "2 c", "3 a", "4 d"]);
+    }
+
+    // rows with the same digest are told apart by their bytes
+    #[test]
+    fn test_digest_collision() {
+        for memory_budget in [1024, 0] {
+            let mut distinct = HashDistinct::new(DistinctOn::Record, memory_budget);
+            let mut rows = Vec::new();
+            let mut sink = |_: KeyValues, r: &dyn Record| -> std::io::Result<bool> {
+                rows.push(r.field_iter(None).next().unwrap().utf8_data().to_string());
+                Ok(true)
+            };
+            for id in ["1", "2", "1", "2"].iter() {
+                let r = test_record(&[(1, id), (150, "a")]);
+                let mut key = HashDistinct::record_key(&r);
+                key.digest = 42;
+                distinct.insert(key, Vec::new(), &r, &mut sink).unwrap();
+            }
+            distinct.finish(&mut sink).unwrap();
+            assert_eq!(rows, vec!["1", "2"]);
+        }
+    }
+}
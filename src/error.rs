@@ -0,0 +1,140 @@
+// The errors of a query point at the token they are about. Rendered with the
+// query they show the line of the token and a caret under it:
+//
+// expected from, found where at line 1, column 12:
+// select 001 where 001 = 'x'
+//            ^
+use crate::lexer::{ItemContext, LexItem};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryErrorKind {
+    // the grammar wants something else here, nothing is found at the end of
+    // the query
+    Expected {
+        expected: String,
+        found: Option<String>,
+    },
+    // the token is where it belongs but makes no sense
+    Invalid(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+    // the start of the token, None for the end of the query
+    pub context: Option<ItemContext>,
+    pub kind: QueryErrorKind,
+}
+
+impl QueryError {
+    // the token of the query the parser is looking at, if there is one left
+    pub fn expected(expected: &str, token: Option<&(ItemContext, LexItem)>) -> QueryError {
+        QueryError {
+            context: token.map(|(context, _)| context.clone()),
+            kind: QueryErrorKind::Expected {
+                expected: expected.to_string(),
+                found: token.map(|(_, item)| item.to_string()),
+            },
+        }
+    }
+
+    pub fn invalid(context: &ItemContext, message: String) -> QueryError {
+        QueryError {
+            context: Some(context.clone()),
+            kind: QueryErrorKind::Invalid(message),
+        }
+    }
+
+    // what is wrong, without the position
+    pub fn message(&self) -> String {
+        match &self.kind {
+            QueryErrorKind::Expected {
+                expected,
+                found: Some(found),
+            } => format!("expected {}, found {}", expected, found),
+            QueryErrorKind::Expected {
+                expected,
+                found: None,
+            } => format!("expected {}, found the end of the query", expected),
+            QueryErrorKind::Invalid(message) => message.clone(),
+        }
+    }
+
+    // the message, the line of the query and a caret under the token
+    pub fn render(&self, query: &str) -> String {
+        let position = self
+            .context
+            .as_ref()
+            .map_or(query.len(), |x| x.0)
+            .min(query.len());
+        let start = query[..position].rfind('\n').map_or(0, |x| x + 1);
+        let end = query[position..]
+            .find('\n')
+            .map_or(query.len(), |x| position + x);
+        let line_number = query[..start].matches('\n').count() + 1;
+        // tabs stay tabs so the caret lines up
+        let indent: String = query[start..position]
+            .chars()
+            .map(|x| if x == '\t' { '\t' } else { ' ' })
+            .collect();
+        format!(
+            "{} at line {}, column {}:\n{}\n{}^",
+            self.message(),
+            line_number,
+            indent.chars().count() + 1,
+            &query[start..end],
+            indent
+        )
+    }
+}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.context {
+            Some(context) => write!(f, "{} at {}", self.message(), context.0),
+            None => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl From<QueryError> for String {
+    fn from(e: QueryError) -> String {
+        e.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::*;
+
+    #[test]
+    fn test_render() {
+        let query = "select 001\nfrom titles\n\twhere 245 ~ 'x' or";
+        let token = (
+            ItemContext(40),
+            LexItem::InfixFunction(crate::lexer::InfixFn::Or),
+        );
+        let e = QueryError::expected("a condition", None);
+        assert_eq!(
+            e.render(query),
+            "expected a condition, found the end of the query at line 3, column 20:\n\
+             \twhere 245 ~ 'x' or\n\
+             \t                  ^"
+        );
+        let e = QueryError::expected("from", Some(&token));
+        assert_eq!(e.to_string(), "expected from, found or at 40");
+        assert_eq!(
+            e.render(query),
+            "expected from, found or at line 3, column 18:\n\
+             \twhere 245 ~ 'x' or\n\
+             \t                ^"
+        );
+        let e = QueryError::invalid(
+            &ItemContext(7),
+            "unknown table alias or record type t".to_string(),
+        );
+        assert_eq!(
+            e.render(query),
+            "unknown table alias or record type t at line 1, column 8:\nselect 001\n       ^"
+        );
+    }
+}
@@ -0,0 +1,268 @@
+// explain select ... describes what a query turned into instead of running
+// it: the parse tree, the files of the tables, the filter tree after nested
+// ands and ors are merged, and the columns that are output. explain analyze
+// runs the query and adds what every filter and the reader did.
+use crate::compiler::{CompilationResult, SubqueryResult};
+use crate::filter::Filter;
+use crate::join;
+use crate::marcrecord::MarcReader;
+use crate::profile;
+use crate::table::{TablePart, TableRef};
+use crate::Record;
+use std::fmt::Display;
+use std::io::Read;
+use std::rc::Rc;
+use std::time::Instant;
+
+// the counters are only read when the plan is written out, after the query ran
+struct Line {
+    depth: usize,
+    text: String,
+    stats: Option<Rc<dyn Display>>,
+}
+
+fn line(out: &mut Vec<Line>, depth: usize, text: &str) {
+    out.push(Line {
+        depth,
+        text: text.to_string(),
+        stats: None,
+    });
+}
+
+fn render(lines: &[Line]) -> String {
+    let mut out = String::new();
+    for line in lines {
+        out.push_str(&"  ".repeat(line.depth));
+        out.push_str(&line.text);
+        if let Some(stats) = &line.stats {
+            out.push_str(&format!(" ({})", stats));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// the files a table is read from
+fn resolve(table: &TableRef) -> String {
+    if table.is_stdin() {
+        return "standard input".to_string();
+    }
+    match table.paths() {
+        Ok(paths) => paths
+            .iter()
+            .map(|x| {
+                if x.exists() {
+                    x.display().to_string()
+                } else {
+                    format!("{} (missing)", x.display())
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(", "),
+        Err(e) => format!("error: {}", e),
+    }
+}
+
+fn write_filter(filter: &dyn Filter, depth: usize, out: &mut Vec<Line>) {
+    out.push(Line {
+        depth,
+        text: filter.describe(),
+        stats: filter.stats().map(|x| -> Rc<dyn Display> { x }),
+    });
+    for input in filter.inputs() {
+        write_filter(input, depth + 1, out);
+    }
+}
+
+fn write_plan(query: &CompilationResult, depth: usize, out: &mut Vec<Line>) {
+    if let Some(tree) = &query.explain {
+        line(out, depth, "parse tree:");
+        for node in tree.lines() {
+            line(out, depth + 1, node);
+        }
+    }
+    line(out, depth, "tables:");
+    for table in &query.tables {
+        line(out, depth + 1, &format!("{}: {}", table, resolve(table)));
+    }
+    if let Some(join) = &query.join {
+        line(out, depth, "join:");
+        line(
+            out,
+            depth + 1,
+            &format!("{}: {}", join.table, resolve(&join.table)),
+        );
+        line(
+            out,
+            depth + 1,
+            &format!(
+                "on left:{} = right:{}",
+                join.left_key.describe(),
+                join.right_key.describe()
+            ),
+        );
+        let built = if join::build_left(&query.tables, &join.table) {
+            "the tables of the from clause are loaded into memory"
+        } else {
+            "the joined table is loaded into memory"
+        };
+        line(out, depth + 1, built);
+    }
+    if let Some(stats) = &query.reader_stats {
+        out.push(Line {
+            depth,
+            text: "reader".to_string(),
+            stats: Some(stats.clone()),
+        });
+    }
+    if let Some(filter) = &query.filter_expr {
+        line(out, depth, "filter:");
+        write_filter(&**filter, depth + 1, out);
+    }
+    let names = query.columns.iter();
+    match &query.aggregation {
+        Some(aggregation) => {
+            let (keys, columns) = aggregation.describe();
+            line(out, depth, &format!("group by: {}", keys.join(", ")));
+            line(out, depth, "columns:");
+            for (name, column) in names.zip(columns) {
+                line(out, depth + 1, &format!("{}: {}", name, column));
+            }
+            if let Some(having) = aggregation.having() {
+                line(out, depth, "having:");
+                write_filter(having, depth + 1, out);
+            }
+        }
+        None => {
+            line(out, depth, "columns:");
+            for (name, column) in names.zip(query.projection.describe()) {
+                line(out, depth + 1, &format!("{}: {}", name, column));
+            }
+        }
+    }
+    if let Some(distinct) = &query.distinct {
+        line(out, depth, &format!("distinct: {}", distinct.describe()));
+    }
+    if !query.order_by.is_empty() {
+        let keys: Vec<String> = query
+            .order_by
+            .iter()
+            .map(|x| {
+                let direction = if x.descending { " desc" } else { "" };
+                format!("{}{}", x.expr.describe(), direction)
+            })
+            .collect();
+        line(out, depth, &format!("order by: {}", keys.join(", ")));
+    }
+    if let Some(limit) = query.limit {
+        line(
+            out,
+            depth,
+            &format!("limit: {} offset {}", limit, query.offset),
+        );
+    } else if query.offset > 0 {
+        line(out, depth, &format!("offset: {}", query.offset));
+    }
+    for (i, subquery) in query.subqueries.iter().enumerate() {
+        let kind = match subquery.result {
+            SubqueryResult::Values(_) => "in",
+            SubqueryResult::Exists(_) => "exists",
+        };
+        line(out, depth, &format!("subquery {} for {}:", i + 1, kind));
+        write_plan(&subquery.query, depth + 1, out);
+    }
+}
+
+pub fn plan(query: &CompilationResult) -> String {
+    let mut lines = Vec::new();
+    write_plan(query, 0, &mut lines);
+    render(&lines)
+}
+
+// wraps the filters and counts the records read, subqueries included
+fn instrument(query: &mut CompilationResult) {
+    query.filter_expr = query.filter_expr.take().map(profile::profile);
+    if let Some(aggregation) = query.aggregation.as_mut() {
+        let having = aggregation.having_mut();
+        *having = having.take().map(profile::profile);
+    }
+    query.reader_stats = Some(Rc::default());
+    for subquery in query.subqueries.iter_mut() {
+        instrument(&mut subquery.query);
+    }
+}
+
+// runs the query without output and returns its plan with the counters
+pub fn analyze<T, M>(mut query: CompilationResult, make_reader: M) -> Result<String, String>
+where
+    T: Read,
+    M: Fn(&TablePart) -> Result<MarcReader<T>, std::io::Error>,
+{
+    instrument(&mut query);
+    let mut lines = Vec::new();
+    write_plan(&query, 0, &mut lines);
+    let start = Instant::now();
+    let mut rows = 0;
+    crate::run_query(query, make_reader, |_: &dyn Record| rows += 1)?;
+    let mut out = render(&lines);
+    out.push_str(&format!(
+        "{} rows in {:.3} ms\n",
+        rows,
+        start.elapsed().as_secs_f64() * 1000.0
+    ));
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::compiler::compile;
+    use crate::explain::*;
+
+    #[test]
+    fn test_plan() -> Result<(), String> {
+        let query = compile(
+            "explain select 001 as id, upper(245.a) from stdin \
+             where (001 = '1' or 001 = '2' or 245.a ~ 'x') and not(leader[6] = 'z') \
+             order by 245.a desc limit 10",
+        )?;
+        let text = plan(&query);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "parse tree:");
+        assert_eq!(lines[1], "  KW(Select) at 8");
+        let tables = lines.iter().position(|x| *x == "tables:").unwrap();
+        assert_eq!(
+            lines[tables..],
+            [
+                "tables:",
+                "  stdin: standard input",
+                "filter:",
+                "  AndFilter",
+                "    OrFilter",
+                "      EqFilter(001 = '1')",
+                "      EqFilter(001 = '2')",
+                "      RegexFilter(245.a ~ 'x')",
+                "    NotFilter",
+                "      EqFilter(leader[6:7] = 'z')",
+                "columns:",
+                "  id: 001",
+                "  upper(245.a): upper(245.a)",
+                "order by: 245.a desc",
+                "limit: 10 offset 0",
+            ]
+        );
+        // without explain there is no parse tree
+        let query = compile(
+            "select count(*) from titles t join gnd g on t.700.0 = g.035.a \
+             where t.001 in (select 001 from stdin) group by t.001",
+        )?;
+        let text = plan(&query);
+        assert!(text.starts_with("tables:\n  titles: titles.mrc (missing)\njoin:\n"));
+        assert!(text.contains("  on left:700.0 = right:035.a\n"));
+        assert!(text.contains("group by: left:001\ncolumns:\n  count(*): count(*)\n"));
+        assert!(text.contains(
+            "  the joined table is loaded into memory\nfilter:\n  InFilter(left:001, subquery)\n"
+        ));
+        assert!(text.contains("subquery 1 for in:\n  tables:\n    stdin: standard input\n"));
+        Ok(())
+    }
+}
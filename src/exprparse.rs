@@ -1,18 +1,26 @@
 /*
 EXPR -> OR or EXPR | OR
 OR -> TERM and OR  | TERM
-TERM -> NOT ~ 'regexstr' | NOT = TERM | NOT
-NOT -> IDENTIFIER ( LIST_OF_EXPR ) | field_ref | ( expr )
+TERM -> NOT ~ 'regexstr' | NOT CMP_OP NOT | FIELD_REF in IN_LIST | NOT
+IN_LIST -> ( LITERAL_LIST ) | file ( 'path' ) | ( STMT )
+LITERAL_LIST -> LITERAL | LITERAL , LITERAL_LIST
+CMP_OP -> = | != | < | <= | > | >=
+NOT -> exists ( STMT ) | IDENTIFIER ( LIST_OF_EXPR ) | IDENTIFIER ( distinct COLUMN_EXPR_LIST ) | FIELD_REF | LITERAL | ( expr )
+LITERAL -> 'string' | number
 LIST_OF_EXPR -> expr | expr , LIST_OF_EXPR
+FIELD_REF -> field_ref | field_ref [ slice ]
+COLUMN_EXPR -> FIELD_REF | LITERAL | IDENTIFIER ( COLUMN_EXPR_LIST ) | IDENTIFIER ( distinct COLUMN_EXPR_LIST )
+COLUMN_EXPR_LIST -> COLUMN_EXPR | COLUMN_EXPR , COLUMN_EXPR_LIST
 
 x and y or z -> (x and y) or z
 */
+#![allow(non_snake_case)]
 use crate::parser::*;
 
 pub fn parse_expr<'a>(
     input: &[(ItemContext, LexItem<'a>)],
     offset: usize,
-) -> Result<(ParseNode<'a>, usize), String> {
+) -> Result<(ParseNode<'a>, usize), QueryError> {
     let (lhs, next_offset) = parse_OR(input, offset)?;
     let c = input.get(next_offset);
     match c {
@@ -34,7 +42,7 @@ pub fn parse_expr<'a>(
 fn parse_expr_inner<'a>(
     input: &[(ItemContext, LexItem<'a>)],
     offset: usize,
-) -> Result<(ParseNode<'a>, usize), String> {
+) -> Result<(ParseNode<'a>, usize), QueryError> {
     let (lhs, next_offset) = parse_OR(input, offset)?;
     let c = input.get(next_offset);
     match c {
@@ -56,142 +64,312 @@ fn parse_expr_inner<'a>(
 fn parse_expr_list<'a>(
     input: &[(ItemContext, LexItem<'a>)],
     offset: usize,
-) -> Result<(Vec<ParseNode<'a>>, usize), String> {
+) -> Result<(Vec<ParseNode<'a>>, usize), QueryError> {
     let mut result = Vec::new();
     let mut cur_off = offset;
     loop {
-        dbg!(input.get(cur_off));
         let (exp, off) = parse_expr_inner(input, cur_off)?;
         result.push(exp);
         match input.get(off) {
             Some((_, LexItem::Punctuation(Punctuation::Comma))) => {
                 cur_off = off + 1;
             }
-            x => {
-                dbg!(&x);
+            _ => {
                 cur_off = off;
                 break;
             }
         }
     }
-    dbg!(&result, cur_off);
     Ok((result, cur_off))
 }
 
+// a select in parentheses
+fn parse_SUBQUERY<'a>(
+    input: &[(ItemContext, LexItem<'a>)],
+    offset: usize,
+) -> Result<(ParseNode<'a>, usize), QueryError> {
+    match input.get(offset) {
+        Some((_, LexItem::Punctuation(Punctuation::Paren))) => {}
+        x => return Err(QueryError::expected("'(' before a subquery", x)),
+    }
+    let (select, next_offset) = parse_SELECT(input, offset + 1)?;
+    match input.get(next_offset) {
+        Some((_, LexItem::Punctuation(Punctuation::Paren))) => Ok((select, next_offset + 1)),
+        x => Err(QueryError::expected("')' after a subquery", x)),
+    }
+}
+
+// the slice of a field ref becomes its child
+pub fn parse_FIELD_REF<'a>(
+    input: &[(ItemContext, LexItem<'a>)],
+    offset: usize,
+) -> Option<(ParseNode<'a>, usize)> {
+    if let Some((ctx, LexItem::FieldRef(record_type, field_type, subfield_type))) =
+        input.get(offset)
+    {
+        let mut node = ParseNode::new(
+            LexItem::FieldRef(*record_type, *field_type, *subfield_type),
+            ctx.clone(),
+        );
+        if let Some((ctx, LexItem::Slice(start, end))) = input.get(offset + 1) {
+            node.children
+                .push(ParseNode::new(LexItem::Slice(*start, *end), ctx.clone()));
+            return Some((node, offset + 2));
+        }
+        Some((node, offset + 1))
+    } else {
+        None
+    }
+}
+
 fn parse_NOT<'a>(
     input: &[(ItemContext, LexItem<'a>)],
     offset: usize,
-) -> Result<(ParseNode<'a>, usize), String> {
-    dbg!(input.get(offset));
+) -> Result<(ParseNode<'a>, usize), QueryError> {
     match input.get(offset) {
+        Some((_, LexItem::Identifier(_)))
+            if matches!(
+                input.get(offset + 2),
+                Some((_, LexItem::KW(Keyword::Distinct)))
+            ) =>
+        {
+            // count(distinct field) in having
+            parse_COLUMN_EXPR(input, offset)
+        }
+        // the subquery is the child of the exists node
+        Some((ctx, LexItem::Identifier("exists"))) => {
+            let (select, next_offset) = parse_SUBQUERY(input, offset + 1)?;
+            let mut exists_node = ParseNode::new(LexItem::Identifier("exists"), ctx.clone());
+            exists_node.children.push(select);
+            Ok((exists_node, next_offset))
+        }
         Some((ctx, LexItem::Identifier(n))) => {
-            dbg!(&n);
             if let Some((_, LexItem::Punctuation(Punctuation::Paren))) = input.get(offset + 1) {
                 let (children, next_offset) = parse_expr_list(input, offset + 2)?;
                 let mut identifier_expr = ParseNode::new(LexItem::Identifier(n), ctx.clone());
                 identifier_expr.children = children;
-                if let Some((_, LexItem::Punctuation(Punctuation::Paren))) = input.get(next_offset)
-                {
-                    return Ok((identifier_expr, next_offset + 1));
-                } else {
-                    return Err(format!(
-                        "expected ')' after expr list found {:?}",
-                        input.get(next_offset)
-                    ));
+                match input.get(next_offset) {
+                    Some((_, LexItem::Punctuation(Punctuation::Paren))) => {
+                        Ok((identifier_expr, next_offset + 1))
+                    }
+                    x => Err(QueryError::expected(
+                        &format!("',' or ')' in the arguments of {}", n),
+                        x,
+                    )),
                 }
             } else {
-                Err(format!(
-                    "expected open paren after identifier, found {:?}",
-                    input.get(offset + 1)
+                Err(QueryError::expected(
+                    &format!("'(' after {}", n),
+                    input.get(offset + 1),
                 ))
             }
         }
-        Some((ctx, LexItem::Punctuation(Punctuation::Paren))) => {
+        Some((_, LexItem::Punctuation(Punctuation::Paren))) => {
             let (expr, next_offset) = parse_expr_inner(input, offset + 1)?;
-            if let Some((_, LexItem::Punctuation(Punctuation::Paren))) = input.get(next_offset) {
-                Ok((expr, next_offset + 1))
-            } else {
-                Err(format!("Mismatched parenthesis. {:?}", ctx))
+            match input.get(next_offset) {
+                Some((_, LexItem::Punctuation(Punctuation::Paren))) => Ok((expr, next_offset + 1)),
+                x => Err(QueryError::expected("')'", x)),
             }
         }
-        Some((ctx, LexItem::FieldRef(record_type, field_type, subfield_type))) => Ok((
-            ParseNode::new(
-                LexItem::FieldRef(*record_type, *field_type, *subfield_type),
-                ctx.clone(),
-            ),
+        Some((_, LexItem::FieldRef(..))) => Ok(parse_FIELD_REF(input, offset).unwrap()),
+        Some((ctx, LexItem::StringLit(s))) => Ok((
+            ParseNode::new(LexItem::StringLit(s), ctx.clone()),
+            offset + 1,
+        )),
+        Some((ctx, LexItem::NumberLit(n))) => Ok((
+            ParseNode::new(LexItem::NumberLit(n), ctx.clone()),
             offset + 1,
         )),
-        Some((ctx, i)) => Err(format!(
-            "Expected identifier, field ref, or '(' but found {:?} at {:?}",
-            i, ctx
+        x => Err(QueryError::expected(
+            "a field ref, a literal, a function or '('",
+            x,
         )),
-        _ => Err("Expected 'not' or '(' but reached end of input".to_string()),
     }
 }
 
 fn parse_TERM<'a>(
     input: &[(ItemContext, LexItem<'a>)],
     offset: usize,
-) -> Result<(ParseNode<'a>, usize), String> {
+) -> Result<(ParseNode<'a>, usize), QueryError> {
     let (lhs, next_offset) = parse_NOT(input, offset)?;
-    dbg!(&lhs);
-    dbg!(input.get(next_offset));
     match input.get(next_offset) {
-        Some((ctx, LexItem::InfixFunction(InfixFn::EqOp))) => {
+        Some((
+            ctx,
+            LexItem::InfixFunction(
+                op @ (InfixFn::EqOp
+                | InfixFn::NotEqOp
+                | InfixFn::LtOp
+                | InfixFn::LeOp
+                | InfixFn::GtOp
+                | InfixFn::GeOp),
+            ),
+        )) => {
             let (rhs, next_offset) = parse_NOT(input, next_offset + 1)?;
-            let mut eqnode = ParseNode::new(LexItem::InfixFunction(InfixFn::EqOp), ctx.clone());
+            let mut eqnode = ParseNode::new(LexItem::InfixFunction(op.clone()), ctx.clone());
             eqnode.children.push(lhs);
             eqnode.children.push(rhs);
             Ok((eqnode, next_offset))
         }
         Some((ctx, LexItem::InfixFunction(InfixFn::MatchOp))) => {
-            match (lhs.entry, input.get(next_offset + 1)) {
-                (
-                    LexItem::FieldRef(record_type, field_type, subfield_type),
-                    Some((ctx2, LexItem::RegexStr(regex))),
-                ) => {
-                    let field_ref_node = ParseNode::new(
-                        LexItem::FieldRef(record_type, field_type, subfield_type),
-                        ctx.clone(),
-                    );
+            if !matches!(lhs.entry, LexItem::FieldRef(..)) {
+                return Err(QueryError::expected(
+                    "a field ref before ~",
+                    Some(&(lhs.context, lhs.entry)),
+                ));
+            }
+            match input.get(next_offset + 1) {
+                Some((ctx2, LexItem::StringLit(regex))) => {
                     let mut matchnode =
                         ParseNode::new(LexItem::InfixFunction(InfixFn::MatchOp), ctx.clone());
-                    matchnode.children.push(field_ref_node);
+                    matchnode.children.push(lhs);
                     matchnode
                         .children
-                        .push(ParseNode::new(LexItem::RegexStr(*regex), ctx2.clone()));
+                        .push(ParseNode::new(LexItem::RegexStr(regex), ctx2.clone()));
                     Ok((matchnode, next_offset + 2))
                 }
-                _ => Err("todo nice message".to_string()),
+                x => Err(QueryError::expected("a quoted regex after ~", x)),
             }
         }
+        Some((ctx, LexItem::InfixFunction(InfixFn::In))) => {
+            if !matches!(lhs.entry, LexItem::FieldRef(..)) {
+                return Err(QueryError::expected(
+                    "a field ref before in",
+                    Some(&(lhs.context, lhs.entry)),
+                ));
+            }
+            let mut in_node = ParseNode::new(LexItem::InfixFunction(InfixFn::In), ctx.clone());
+            in_node.children.push(lhs);
+            let (values, next_offset) = parse_IN_LIST(input, next_offset + 1)?;
+            in_node.children.extend(values);
+            Ok((in_node, next_offset))
+        }
         _ => Ok((lhs, next_offset)),
     }
 }
 
-fn parse_COLUMN_EXPR<'a>(
+// the literals of the list or a file node with the path as its child
+fn parse_IN_LIST<'a>(
     input: &[(ItemContext, LexItem<'a>)],
     offset: usize,
-) -> Result<(ParseNode<'a>, usize), String> {
-    if let Some((ctx, LexItem::FieldRef(record_type, field_type, subfield_type))) =
-        input.get(offset)
-    {
-        Ok((
-            ParseNode::new(
-                LexItem::FieldRef(*record_type, *field_type, *subfield_type),
-                ctx.clone(),
-            ),
+) -> Result<(Vec<ParseNode<'a>>, usize), QueryError> {
+    match input.get(offset) {
+        Some((ctx, LexItem::Identifier("file"))) => {
+            match input.get(offset + 1) {
+                Some((_, LexItem::Punctuation(Punctuation::Paren))) => {}
+                x => return Err(QueryError::expected("'(' after file", x)),
+            }
+            let mut file_node = ParseNode::new(LexItem::Identifier("file"), ctx.clone());
+            match input.get(offset + 2) {
+                Some((path_ctx, LexItem::StringLit(path))) => file_node
+                    .children
+                    .push(ParseNode::new(LexItem::StringLit(path), path_ctx.clone())),
+                x => return Err(QueryError::expected("a quoted path in file(...)", x)),
+            }
+            match input.get(offset + 3) {
+                Some((_, LexItem::Punctuation(Punctuation::Paren))) => {
+                    Ok((vec![file_node], offset + 4))
+                }
+                x => Err(QueryError::expected("')' after the path", x)),
+            }
+        }
+        Some((_, LexItem::Punctuation(Punctuation::Paren)))
+            if matches!(
+                input.get(offset + 1),
+                Some((_, LexItem::KW(Keyword::Select)))
+            ) =>
+        {
+            let (select, next_offset) = parse_SUBQUERY(input, offset)?;
+            Ok((vec![select], next_offset))
+        }
+        Some((_, LexItem::Punctuation(Punctuation::Paren))) => {
+            let mut values = Vec::new();
+            let mut next_offset = offset + 1;
+            loop {
+                match input.get(next_offset) {
+                    Some((ctx, literal @ (LexItem::StringLit(_) | LexItem::NumberLit(_)))) => {
+                        values.push(ParseNode::new(literal.clone(), ctx.clone()))
+                    }
+                    x => return Err(QueryError::expected("a literal in the list of in", x)),
+                }
+                match input.get(next_offset + 1) {
+                    Some((_, LexItem::Punctuation(Punctuation::Comma))) => next_offset += 2,
+                    Some((_, LexItem::Punctuation(Punctuation::Paren))) => {
+                        return Ok((values, next_offset + 2))
+                    }
+                    x => return Err(QueryError::expected("',' or ')' in the list of in", x)),
+                }
+            }
+        }
+        x => Err(QueryError::expected("a list or file(...) after in", x)),
+    }
+}
+
+// the arguments of a function call are its children, after distinct they
+// are the children of the distinct node
+pub fn parse_COLUMN_EXPR<'a>(
+    input: &[(ItemContext, LexItem<'a>)],
+    offset: usize,
+) -> Result<(ParseNode<'a>, usize), QueryError> {
+    match input.get(offset) {
+        Some((ctx, LexItem::Identifier(name))) => {
+            if !matches!(
+                input.get(offset + 1),
+                Some((_, LexItem::Punctuation(Punctuation::Paren)))
+            ) {
+                return Err(QueryError::expected(
+                    &format!("'(' after {}", name),
+                    input.get(offset + 1),
+                ));
+            }
+            let mut call = ParseNode::new(LexItem::Identifier(name), ctx.clone());
+            let mut next_offset = offset + 2;
+            let mut distinct = None;
+            if let Some((ctx, LexItem::KW(Keyword::Distinct))) = input.get(next_offset) {
+                distinct = Some(ParseNode::new(LexItem::KW(Keyword::Distinct), ctx.clone()));
+                next_offset += 1;
+            }
+            let mut arguments = Vec::new();
+            loop {
+                let (argument, offset) = parse_COLUMN_EXPR(input, next_offset)?;
+                arguments.push(argument);
+                next_offset = offset + 1;
+                match input.get(offset) {
+                    Some((_, LexItem::Punctuation(Punctuation::Comma))) => {}
+                    Some((_, LexItem::Punctuation(Punctuation::Paren))) => break,
+                    x => {
+                        return Err(QueryError::expected(
+                            &format!("',' or ')' in the arguments of {}", name),
+                            x,
+                        ))
+                    }
+                }
+            }
+            match distinct {
+                Some(mut distinct) => {
+                    distinct.children = arguments;
+                    call.children.push(distinct);
+                }
+                None => call.children = arguments,
+            }
+            Ok((call, next_offset))
+        }
+        Some((ctx, LexItem::StringLit(s))) => Ok((
+            ParseNode::new(LexItem::StringLit(s), ctx.clone()),
             offset + 1,
-        ))
-    } else {
-        Err("expected a field ref expression".to_string())
+        )),
+        Some((ctx, LexItem::NumberLit(n))) => Ok((
+            ParseNode::new(LexItem::NumberLit(n), ctx.clone()),
+            offset + 1,
+        )),
+        x => parse_FIELD_REF(input, offset)
+            .ok_or_else(|| QueryError::expected("a field ref, a literal or a function", x)),
     }
 }
 
 pub fn parse_OR<'a>(
     input: &[(ItemContext, LexItem<'a>)],
     offset: usize,
-) -> Result<(ParseNode<'a>, usize), String> {
+) -> Result<(ParseNode<'a>, usize), QueryError> {
     let (lhs, next_offset) = parse_TERM(input, offset)?;
     let c = input.get(next_offset);
     match c {
@@ -210,7 +388,6 @@ pub fn parse_OR<'a>(
 #[cfg(test)]
 mod test {
     use crate::exprparse::*;
-    use crate::parser::*;
     #[test]
     fn test_parse1() -> Result<(), String> {
         let str1 = "150 ~ 'aoeu'";
@@ -336,13 +513,79 @@ mod test {
         }
         Ok(())
     }
+    #[test]
+    fn test_parse_literals() -> Result<(), String> {
+        let str1 = "150.a != 'Katze' or 008 = -1";
+        let (p, _) = parse_expr(&lex(str1)?, 0)?;
+        let mut v: Vec<LexItem<'static>> = Vec::new();
+        p.visit_pre(&mut |n: &ParseNode<'static>| v.push(n.entry.clone()));
+        assert_eq!(
+            v,
+            vec![
+                LexItem::InfixFunction(InfixFn::Or),
+                LexItem::InfixFunction(InfixFn::NotEqOp),
+                LexItem::FieldRef(None, Some("150"), Some("a")),
+                LexItem::StringLit("Katze"),
+                LexItem::InfixFunction(InfixFn::EqOp),
+                LexItem::FieldRef(None, Some("008"), None),
+                LexItem::NumberLit("-1"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_in() -> Result<(), String> {
+        let (p, _) = parse_expr(&lex("001 in ('x', +1, 2) and 035.a in file('ids.txt')")?, 0)?;
+        let mut v: Vec<LexItem<'static>> = Vec::new();
+        p.visit_pre(&mut |n: &ParseNode<'static>| v.push(n.entry.clone()));
+        assert_eq!(
+            v,
+            vec![
+                LexItem::InfixFunction(InfixFn::And),
+                LexItem::InfixFunction(InfixFn::In),
+                LexItem::FieldRef(None, Some("001"), None),
+                LexItem::StringLit("x"),
+                LexItem::NumberLit("+1"),
+                LexItem::NumberLit("2"),
+                LexItem::InfixFunction(InfixFn::In),
+                LexItem::FieldRef(None, Some("035"), Some("a")),
+                LexItem::Identifier("file"),
+                LexItem::StringLit("ids.txt"),
+            ]
+        );
+        assert!(parse_expr(&lex("001 in (035.a)")?, 0).is_err());
+        assert!(parse_expr(&lex("001 in ()")?, 0).is_err());
+        assert!(parse_expr(&lex("'x' in ('x')")?, 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_subquery() -> Result<(), String> {
+        let (p, _) = parse_expr(
+            &lex("773.w in (select 001 from journals where 041.a = 'eng') or exists (select 001 from gnd)")?,
+            0,
+        )?;
+        assert_eq!(p.children[0].entry, LexItem::InfixFunction(InfixFn::In));
+        let subquery = &p.children[0].children[1];
+        assert_eq!(subquery.entry, LexItem::KW(Keyword::Select));
+        assert_eq!(subquery.children.len(), 3);
+        assert_eq!(p.children[1].entry, LexItem::Identifier("exists"));
+        assert_eq!(
+            p.children[1].children[0].entry,
+            LexItem::KW(Keyword::Select)
+        );
+        assert!(parse_expr(&lex("exists (select 001 from gnd")?, 0).is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_parse5() -> Result<(), String> {
         let str = "not_null(150)";
         let (p, _) = parse_expr(&lex(str)?, 0)?;
         assert_eq!(p.entry, LexItem::Identifier("not_null"));
         assert_eq!(
-            p.children.get(0).map(|x| x.entry.clone()),
+            p.children.first().map(|x| x.entry.clone()),
             Some(LexItem::FieldRef(None, Some("150"), None))
         );
         Ok(())
@@ -1,8 +1,11 @@
-use crate::record::{Record, RecordField, RecordType};
+use crate::record::{Record, RecordField, RecordType, LEADER_FIELD_TYPE};
 
 pub trait FieldExpression {
     fn compute<'a>(&self, record: &'a dyn Record)
         -> Box<dyn Iterator<Item = RecordField<'a>> + 'a>;
+
+    // the expression for explain
+    fn describe(&self) -> String;
 }
 
 pub struct FieldTypeSelect {
@@ -21,15 +24,52 @@ impl FieldExpression for FieldTypeSelect {
         &self,
         record: &'a dyn Record,
     ) -> Box<dyn Iterator<Item = RecordField<'a>> + 'a> {
-        dbg!("compute");
         Box::new(record.field_iter_vec(&self.field_types))
     }
+
+    fn describe(&self) -> String {
+        let field_types: Vec<String> = self
+            .field_types
+            .iter()
+            .map(|x| format!("{:03}", x))
+            .collect();
+        format!("fields {}", field_types.join(", "))
+    }
+}
+
+// the part of a field a field ref selects
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldPart {
+    Whole,
+    Subfield(u8),
+    AllSubfields,
+    Indicator1,
+    Indicator2,
+}
+
+impl FieldPart {
+    // the subfield component of a field ref token
+    pub fn from_token(s: Option<&str>) -> FieldPart {
+        match s {
+            None => FieldPart::Whole,
+            Some("ind1") => FieldPart::Indicator1,
+            Some("ind2") => FieldPart::Indicator2,
+            Some("*") => FieldPart::AllSubfields,
+            Some(code) => FieldPart::Subfield(code.as_bytes()[0]),
+        }
+    }
 }
 
 pub struct FieldRefExpr {
     record_type: Option<RecordType>,
+    // the field type "leader" selects the leader
+    leader: bool,
     field_type: Option<usize>,
-    subfield_type: Option<u8>,
+    part: FieldPart,
+    // byte positions [start, end) of the selected values
+    range: Option<(usize, Option<usize>)>,
+    // the table of a join the field belongs to
+    side: Option<usize>,
 }
 
 use std::marker::PhantomData;
@@ -55,10 +95,26 @@ impl FieldRefExpr {
     ) -> FieldRefExpr {
         FieldRefExpr {
             record_type: record_type.and_then(RecordType::from_str),
+            leader: field_type == Some("leader"),
             field_type: field_type.and_then(|x| x.parse::<usize>().ok()),
-            subfield_type: subfield_type.map(|x| x.bytes().next().unwrap()),
+            part: FieldPart::from_token(subfield_type),
+            range: None,
+            side: None,
         }
     }
+
+    // only the bytes start..end of each value, values that are too short
+    // to contain start are skipped
+    pub fn with_range(mut self, start: usize, end: Option<usize>) -> FieldRefExpr {
+        self.range = Some((start, end));
+        self
+    }
+
+    // only the fields of one table of a joined record
+    pub fn with_side(mut self, side: usize) -> FieldRefExpr {
+        self.side = Some(side);
+        self
+    }
 }
 
 impl FieldExpression for FieldRefExpr {
@@ -66,14 +122,119 @@ impl FieldExpression for FieldRefExpr {
         &self,
         record: &'a dyn Record,
     ) -> Box<dyn Iterator<Item = RecordField<'a>> + 'a> {
+        let record = match self.side {
+            None => record,
+            Some(side) => match record.joined(side) {
+                Some(record) => record,
+                None => return Box::new(EmptyIter { _p: PhantomData }),
+            },
+        };
         if self
             .record_type
-            .as_ref()
-            .map(|x| *x == record.record_type())
-            .unwrap_or(false)
+            .is_some_and(|x| Some(x) != record.record_type())
         {
             return Box::new(EmptyIter { _p: PhantomData });
         }
-        Box::new(record.field_iter(self.field_type))
+        let fields: Box<dyn Iterator<Item = RecordField<'a>> + 'a> = if self.leader {
+            Box::new(std::iter::once(RecordField {
+                field_type: LEADER_FIELD_TYPE,
+                data: record.header(),
+            }))
+        } else {
+            record.field_iter(self.field_type)
+        };
+        let values: Box<dyn Iterator<Item = RecordField<'a>> + 'a> = match self.part {
+            FieldPart::Whole => Box::new(fields),
+            // every subfield on its own
+            FieldPart::AllSubfields => Box::new(fields.flat_map(|field| {
+                let field_type = field.field_type;
+                field
+                    .subfields()
+                    .map(move |(_, data)| RecordField { field_type, data })
+            })),
+            FieldPart::Subfield(code) => Box::new(fields.flat_map(move |field| {
+                let field_type = field.field_type;
+                field
+                    .subfields_by_code(code)
+                    .map(move |data| RecordField { field_type, data })
+            })),
+            FieldPart::Indicator1 | FieldPart::Indicator2 => {
+                let i = if self.part == FieldPart::Indicator1 {
+                    0
+                } else {
+                    1
+                };
+                Box::new(fields.filter_map(move |field| {
+                    if field.is_control_field() || field.data.len() <= i {
+                        None
+                    } else {
+                        Some(RecordField {
+                            field_type: field.field_type,
+                            data: &field.data[i..i + 1],
+                        })
+                    }
+                }))
+            }
+        };
+        match self.range {
+            None => values,
+            Some((start, end)) => Box::new(values.filter_map(move |value| {
+                let len = value.data.len();
+                let end = end.map_or(len, |x| std::cmp::min(x, len));
+                // positions count bytes, a character they cut is taken whole
+                let is_inside = |i: usize| i < len && value.data[i] & 0xc0 == 0x80;
+                let mut start = start;
+                while start > 0 && is_inside(start) {
+                    start -= 1;
+                }
+                let mut end = end;
+                while is_inside(end) {
+                    end += 1;
+                }
+                if start < end {
+                    Some(RecordField {
+                        field_type: value.field_type,
+                        data: &value.data[start..end],
+                    })
+                } else {
+                    None
+                }
+            })),
+        }
+    }
+
+    // like the field ref in the query, the side of a join comes first
+    fn describe(&self) -> String {
+        let mut text = String::new();
+        match self.side {
+            Some(0) => text.push_str("left:"),
+            Some(_) => text.push_str("right:"),
+            None => {}
+        }
+        if let Some(record_type) = self.record_type {
+            text.push_str(&record_type.qualifier());
+            text.push('.');
+        }
+        match self.field_type {
+            _ if self.leader => text.push_str("leader"),
+            Some(field_type) => text.push_str(&format!("{:03}", field_type)),
+            None => text.push('*'),
+        }
+        match self.part {
+            FieldPart::Whole => {}
+            FieldPart::Subfield(code) => {
+                text.push('.');
+                text.push(code as char);
+            }
+            FieldPart::AllSubfields => text.push_str(".*"),
+            FieldPart::Indicator1 => text.push_str(".ind1"),
+            FieldPart::Indicator2 => text.push_str(".ind2"),
+        }
+        match self.range {
+            Some((start, Some(end))) => text.push_str(&format!("[{}:{}]", start, end)),
+            Some((start, None)) => text.push_str(&format!("[{}:]", start)),
+            None => {}
+        }
+        text
     }
 }
@@ -1,8 +1,14 @@
 use crate::field_expression::FieldExpression;
-use crate::util::TriStateBool;
+use crate::profile::FilterStats;
+use crate::util::{compare_values, parse_number, TriStateBool};
 use crate::Record;
 use regex::bytes::Regex;
 use std::any::Any;
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::rc::Rc;
 
 pub trait Filter: Any {
     //fn filter(values : &mut Vec<Record>);
@@ -10,8 +16,9 @@ pub trait Filter: Any {
     fn filter<'a>(&self, values: &mut [Box<dyn Record + 'a>]) -> (usize, usize) {
         let mut true_pos = 0;
         let mut null_pos = 0;
-        let mut false_pos = values.len() - 1;
-        while null_pos <= false_pos {
+        // everything from false_pos on is known to be false
+        let mut false_pos = values.len();
+        while null_pos < false_pos {
             match self.evaluate_predicate(&*values[null_pos]) {
                 TriStateBool::True => {
                     values.swap(true_pos, null_pos);
@@ -19,8 +26,8 @@ pub trait Filter: Any {
                     null_pos += 1;
                 }
                 TriStateBool::False => {
-                    values.swap(null_pos, false_pos);
                     false_pos -= 1;
+                    values.swap(null_pos, false_pos);
                 }
                 TriStateBool::Null => null_pos += 1,
             }
@@ -28,6 +35,20 @@ pub trait Filter: Any {
         (true_pos, null_pos)
     }
     fn as_any(&mut self) -> &mut dyn Any;
+    // the type of the node and its arguments for explain
+    fn describe(&self) -> String;
+    // the filters this one combines
+    fn inputs(&self) -> Vec<&dyn Filter> {
+        Vec::new()
+    }
+    // the same, so explain analyze can wrap them
+    fn inputs_mut(&mut self) -> Vec<&mut Box<dyn Filter>> {
+        Vec::new()
+    }
+    // the counters of a filter wrapped by explain analyze
+    fn stats(&self) -> Option<Rc<FilterStats>> {
+        None
+    }
 }
 
 pub struct RegexFilter {
@@ -36,11 +57,14 @@ pub struct RegexFilter {
 }
 
 impl RegexFilter {
-    pub fn new(field_expr: Box<dyn FieldExpression>, regex: &str) -> RegexFilter {
-        RegexFilter {
+    pub fn new(
+        field_expr: Box<dyn FieldExpression>,
+        regex: &str,
+    ) -> Result<RegexFilter, regex::Error> {
+        Ok(RegexFilter {
             field_expr,
-            regex: Regex::new(regex).unwrap(),
-        }
+            regex: Regex::new(regex)?,
+        })
     }
 }
 
@@ -62,6 +86,13 @@ impl Filter for RegexFilter {
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+    fn describe(&self) -> String {
+        format!(
+            "RegexFilter({} ~ '{}')",
+            self.field_expr.describe(),
+            self.regex.as_str()
+        )
+    }
 }
 
 pub struct AndFilter {
@@ -97,6 +128,15 @@ impl Filter for AndFilter {
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+    fn describe(&self) -> String {
+        "AndFilter".to_string()
+    }
+    fn inputs(&self) -> Vec<&dyn Filter> {
+        self.children.iter().map(|x| &**x).collect()
+    }
+    fn inputs_mut(&mut self) -> Vec<&mut Box<dyn Filter>> {
+        self.children.iter_mut().collect()
+    }
 }
 
 pub struct OrFilter {
@@ -132,6 +172,15 @@ impl Filter for OrFilter {
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+    fn describe(&self) -> String {
+        "OrFilter".to_string()
+    }
+    fn inputs(&self) -> Vec<&dyn Filter> {
+        self.children.iter().map(|x| &**x).collect()
+    }
+    fn inputs_mut(&mut self) -> Vec<&mut Box<dyn Filter>> {
+        self.children.iter_mut().collect()
+    }
 }
 
 pub struct NotFilter {
@@ -151,6 +200,15 @@ impl Filter for NotFilter {
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+    fn describe(&self) -> String {
+        "NotFilter".to_string()
+    }
+    fn inputs(&self) -> Vec<&dyn Filter> {
+        vec![&*self.child]
+    }
+    fn inputs_mut(&mut self) -> Vec<&mut Box<dyn Filter>> {
+        vec![&mut self.child]
+    }
 }
 
 pub struct NotNullFilter {
@@ -166,22 +224,32 @@ impl NotNullFilter {
 impl Filter for NotNullFilter {
     fn evaluate_predicate(&self, r: &dyn Record) -> TriStateBool {
         match &self.child {
-            FilterInput::filter(f) => match f.evaluate_predicate(r) {
+            FilterInput::Filter(f) => match f.evaluate_predicate(r) {
                 TriStateBool::Null => TriStateBool::False,
                 _ => TriStateBool::True,
             },
-            FilterInput::field_ref(f) => {
+            FilterInput::FieldRef(f) => {
                 if f.compute(r).next().is_some() {
                     TriStateBool::True
                 } else {
                     TriStateBool::False
                 }
             }
+            FilterInput::Literal(_) => TriStateBool::True,
         }
     }
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+    fn describe(&self) -> String {
+        format!("NotNullFilter({})", self.child.describe())
+    }
+    fn inputs(&self) -> Vec<&dyn Filter> {
+        self.child.filter().into_iter().collect()
+    }
+    fn inputs_mut(&mut self) -> Vec<&mut Box<dyn Filter>> {
+        self.child.filter_mut().into_iter().collect()
+    }
 }
 
 pub struct IsNullFilter {
@@ -197,27 +265,96 @@ impl IsNullFilter {
 impl Filter for IsNullFilter {
     fn evaluate_predicate(&self, r: &dyn Record) -> TriStateBool {
         match &self.child {
-            FilterInput::filter(f) => match f.evaluate_predicate(r) {
+            FilterInput::Filter(f) => match f.evaluate_predicate(r) {
                 TriStateBool::Null => TriStateBool::True,
                 _ => TriStateBool::False,
             },
-            FilterInput::field_ref(f) => {
+            FilterInput::FieldRef(f) => {
                 if f.compute(r).next().is_some() {
                     TriStateBool::False
                 } else {
                     TriStateBool::True
                 }
             }
+            FilterInput::Literal(_) => TriStateBool::False,
         }
     }
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+    fn describe(&self) -> String {
+        format!("IsNullFilter({})", self.child.describe())
+    }
+    fn inputs(&self) -> Vec<&dyn Filter> {
+        self.child.filter().into_iter().collect()
+    }
+    fn inputs_mut(&mut self) -> Vec<&mut Box<dyn Filter>> {
+        self.child.filter_mut().into_iter().collect()
+    }
+}
+
+// a constant in an expression, numbers compare by value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Str(Vec<u8>),
+    Number(f64),
+}
+
+impl Literal {
+    pub fn matches(&self, data: &[u8]) -> bool {
+        match self {
+            Literal::Str(s) => s.as_slice() == data,
+            Literal::Number(n) => parse_number(data) == Some(*n),
+        }
+    }
+
+    // numbers are written without sign and trailing zeros
+    pub fn to_bytes(&self) -> Cow<'_, [u8]> {
+        match self {
+            Literal::Str(s) => Cow::Borrowed(s),
+            Literal::Number(n) => Cow::Owned(n.to_string().into_bytes()),
+        }
+    }
+
+    pub fn equals(&self, other: &Literal) -> bool {
+        match (self, other) {
+            (Literal::Number(a), Literal::Number(b)) => a == b,
+            (Literal::Str(s), _) => other.matches(s),
+            (_, Literal::Str(s)) => self.matches(s),
+        }
+    }
 }
 
 pub enum FilterInput {
-    filter(Box<dyn Filter>),
-    field_ref(Box<dyn FieldExpression>),
+    Filter(Box<dyn Filter>),
+    FieldRef(Box<dyn FieldExpression>),
+    Literal(Literal),
+}
+
+impl FilterInput {
+    fn filter(&self) -> Option<&dyn Filter> {
+        match self {
+            FilterInput::Filter(f) => Some(&**f),
+            _ => None,
+        }
+    }
+
+    fn filter_mut(&mut self) -> Option<&mut Box<dyn Filter>> {
+        match self {
+            FilterInput::Filter(f) => Some(f),
+            _ => None,
+        }
+    }
+
+    // filters are described on their own
+    fn describe(&self) -> String {
+        match self {
+            FilterInput::Filter(_) => "filter".to_string(),
+            FilterInput::FieldRef(f) => f.describe(),
+            FilterInput::Literal(Literal::Str(s)) => format!("'{}'", String::from_utf8_lossy(s)),
+            FilterInput::Literal(l) => String::from_utf8_lossy(&l.to_bytes()).into_owned(),
+        }
+    }
 }
 pub struct EqFilter {
     left_child: FilterInput,
@@ -236,14 +373,14 @@ impl EqFilter {
 impl Filter for EqFilter {
     fn evaluate_predicate(&self, r: &dyn Record) -> TriStateBool {
         match (&self.left_child, &self.right_child) {
-            (FilterInput::filter(f1), FilterInput::filter(f2)) => {
+            (FilterInput::Filter(f1), FilterInput::Filter(f2)) => {
                 if f1.evaluate_predicate(r) == f2.evaluate_predicate(r) {
                     TriStateBool::True
                 } else {
                     TriStateBool::False
                 }
             }
-            (FilterInput::field_ref(f1), FilterInput::field_ref(f2)) => {
+            (FilterInput::FieldRef(f1), FilterInput::FieldRef(f2)) => {
                 let mut has_f1 = false;
                 let mut has_f2 = false;
                 // TODO hash instead of nested-loop?
@@ -259,7 +396,29 @@ impl Filter for EqFilter {
                 if has_f1 && has_f2 {
                     TriStateBool::False
                 } else {
+                    TriStateBool::Null
+                }
+            }
+            (FilterInput::FieldRef(f), FilterInput::Literal(l))
+            | (FilterInput::Literal(l), FilterInput::FieldRef(f)) => {
+                let mut has_field = false;
+                for field in f.compute(r) {
+                    has_field = true;
+                    if l.matches(field.data) {
+                        return TriStateBool::True;
+                    }
+                }
+                if has_field {
+                    TriStateBool::False
+                } else {
+                    TriStateBool::Null
+                }
+            }
+            (FilterInput::Literal(l1), FilterInput::Literal(l2)) => {
+                if l1.equals(l2) {
                     TriStateBool::True
+                } else {
+                    TriStateBool::False
                 }
             }
             _ => unreachable!(),
@@ -268,6 +427,245 @@ impl Filter for EqFilter {
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+    fn describe(&self) -> String {
+        format!(
+            "EqFilter({} = {})",
+            self.left_child.describe(),
+            self.right_child.describe()
+        )
+    }
+    fn inputs(&self) -> Vec<&dyn Filter> {
+        self.left_child
+            .filter()
+            .into_iter()
+            .chain(self.right_child.filter())
+            .collect()
+    }
+    fn inputs_mut(&mut self) -> Vec<&mut Box<dyn Filter>> {
+        self.left_child
+            .filter_mut()
+            .into_iter()
+            .chain(self.right_child.filter_mut())
+            .collect()
+    }
+}
+
+// the values a field ref or literal provides, filters don't have values
+fn input_values<'a>(
+    input: &'a FilterInput,
+    r: &'a dyn Record,
+) -> Box<dyn Iterator<Item = Cow<'a, [u8]>> + 'a> {
+    match input {
+        FilterInput::FieldRef(f) => Box::new(f.compute(r).map(|x| Cow::Borrowed(x.data))),
+        FilterInput::Literal(l) => Box::new(std::iter::once(l.to_bytes())),
+        FilterInput::Filter(_) => unreachable!(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+        }
+    }
+
+    fn holds(self, ord: Ordering) -> bool {
+        match self {
+            CompareOp::Lt => ord == Ordering::Less,
+            CompareOp::Le => ord != Ordering::Greater,
+            CompareOp::Gt => ord == Ordering::Greater,
+            CompareOp::Ge => ord != Ordering::Less,
+        }
+    }
+}
+
+// true if any pair of values compares as requested, null if a side has no values
+pub struct CompareFilter {
+    left_child: FilterInput,
+    op: CompareOp,
+    right_child: FilterInput,
+}
+
+impl CompareFilter {
+    pub fn new(left_child: FilterInput, op: CompareOp, right_child: FilterInput) -> CompareFilter {
+        CompareFilter {
+            left_child,
+            op,
+            right_child,
+        }
+    }
+}
+
+impl Filter for CompareFilter {
+    fn evaluate_predicate(&self, r: &dyn Record) -> TriStateBool {
+        let right: Vec<Cow<[u8]>> = input_values(&self.right_child, r).collect();
+        if right.is_empty() {
+            return TriStateBool::Null;
+        }
+        let mut has_left = false;
+        for left in input_values(&self.left_child, r) {
+            has_left = true;
+            if right
+                .iter()
+                .any(|x| self.op.holds(compare_values(&left, x)))
+            {
+                return TriStateBool::True;
+            }
+        }
+        if has_left {
+            TriStateBool::False
+        } else {
+            TriStateBool::Null
+        }
+    }
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn describe(&self) -> String {
+        format!(
+            "CompareFilter({} {} {})",
+            self.left_child.describe(),
+            self.op.symbol(),
+            self.right_child.describe()
+        )
+    }
+}
+
+// a set of literals, numbers compare by value. Large lists are looked up in
+// hash sets instead of comparing every literal.
+#[derive(Default)]
+pub struct ValueSet {
+    strings: HashSet<Vec<u8>>,
+    // the bits of the numbers, 0 and -0 are the same
+    numbers: HashSet<u64>,
+}
+
+fn number_key(x: f64) -> u64 {
+    if x == 0.0 {
+        0
+    } else {
+        x.to_bits()
+    }
+}
+
+impl ValueSet {
+    pub fn insert(&mut self, literal: Literal) {
+        match literal {
+            Literal::Str(s) => {
+                self.strings.insert(s);
+            }
+            Literal::Number(n) => {
+                self.numbers.insert(number_key(n));
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len() + self.numbers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, data: &[u8]) -> bool {
+        self.strings.contains(data)
+            || (!self.numbers.is_empty()
+                && parse_number(data).is_some_and(|x| self.numbers.contains(&number_key(x))))
+    }
+}
+
+// true if a value is in the set. The set of a subquery is filled in when the
+// subquery has run, before the filter is used.
+pub struct InFilter {
+    field_expr: Box<dyn FieldExpression>,
+    values: Rc<OnceCell<ValueSet>>,
+}
+
+impl InFilter {
+    pub fn new(field_expr: Box<dyn FieldExpression>, literals: Vec<Literal>) -> InFilter {
+        let mut values = ValueSet::default();
+        for literal in literals {
+            values.insert(literal);
+        }
+        InFilter::with_values(field_expr, Rc::new(OnceCell::from(values)))
+    }
+
+    pub fn with_values(
+        field_expr: Box<dyn FieldExpression>,
+        values: Rc<OnceCell<ValueSet>>,
+    ) -> InFilter {
+        InFilter { field_expr, values }
+    }
+}
+
+impl Filter for InFilter {
+    fn evaluate_predicate(&self, r: &dyn Record) -> TriStateBool {
+        let values = self.values.get().expect("the subquery has not run");
+        let mut has_field = false;
+        for field in self.field_expr.compute(r) {
+            has_field = true;
+            if values.contains(field.data) {
+                return TriStateBool::True;
+            }
+        }
+        if has_field {
+            TriStateBool::False
+        } else {
+            TriStateBool::Null
+        }
+    }
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn describe(&self) -> String {
+        match self.values.get() {
+            Some(values) => format!(
+                "InFilter({}, {} values)",
+                self.field_expr.describe(),
+                values.len()
+            ),
+            None => format!("InFilter({}, subquery)", self.field_expr.describe()),
+        }
+    }
+}
+
+// whether a subquery has a row, it is the same for every record
+pub struct ExistsFilter {
+    result: Rc<OnceCell<bool>>,
+}
+
+impl ExistsFilter {
+    pub fn new(result: Rc<OnceCell<bool>>) -> ExistsFilter {
+        ExistsFilter { result }
+    }
+}
+
+impl Filter for ExistsFilter {
+    fn evaluate_predicate(&self, _: &dyn Record) -> TriStateBool {
+        if *self.result.get().expect("the subquery has not run") {
+            TriStateBool::True
+        } else {
+            TriStateBool::False
+        }
+    }
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn describe(&self) -> String {
+        "ExistsFilter(subquery)".to_string()
+    }
 }
 
 #[cfg(test)]
@@ -338,12 +736,15 @@ mod test {
         fn as_any(&mut self) -> &mut dyn Any {
             self
         }
+        fn describe(&self) -> String {
+            "TestFilter".to_string()
+        }
     }
 
     #[test]
     fn test_filter() {
         let mut data = test_data();
-        let mut filter = TestFilter {
+        let filter = TestFilter {
             results: vec![
                 TriStateBool::False,
                 TriStateBool::Null,
@@ -362,19 +763,22 @@ mod test {
     #[test]
     fn test_regex() {
         let field_expr = FieldRefExpr::new(None, Some("23"), None);
-        let regex = RegexFilter::new(Box::new(field_expr), "foo");
+        let regex = RegexFilter::new(Box::new(field_expr), "foo").unwrap();
+        assert!(
+            RegexFilter::new(Box::new(FieldRefExpr::new(None, Some("23"), None)), "(").is_err()
+        );
         let mut data = test_data();
         let (t, n) = regex.filter(&mut data);
         assert_eq!(t, 2);
-        for i in 0..t {
-            assert_eq!(regex.evaluate_predicate(&*data[i]), TriStateBool::True);
+        for r in &data[..t] {
+            assert_eq!(regex.evaluate_predicate(&**r), TriStateBool::True);
         }
         assert_eq!(n, 4);
-        for i in t..n {
-            assert_eq!(regex.evaluate_predicate(&*data[i]), TriStateBool::Null);
+        for r in &data[t..n] {
+            assert_eq!(regex.evaluate_predicate(&**r), TriStateBool::Null);
         }
-        for i in n..data.len() {
-            assert_eq!(regex.evaluate_predicate(&*data[i]), TriStateBool::False);
+        for r in &data[n..] {
+            assert_eq!(regex.evaluate_predicate(&**r), TriStateBool::False);
         }
         let order: Vec<usize> = data
             .iter()
@@ -392,4 +796,78 @@ mod test {
             .collect();
         assert_eq!(order, vec![0, 1, 5, 4, 3, 2]);
     }
+
+    #[test]
+    fn test_eq_literal() {
+        let eq = EqFilter::new(
+            FilterInput::FieldRef(Box::new(FieldRefExpr::new(None, Some("23"), None))),
+            FilterInput::Literal(Literal::Str(b"bar".to_vec())),
+        );
+        let mut data = test_data();
+        let (t, n) = eq.filter(&mut data);
+        assert_eq!((t, n), (2, 4));
+        let num = EqFilter::new(
+            FilterInput::FieldRef(Box::new(FieldRefExpr::new(None, Some("0"), None))),
+            FilterInput::Literal(Literal::Number(3.0)),
+        );
+        assert_eq!(num.filter(&mut data).0, 1);
+        assert!(Literal::Number(7.0).equals(&Literal::Str(b" 7.0".to_vec())));
+    }
+
+    #[test]
+    fn test_compare() {
+        let field = || FilterInput::FieldRef(Box::new(FieldRefExpr::new(None, Some("0"), None)));
+        let mut data = test_data();
+        // field 0 holds the numbers 0 to 5
+        let gt = CompareFilter::new(
+            field(),
+            CompareOp::Gt,
+            FilterInput::Literal(Literal::Number(3.0)),
+        );
+        assert_eq!(gt.filter(&mut data), (2, 2));
+        let le = CompareFilter::new(
+            FilterInput::Literal(Literal::Str(b"10".to_vec())),
+            CompareOp::Le,
+            field(),
+        );
+        assert_eq!(le.filter(&mut data).0, 0);
+        // lexicographic
+        let lt = CompareFilter::new(
+            FilterInput::FieldRef(Box::new(FieldRefExpr::new(None, Some("23"), None))),
+            CompareOp::Lt,
+            FilterInput::Literal(Literal::Str(b"baz".to_vec())),
+        );
+        assert_eq!(lt.filter(&mut data), (2, 4));
+        assert_eq!(compare_values(b"9", b"10"), Ordering::Less);
+        assert_eq!(compare_values(b"9a", b"10"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_in() {
+        let mut data = test_data();
+        let field = |x| Box::new(FieldRefExpr::new(None, Some(x), None));
+        let strings = InFilter::new(
+            field("23"),
+            vec![Literal::Str(b"bar".to_vec()), Literal::Str(b"baz".to_vec())],
+        );
+        assert_eq!(strings.filter(&mut data), (2, 4));
+        let numbers = InFilter::new(
+            field("0"),
+            vec![
+                Literal::Number(-0.0),
+                Literal::Number(5.0),
+                Literal::Number(7.0),
+            ],
+        );
+        assert_eq!(numbers.filter(&mut data).0, 2);
+        let mut values = ValueSet::default();
+        values.insert(Literal::Number(5.0));
+        assert!(values.contains(b"05"));
+        assert!(!values.contains(b"x5"));
+        // filled in later, like the values of a subquery
+        let cell = Rc::new(OnceCell::new());
+        let later = InFilter::with_values(field("0"), cell.clone());
+        assert!(cell.set(values).is_ok());
+        assert_eq!(later.filter(&mut data).0, 1);
+    }
 }
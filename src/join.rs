@@ -0,0 +1,224 @@
+// An inner equi-join of the tables of the from clause with one more table.
+// The smaller side is loaded into memory and indexed by its key values, the
+// other side is streamed and looked up. Keys of repeatable fields have any
+// number of values, two records join if they have at least one value in
+// common, and every pair of records is joined once.
+use crate::field_expression::FieldExpression;
+use crate::ownedrecord::OwnedRecord;
+use crate::record::{Record, RecordField, RecordType};
+use crate::table::TableRef;
+use std::collections::HashMap;
+
+pub struct Join {
+    pub table: TableRef,
+    // the key of the tables of the from clause and the key of the joined table
+    pub left_key: Box<dyn FieldExpression>,
+    pub right_key: Box<dyn FieldExpression>,
+}
+
+// the total size of the files of a table, stdin has no size
+fn table_size(table: &TableRef) -> Option<u64> {
+    if table.is_stdin() {
+        return None;
+    }
+    let mut size = 0;
+    for path in table.paths().ok()? {
+        size += std::fs::metadata(path).ok()?.len();
+    }
+    Some(size)
+}
+
+// whether the left side is smaller and should be built, a side without a
+// size can only be streamed
+pub fn build_left(left: &[TableRef], right: &TableRef) -> bool {
+    let left_size: Option<u64> = left.iter().map(table_size).sum();
+    match (left_size, table_size(right)) {
+        (Some(l), Some(r)) => l < r,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
+// the records of both tables, the fields are those of the left record
+// followed by those of the right one
+pub struct JoinedRecord<'a> {
+    left: &'a dyn Record,
+    right: &'a dyn Record,
+}
+
+impl<'a> Record for JoinedRecord<'a> {
+    fn header(&self) -> &[u8] {
+        self.left.header()
+    }
+
+    fn record_type(&self) -> Option<RecordType> {
+        self.left.record_type()
+    }
+
+    fn field_iter(
+        &self,
+        field_type: Option<usize>,
+    ) -> Box<dyn Iterator<Item = RecordField<'_>> + '_> {
+        Box::new(
+            self.left
+                .field_iter(field_type)
+                .chain(self.right.field_iter(field_type)),
+        )
+    }
+
+    fn field_iter_vec(
+        &self,
+        field_types: &[usize],
+    ) -> Box<dyn Iterator<Item = RecordField<'_>> + '_> {
+        Box::new(
+            self.left
+                .field_iter_vec(field_types)
+                .chain(self.right.field_iter_vec(field_types)),
+        )
+    }
+
+    fn to_marc21(&self, writer: &mut dyn std::io::Write) -> std::io::Result<()> {
+        OwnedRecord::from_record(self).to_marc21(writer)
+    }
+
+    fn joined(&self, side: usize) -> Option<&dyn Record> {
+        match side {
+            0 => Some(self.left),
+            1 => Some(self.right),
+            _ => None,
+        }
+    }
+}
+
+pub struct HashJoin {
+    build_key: Box<dyn FieldExpression>,
+    probe_key: Box<dyn FieldExpression>,
+    build_left: bool,
+    records: Vec<OwnedRecord>,
+    // the records of the build side with each key value
+    index: HashMap<Vec<u8>, Vec<usize>>,
+}
+
+impl HashJoin {
+    pub fn new(join: Join, build_left: bool) -> HashJoin {
+        let (build_key, probe_key) = if build_left {
+            (join.left_key, join.right_key)
+        } else {
+            (join.right_key, join.left_key)
+        };
+        HashJoin {
+            build_key,
+            probe_key,
+            build_left,
+            records: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    // adds a record of the build side, records without key values never join
+    pub fn add(&mut self, record: &dyn Record) {
+        let mut values: Vec<&[u8]> = self.build_key.compute(record).map(|x| x.data).collect();
+        if values.is_empty() {
+            return;
+        }
+        values.sort_unstable();
+        values.dedup();
+        let i = self.records.len();
+        for value in values {
+            self.index.entry(value.to_vec()).or_default().push(i);
+        }
+        self.records.push(OwnedRecord::from_record(record));
+    }
+
+    // the joined records of a record of the probe side, in the order of the build side
+    pub fn probe<'a>(&'a self, record: &'a dyn Record) -> Vec<JoinedRecord<'a>> {
+        let mut matches: Vec<usize> = self
+            .probe_key
+            .compute(record)
+            .filter_map(|x| self.index.get(x.data))
+            .flatten()
+            .copied()
+            .collect();
+        matches.sort_unstable();
+        matches.dedup();
+        matches
+            .into_iter()
+            .map(|i| {
+                let built: &dyn Record = &self.records[i];
+                if self.build_left {
+                    JoinedRecord {
+                        left: built,
+                        right: record,
+                    }
+                } else {
+                    JoinedRecord {
+                        left: record,
+                        right: built,
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::field_expression::*;
+    use crate::join::*;
+    use crate::ownedrecord::test_record;
+
+    fn join(build_left: bool) -> HashJoin {
+        HashJoin::new(
+            Join {
+                table: TableRef::Name("gnd".to_string()),
+                left_key: Box::new(FieldRefExpr::new(None, Some("700"), Some("0"))),
+                right_key: Box::new(FieldRefExpr::new(None, Some("035"), Some("a"))),
+            },
+            build_left,
+        )
+    }
+
+    fn values(record: &dyn Record) -> Vec<String> {
+        record
+            .field_iter(None)
+            .map(|x| x.utf8_data().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_hash_join() {
+        let title = test_record(&[
+            (1, "t1"),
+            (700, "1 \x1faLu, Wei\x1f0(DE-588)1"),
+            (700, "1 \x1faChen, Gang\x1f0(DE-588)2\x1f0(DE-101)2"),
+            (700, "1 \x1faZhu, Xu\x1f0(DE-588)3"),
+        ]);
+        let authorities = [
+            test_record(&[(1, "g2"), (35, "  \x1fa(DE-588)2\x1fa(DE-101)2")]),
+            test_record(&[(1, "g1"), (35, "  \x1fa(DE-588)1")]),
+            test_record(&[(1, "g4")]),
+        ];
+        let mut hash_join = join(false);
+        for r in &authorities {
+            hash_join.add(r);
+        }
+        // g2 shares two values with the title but joins once
+        let joined = hash_join.probe(&title);
+        assert_eq!(joined.len(), 2);
+        assert_eq!(values(&joined[0])[0], "t1");
+        assert_eq!(values(&joined[0])[4], "g2");
+        assert_eq!(
+            values(joined[1].joined(1).unwrap()),
+            vec!["g1", "  \x1fa(DE-588)1"]
+        );
+
+        // the titles are built, the joined records still start with them
+        let mut hash_join = join(true);
+        hash_join.add(&title);
+        let joined = hash_join.probe(&authorities[0]);
+        assert_eq!(joined.len(), 1);
+        assert_eq!(values(joined[0].joined(0).unwrap())[0], "t1");
+        assert_eq!(values(joined[0].joined(1).unwrap())[0], "g2");
+        assert!(hash_join.probe(&authorities[2]).is_empty());
+    }
+}
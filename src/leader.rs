@@ -0,0 +1,229 @@
+// Typed values of the leader positions, see
+// https://www.loc.gov/marc/bibliographic/bdleader.html
+// The enums cover the codes of all MARC 21 formats, positions that are
+// undefined in a format are blank there. from_byte only knows the codes of
+// the standard, parsed records keep any other byte as Other.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeaderError {
+    pub position: usize,
+    pub value: u8,
+}
+
+impl LeaderError {
+    pub fn new(position: usize, value: u8) -> LeaderError {
+        LeaderError { position, value }
+    }
+}
+
+impl std::fmt::Display for LeaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid value '{}' at leader position {:02}",
+            self.value.escape_ascii(),
+            self.position
+        )
+    }
+}
+
+impl std::error::Error for LeaderError {}
+
+// 05
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordStatus {
+    IncreaseEncodingLevel,
+    Corrected,
+    Deleted,
+    New,
+    // authority only
+    Obsolete,
+    // bibliographic only
+    IncreaseFromPrepublication,
+    // authority only, deleted because the heading was split
+    Split,
+    // authority only, deleted because the heading was replaced
+    Replaced,
+    Other(u8),
+}
+
+impl RecordStatus {
+    pub fn from_byte(b: u8) -> Option<RecordStatus> {
+        match b {
+            b'a' => Some(RecordStatus::IncreaseEncodingLevel),
+            b'c' => Some(RecordStatus::Corrected),
+            b'd' => Some(RecordStatus::Deleted),
+            b'n' => Some(RecordStatus::New),
+            b'o' => Some(RecordStatus::Obsolete),
+            b'p' => Some(RecordStatus::IncreaseFromPrepublication),
+            b's' => Some(RecordStatus::Split),
+            b'x' => Some(RecordStatus::Replaced),
+            _ => None,
+        }
+    }
+}
+
+// 07, bibliographic only
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BibliographicLevel {
+    Unspecified,
+    MonographicComponentPart,
+    SerialComponentPart,
+    Collection,
+    Subunit,
+    IntegratingResource,
+    Monograph,
+    Serial,
+    Other(u8),
+}
+
+impl BibliographicLevel {
+    pub fn from_byte(b: u8) -> Option<BibliographicLevel> {
+        match b {
+            b' ' => Some(BibliographicLevel::Unspecified),
+            b'a' => Some(BibliographicLevel::MonographicComponentPart),
+            b'b' => Some(BibliographicLevel::SerialComponentPart),
+            b'c' => Some(BibliographicLevel::Collection),
+            b'd' => Some(BibliographicLevel::Subunit),
+            b'i' => Some(BibliographicLevel::IntegratingResource),
+            b'm' => Some(BibliographicLevel::Monograph),
+            b's' => Some(BibliographicLevel::Serial),
+            _ => None,
+        }
+    }
+}
+
+// 08, bibliographic only
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TypeOfControl {
+    Unspecified,
+    Archival,
+    Other(u8),
+}
+
+impl TypeOfControl {
+    pub fn from_byte(b: u8) -> Option<TypeOfControl> {
+        match b {
+            b' ' => Some(TypeOfControl::Unspecified),
+            b'a' => Some(TypeOfControl::Archival),
+            _ => None,
+        }
+    }
+}
+
+// 09
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CharacterCodingScheme {
+    Marc8,
+    Unicode,
+    Other(u8),
+}
+
+impl CharacterCodingScheme {
+    pub fn from_byte(b: u8) -> Option<CharacterCodingScheme> {
+        match b {
+            b' ' => Some(CharacterCodingScheme::Marc8),
+            b'a' => Some(CharacterCodingScheme::Unicode),
+            _ => None,
+        }
+    }
+}
+
+// 17, the digits are holdings levels in holdings records
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EncodingLevel {
+    Full,
+    FullNotExamined,
+    LessThanFullNotExamined,
+    Abbreviated,
+    Core,
+    Partial,
+    Minimal,
+    Prepublication,
+    // holdings only
+    MixedLevel,
+    // authority only
+    Complete,
+    // authority only
+    Incomplete,
+    Unknown,
+    NotApplicable,
+    Other(u8),
+}
+
+impl EncodingLevel {
+    pub fn from_byte(b: u8) -> Option<EncodingLevel> {
+        match b {
+            b' ' => Some(EncodingLevel::Full),
+            b'1' => Some(EncodingLevel::FullNotExamined),
+            b'2' => Some(EncodingLevel::LessThanFullNotExamined),
+            b'3' => Some(EncodingLevel::Abbreviated),
+            b'4' => Some(EncodingLevel::Core),
+            b'5' => Some(EncodingLevel::Partial),
+            b'7' => Some(EncodingLevel::Minimal),
+            b'8' => Some(EncodingLevel::Prepublication),
+            b'm' => Some(EncodingLevel::MixedLevel),
+            b'n' => Some(EncodingLevel::Complete),
+            b'o' => Some(EncodingLevel::Incomplete),
+            b'u' => Some(EncodingLevel::Unknown),
+            b'z' => Some(EncodingLevel::NotApplicable),
+            _ => None,
+        }
+    }
+}
+
+// 18, the punctuation policy in authority records
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DescriptiveCatalogingForm {
+    NonIsbd,
+    Aacr2,
+    IsbdPunctuationOmitted,
+    IsbdPunctuationIncluded,
+    NonIsbdPunctuationOmitted,
+    Unknown,
+    Other(u8),
+}
+
+impl DescriptiveCatalogingForm {
+    pub fn from_byte(b: u8) -> Option<DescriptiveCatalogingForm> {
+        match b {
+            b' ' => Some(DescriptiveCatalogingForm::NonIsbd),
+            b'a' => Some(DescriptiveCatalogingForm::Aacr2),
+            b'c' => Some(DescriptiveCatalogingForm::IsbdPunctuationOmitted),
+            b'i' => Some(DescriptiveCatalogingForm::IsbdPunctuationIncluded),
+            b'n' => Some(DescriptiveCatalogingForm::NonIsbdPunctuationOmitted),
+            b'u' => Some(DescriptiveCatalogingForm::Unknown),
+            _ => None,
+        }
+    }
+}
+
+// 19, bibliographic only
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MultipartResourceRecordLevel {
+    Unspecified,
+    Set,
+    PartWithIndependentTitle,
+    PartWithDependentTitle,
+    Other(u8),
+}
+
+impl MultipartResourceRecordLevel {
+    pub fn from_byte(b: u8) -> Option<MultipartResourceRecordLevel> {
+        match b {
+            b' ' => Some(MultipartResourceRecordLevel::Unspecified),
+            b'a' => Some(MultipartResourceRecordLevel::Set),
+            b'b' => Some(MultipartResourceRecordLevel::PartWithIndependentTitle),
+            b'c' => Some(MultipartResourceRecordLevel::PartWithDependentTitle),
+            _ => None,
+        }
+    }
+}
+
+// 20-23, the layout of the directory entries
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EntryMap {
+    pub length_of_field_length: usize,
+    pub starting_position_length: usize,
+    pub implementation_defined_length: usize,
+}
@@ -1,5 +1,5 @@
-use regex::Regex;
-use std::cmp::{max, min};
+use crate::error::QueryError;
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct ItemContext(pub usize);
 
@@ -8,6 +8,20 @@ pub enum Keyword {
     Select,
     FromKW,
     Where,
+    OrderBy,
+    Asc,
+    Desc,
+    Limit,
+    Offset,
+    GroupBy,
+    Having,
+    Distinct,
+    On,
+    As,
+    UnionAll,
+    Join,
+    Explain,
+    Analyze,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -16,6 +30,13 @@ pub enum InfixFn {
     And,
     MatchOp,
     EqOp,
+    NotEqOp,
+    LtOp,
+    LeOp,
+    GtOp,
+    GeOp,
+    // value in a list of literals or the lines of a file
+    In,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -30,14 +51,89 @@ pub enum LexItem<'a> {
     InfixFunction(InfixFn),
     Punctuation(Punctuation),
     Identifier(&'a str),
+    // a quoted string, it becomes a RegexStr after ~
+    StringLit(&'a str),
+    // signed, or bare digits where a value is expected, see value_position
+    NumberLit(&'a str),
     RegexStr(&'a str),
     FieldRef(Option<&'a str>, Option<&'a str>, Option<&'a str>),
+    // [start] or [start:end] or [start:], follows a field ref
+    Slice(usize, Option<usize>),
+}
+
+impl Keyword {
+    pub fn text(&self) -> &'static str {
+        match self {
+            Keyword::Select => "select",
+            Keyword::FromKW => "from",
+            Keyword::Where => "where",
+            Keyword::OrderBy => "order by",
+            Keyword::Asc => "asc",
+            Keyword::Desc => "desc",
+            Keyword::Limit => "limit",
+            Keyword::Offset => "offset",
+            Keyword::GroupBy => "group by",
+            Keyword::Having => "having",
+            Keyword::Distinct => "distinct",
+            Keyword::On => "on",
+            Keyword::As => "as",
+            Keyword::UnionAll => "union all",
+            Keyword::Join => "join",
+            Keyword::Explain => "explain",
+            Keyword::Analyze => "analyze",
+        }
+    }
+}
+
+impl InfixFn {
+    pub fn text(&self) -> &'static str {
+        match self {
+            InfixFn::Or => "or",
+            InfixFn::And => "and",
+            InfixFn::MatchOp => "~",
+            InfixFn::EqOp => "=",
+            InfixFn::NotEqOp => "!=",
+            InfixFn::LtOp => "<",
+            InfixFn::LeOp => "<=",
+            InfixFn::GtOp => ">",
+            InfixFn::GeOp => ">=",
+            InfixFn::In => "in",
+        }
+    }
+}
+
+// the token as it is written in a query, for error messages
+impl std::fmt::Display for LexItem<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexItem::KW(kw) => write!(f, "{}", kw.text()),
+            LexItem::InfixFunction(func) => write!(f, "{}", func.text()),
+            LexItem::Punctuation(Punctuation::Comma) => write!(f, ","),
+            LexItem::Punctuation(Punctuation::Paren) => write!(f, "a parenthesis"),
+            LexItem::Identifier(name) => write!(f, "{}", name),
+            LexItem::StringLit(s) | LexItem::RegexStr(s) => write!(f, "'{}'", s),
+            LexItem::NumberLit(n) => write!(f, "{}", n),
+            LexItem::FieldRef(record_type, field_type, subfield_type) => {
+                if let Some(record_type) = record_type {
+                    write!(f, "{}.", record_type)?;
+                }
+                write!(f, "{}", field_type.unwrap_or(""))?;
+                match subfield_type {
+                    Some(subfield_type) => write!(f, ".{}", subfield_type),
+                    None => Ok(()),
+                }
+            }
+            LexItem::Slice(start, Some(end)) => write!(f, "[{}:{}]", start, end),
+            LexItem::Slice(start, None) => write!(f, "[{}:]", start),
+        }
+    }
 }
 
+#[allow(clippy::result_unit_err)]
 pub fn extract_regex_str(input: &str) -> Result<(usize, &str), ()> {
     assert!(input.starts_with('\''));
     let mut escaped = false;
-    for (i, c) in input[1..].chars().enumerate() {
+    for (i, c) in input[1..].char_indices() {
         match c {
             '\\' => {
                 escaped = !escaped;
@@ -56,115 +152,367 @@ pub fn extract_regex_str(input: &str) -> Result<(usize, &str), ()> {
     Err(())
 }
 
-pub fn lex(input: &str) -> Result<Vec<(ItemContext, LexItem)>, String> {
-    // matching a set of regexes is not the most efficient way to do this
-    // but our users probably won't provide kilobytes of expr-code
-
-    // CAREFUL: the order matters (identifiers are a catch-all) and the /continue/ in the body also matters
-    let whitespace = Regex::new(r"^ *").unwrap();
-    let keyword_regexes: Vec<regex::Regex> = [r"^select", r"^from", r"^where"]
-        .iter()
-        .map(|x| Regex::new(x).unwrap())
-        .collect();
-    let infix_regexes: Vec<regex::Regex> = [r"^or", r"^and", r"^~", r"^="]
-        .iter()
-        .map(|x| Regex::new(x).unwrap())
-        .collect();
-    let punctuation_regexes: Vec<regex::Regex> = [r"^,", r"^[)(]"]
-        .iter()
-        .map(|x| Regex::new(x).unwrap())
-        .collect();
-    // we only use a regex to find the start of a regex
-    // and then manually extract until the first unescaped '
-    // I think you can write a regex to do the same thing, but it seems
-    // quite complicated.
-    let regexstr_regex = Regex::new(r"^'").unwrap();
-    // a field ref is a record type (opt.)
-    // followed by a field type
-    // followed by a subfield type (opt.)
-    let field_ref_regex = Regex::new(r"^(([a\*])\.)?([0-9]+|\*)\.?([a-z\*])?").unwrap();
-    let table_ref_regex = Regex::new(r"^[a-zA-Z0-9_-]+").unwrap();
-    let mut i = 0;
-    let mut result = Vec::new();
-    'outer: while i < input.len() {
-        let cur_i = i;
-        dbg!(&result);
-        if let Some(cap) = whitespace.captures(&input[i..]) {
-            i += cap.get(0).unwrap().end();
-        }
-        for (j, regex) in keyword_regexes.iter().enumerate() {
-            if let Some(cap) = regex.captures(&input[i..]) {
-                result.push((
-                    ItemContext(i),
-                    LexItem::KW([Keyword::Select, Keyword::FromKW, Keyword::Where][j].clone()),
-                ));
-                i += cap.get(0).unwrap().end();
-                continue 'outer;
+// the value of a string literal, \' and \\ stand for ' and \
+pub fn unescape_str(input: &str) -> String {
+    let mut result = String::with_capacity(input.len());
+    let mut escaped = false;
+    for c in input.chars() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '\'' | '\\' => {
+                result.push(c);
+                escaped = false;
+            }
+            _ => {
+                if escaped {
+                    result.push('\\');
+                }
+                result.push(c);
+                escaped = false;
             }
         }
-        for (j, regex) in infix_regexes.iter().enumerate() {
-            if let Some(cap) = regex.captures(&input[i..]) {
-                result.push((
-                    ItemContext(i),
-                    LexItem::InfixFunction(
-                        [InfixFn::Or, InfixFn::And, InfixFn::MatchOp, InfixFn::EqOp][j].clone(),
-                    ),
-                ));
-                i += cap.get(0).unwrap().end();
-                continue 'outer;
+    }
+    if escaped {
+        result.push('\\');
+    }
+    result
+}
+
+// the words that are keywords or operators, some of them are two words like
+// order by with whitespace in between. The functions of where are matched
+// by name later on, so they are spelled in lower case here.
+const WORDS: &[(&str, Option<&str>, LexItem<'static>)] = &[
+    ("explain", None, LexItem::KW(Keyword::Explain)),
+    ("analyze", None, LexItem::KW(Keyword::Analyze)),
+    ("select", None, LexItem::KW(Keyword::Select)),
+    ("from", None, LexItem::KW(Keyword::FromKW)),
+    ("where", None, LexItem::KW(Keyword::Where)),
+    ("order", Some("by"), LexItem::KW(Keyword::OrderBy)),
+    ("group", Some("by"), LexItem::KW(Keyword::GroupBy)),
+    ("having", None, LexItem::KW(Keyword::Having)),
+    ("distinct", None, LexItem::KW(Keyword::Distinct)),
+    ("on", None, LexItem::KW(Keyword::On)),
+    ("as", None, LexItem::KW(Keyword::As)),
+    ("union", Some("all"), LexItem::KW(Keyword::UnionAll)),
+    ("inner", Some("join"), LexItem::KW(Keyword::Join)),
+    ("join", None, LexItem::KW(Keyword::Join)),
+    ("asc", None, LexItem::KW(Keyword::Asc)),
+    ("desc", None, LexItem::KW(Keyword::Desc)),
+    ("limit", None, LexItem::KW(Keyword::Limit)),
+    ("offset", None, LexItem::KW(Keyword::Offset)),
+    ("or", None, LexItem::InfixFunction(InfixFn::Or)),
+    ("and", None, LexItem::InfixFunction(InfixFn::And)),
+    ("in", None, LexItem::InfixFunction(InfixFn::In)),
+    ("not", None, LexItem::Identifier("not")),
+    ("not_null", None, LexItem::Identifier("not_null")),
+    ("is_null", None, LexItem::Identifier("is_null")),
+    ("exists", None, LexItem::Identifier("exists")),
+    ("file", None, LexItem::Identifier("file")),
+];
+
+fn is_word_byte(x: u8) -> bool {
+    x.is_ascii_alphanumeric() || x == b'_'
+}
+
+fn word_len(input: &str) -> usize {
+    input.bytes().take_while(|x| is_word_byte(*x)).count()
+}
+
+fn digits_len(input: &str) -> usize {
+    input.bytes().take_while(u8::is_ascii_digit).count()
+}
+
+// a keyword or operator that is a whole word, in any case; a word followed
+// by a dot is the table alias of a field ref
+fn keyword(input: &str) -> Option<(LexItem<'static>, usize)> {
+    let len = word_len(input);
+    if input[len..].starts_with('.') {
+        return None;
+    }
+    let word = &input[..len];
+    for (first, second, item) in WORDS {
+        if !word.eq_ignore_ascii_case(first) {
+            continue;
+        }
+        match second {
+            None => return Some((item.clone(), len)),
+            Some(second) => {
+                let rest = &input[len..];
+                let next = rest.trim_start();
+                let space = rest.len() - next.len();
+                let next_len = word_len(next);
+                if space > 0 && next[..next_len].eq_ignore_ascii_case(second) {
+                    return Some((item.clone(), len + space + next_len));
+                }
+            }
+        }
+    }
+    None
+}
+
+// digits, * or the leader
+fn field_type(input: &str) -> Option<&str> {
+    let len = match digits_len(input) {
+        0 if input.starts_with('*') => 1,
+        0 if input.starts_with("leader") && word_len(input) == 6 => 6,
+        len => len,
+    };
+    if len > 0 {
+        Some(&input[..len])
+    } else {
+        None
+    }
+}
+
+// an indicator (245.ind2 or 245#2) or a subfield type (numeric codes need
+// the dot), both are optional
+fn subfield_type(input: &str) -> (Option<&str>, usize) {
+    let indicator = |x: &u8| if *x == b'1' { "ind1" } else { "ind2" };
+    match input.as_bytes() {
+        [b'.', b'i', b'n', b'd', x @ (b'1' | b'2'), ..] => (Some(indicator(x)), 5),
+        [b'#', x @ (b'1' | b'2'), ..] => (Some(indicator(x)), 2),
+        [b'.', x, ..] if x.is_ascii_lowercase() || x.is_ascii_digit() || *x == b'*' => {
+            (Some(&input[1..2]), 2)
+        }
+        [x, ..] if x.is_ascii_lowercase() || *x == b'*' => (Some(&input[..1]), 1),
+        _ => (None, 0),
+    }
+}
+
+// a field ref is a record type (opt., the leader/06 code) or a table alias
+// followed by a field type and a subfield type
+fn field_ref(input: &str) -> Option<(LexItem<'_>, usize)> {
+    let qualifier_len = match input.as_bytes().first() {
+        Some(b'*') => 1,
+        Some(x) if x.is_ascii_alphabetic() || *x == b'_' => word_len(input),
+        _ => 0,
+    };
+    // without a field type after the dot it is no qualifier
+    let (record_type, start) = match input[qualifier_len..].strip_prefix('.') {
+        Some(rest) if qualifier_len > 0 && field_type(rest).is_some() => {
+            (Some(&input[..qualifier_len]), qualifier_len + 1)
+        }
+        _ => (None, 0),
+    };
+    let field_type = field_type(&input[start..])?;
+    let end = start + field_type.len();
+    let (subfield_type, len) = subfield_type(&input[end..]);
+    Some((
+        LexItem::FieldRef(record_type, Some(field_type), subfield_type),
+        end + len,
+    ))
+}
+
+// [start], [start:end] or [start:], offset is where the input starts in the
+// query
+fn slice(input: &str, offset: usize) -> Result<(LexItem<'static>, usize), QueryError> {
+    let parse = |start: usize, len: usize| {
+        input[start..start + len].parse::<usize>().map_err(|e| {
+            QueryError::invalid(
+                &ItemContext(offset + start),
+                format!("invalid position: {}", e),
+            )
+        })
+    };
+    let invalid = || {
+        QueryError::invalid(
+            &ItemContext(offset),
+            "expected a slice like [6], [0:3] or [6:]".to_string(),
+        )
+    };
+    let start_len = digits_len(&input[1..]);
+    if start_len == 0 {
+        return Err(invalid());
+    }
+    let start = parse(1, start_len)?;
+    let mut i = 1 + start_len;
+    let end = if input[i..].starts_with(':') {
+        let end_len = digits_len(&input[i + 1..]);
+        let end = match end_len {
+            0 => None,
+            _ => Some(parse(i + 1, end_len)?),
+        };
+        i += 1 + end_len;
+        end
+    } else {
+        Some(start + 1)
+    };
+    if !input[i..].starts_with(']') {
+        return Err(invalid());
+    }
+    Ok((LexItem::Slice(start, end), i + 1))
+}
+
+// a sign, digits and optional decimals
+fn number_len(input: &str) -> usize {
+    let mut len = 1 + digits_len(&input[1..]);
+    if input[len..].starts_with('.') {
+        let decimals = digits_len(&input[len + 1..]);
+        if decimals > 0 {
+            len += 1 + decimals;
+        }
+    }
+    len
+}
+
+// table names and function names, -- starts a comment instead
+fn identifier_len(input: &str) -> usize {
+    let bytes = input.as_bytes();
+    let mut len = 0;
+    while len < bytes.len()
+        && (is_word_byte(bytes[len]) || bytes[len] == b'-' && bytes.get(len + 1) != Some(&b'-'))
+    {
+        len += 1;
+    }
+    len
+}
+
+fn infix(func: InfixFn, len: usize) -> Result<(LexItem<'static>, usize), QueryError> {
+    Ok((LexItem::InfixFunction(func), len))
+}
+
+// the token at the start of the input and its length, offset is where the
+// input starts in the query
+fn token(input: &str, offset: usize) -> Result<(LexItem<'_>, usize), QueryError> {
+    match input.as_bytes() {
+        [b'\'', ..] => match extract_regex_str(input) {
+            Ok((len, s)) => Ok((LexItem::StringLit(s), len)),
+            Err(()) => Err(QueryError::invalid(
+                &ItemContext(offset),
+                "the quote is never closed".to_string(),
+            )),
+        },
+        [b',', ..] => Ok((LexItem::Punctuation(Punctuation::Comma), 1)),
+        [b'(' | b')', ..] => Ok((LexItem::Punctuation(Punctuation::Paren), 1)),
+        [b'~', ..] => infix(InfixFn::MatchOp, 1),
+        [b'!', b'=', ..] => infix(InfixFn::NotEqOp, 2),
+        [b'=', ..] => infix(InfixFn::EqOp, 1),
+        [b'<', b'=', ..] => infix(InfixFn::LeOp, 2),
+        [b'<', ..] => infix(InfixFn::LtOp, 1),
+        [b'>', b'=', ..] => infix(InfixFn::GeOp, 2),
+        [b'>', ..] => infix(InfixFn::GtOp, 1),
+        [b'[', ..] => slice(input, offset),
+        [b'-' | b'+', x, ..] if x.is_ascii_digit() => {
+            let len = number_len(input);
+            Ok((LexItem::NumberLit(&input[..len]), len))
+        }
+        // identifiers are the catch-all
+        _ => {
+            if let Some(keyword) = keyword(input) {
+                return Ok(keyword);
+            }
+            if let Some(field_ref) = field_ref(input) {
+                return Ok(field_ref);
+            }
+            match identifier_len(input) {
+                0 => Err(QueryError::invalid(
+                    &ItemContext(offset),
+                    format!("unrecognized token {}", input.chars().next().unwrap()),
+                )),
+                len => Ok((LexItem::Identifier(&input[..len]), len)),
             }
         }
-        for (j, regex) in punctuation_regexes.iter().enumerate() {
-            if let Some(cap) = regex.captures(&input[i..]) {
-                result.push((
-                    ItemContext(i),
-                    LexItem::Punctuation([Punctuation::Comma, Punctuation::Paren][j].clone()),
-                ));
-                i += cap.get(0).unwrap().end();
-                continue 'outer;
+    }
+}
+
+// the position of the next token after whitespace and comments
+fn skip_blanks(input: &str, mut i: usize) -> Result<usize, QueryError> {
+    loop {
+        let rest = input[i..].trim_start();
+        i = input.len() - rest.len();
+        if rest.starts_with("--") {
+            i += rest.find('\n').unwrap_or(rest.len());
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            match comment.find("*/") {
+                Some(end) => i += end + 4,
+                None => {
+                    return Err(QueryError::invalid(
+                        &ItemContext(i),
+                        "the comment is never closed".to_string(),
+                    ))
+                }
             }
+        } else {
+            return Ok(i);
+        }
+    }
+}
+
+// what a parenthesis is opened for
+#[derive(Clone, Copy, PartialEq)]
+enum Opened {
+    // the arguments of a function
+    Call,
+    // the values of in
+    InList,
+    // an expression or a subquery
+    Group,
+}
+
+// a bare run of digits is a number where a value is expected: after limit
+// and offset, in the list of in and in the arguments of a function after the
+// first, which is what the function is applied to. Elsewhere it is a field
+// type, so 150 = 142 compares two fields; a number to compare with is signed
+// (+142) or quoted. A slice after the digits makes them a field everywhere.
+fn value_position(
+    input: &str,
+    previous: Option<&(ItemContext, LexItem)>,
+    opened: Option<&Opened>,
+) -> bool {
+    match previous {
+        Some((_, LexItem::KW(Keyword::Limit | Keyword::Offset))) => true,
+        Some((_, LexItem::Punctuation(Punctuation::Comma))) => {
+            matches!(opened, Some(Opened::Call | Opened::InList))
         }
-        if regexstr_regex.captures(&input[i..]).is_some() {
-            if let Ok((end, slice)) = extract_regex_str(&input[i..]) {
-                result.push((ItemContext(i), LexItem::RegexStr(slice)));
-                i += end;
-                continue 'outer;
-            } else {
-                return Err(format!("reached end of input while looking for matching ' for the ' at position {}, {}", i,
-                  &input[max(0, i)..min(i + 5, input.len())]));
+        Some((context, LexItem::Punctuation(Punctuation::Paren))) => {
+            input[context.0..].starts_with('(') && opened == Some(&Opened::InList)
+        }
+        _ => false,
+    }
+}
+
+// a single pass over the query; keywords are whole words in any case,
+// comments run from -- to the end of the line or from /* to */
+pub fn lex(input: &str) -> Result<Vec<(ItemContext, LexItem<'_>)>, QueryError> {
+    let mut result = Vec::new();
+    let mut opened = Vec::new();
+    let mut i = skip_blanks(input, 0)?;
+    while i < input.len() {
+        let (mut item, len) = token(&input[i..], i)?;
+        if let LexItem::FieldRef(None, Some(digits), None) = item {
+            if digits.bytes().all(|x| x.is_ascii_digit())
+                && !input[i + len..].trim_start().starts_with('[')
+                && value_position(input, result.last(), opened.last())
+            {
+                item = LexItem::NumberLit(digits);
             }
         }
-        if let Some(cap) = field_ref_regex.captures(&input[i..]) {
-            let record_type = cap.get(2).map(|x| x.as_str());
-            let field_type = cap.get(3).map(|x| x.as_str());
-            let subfield_type = cap.get(4).map(|x| x.as_str());
-            result.push((
-                ItemContext(i),
-                LexItem::FieldRef(record_type, field_type, subfield_type),
-            ));
-            i += cap.get(0).unwrap().end();
-            continue 'outer;
-        }
-        if let Some(cap) = table_ref_regex.captures(&input[i..]) {
-            result.push((
-                ItemContext(i),
-                LexItem::Identifier(cap.get(0).map(|x| x.as_str()).unwrap()),
-            ));
-            i += cap.get(0).unwrap().end();
-            continue 'outer;
-        }
-        if i <= cur_i {
-            // no regex matched or we coded a bug
-            return Err(format!(
-                "Unrecognized token at position {}, '{}'",
-                i,
-                &input[i..]
-            ));
+        match item {
+            LexItem::Punctuation(Punctuation::Paren) if input[i..].starts_with('(') => {
+                opened.push(match result.last() {
+                    Some((_, LexItem::Identifier(_))) => Opened::Call,
+                    Some((_, LexItem::InfixFunction(InfixFn::In))) => Opened::InList,
+                    _ => Opened::Group,
+                })
+            }
+            LexItem::Punctuation(Punctuation::Paren) => {
+                opened.pop();
+            }
+            // in (select ...) and exists (select ...)
+            LexItem::KW(Keyword::Select) => {
+                if let Some(x) = opened.last_mut() {
+                    *x = Opened::Group;
+                }
+            }
+            _ => {}
         }
+        result.push((ItemContext(i), item));
+        i = skip_blanks(input, i + len)?;
     }
     Ok(result)
 }
 
+#[cfg(test)]
 mod tests {
     use crate::lexer::*;
     #[test]
@@ -191,7 +539,6 @@ mod tests {
     fn test_tokenize() -> Result<(), ()> {
         let input1 = "  or  and  ~  'aoeu'a.123.b)()123.b123  select , from some_table where  =";
         let r1 = lex(input1);
-        dbg!(&r1);
         if let Ok(tokens) = r1 {
             assert_eq!(tokens.len(), 16);
             assert_eq!(
@@ -200,7 +547,7 @@ mod tests {
                     (ItemContext(2), LexItem::InfixFunction(InfixFn::Or)),
                     (ItemContext(6), LexItem::InfixFunction(InfixFn::And)),
                     (ItemContext(11), LexItem::InfixFunction(InfixFn::MatchOp)),
-                    (ItemContext(14), LexItem::RegexStr("aoeu")),
+                    (ItemContext(14), LexItem::StringLit("aoeu")),
                     (
                         ItemContext(20),
                         LexItem::FieldRef(Some("a"), Some("123"), Some("b"))
@@ -227,11 +574,231 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_tokenize_subfield() -> Result<(), String> {
+        let tokens = lex("700.0 035a 245.*")?;
+        assert_eq!(
+            tokens,
+            vec![
+                (
+                    ItemContext(0),
+                    LexItem::FieldRef(None, Some("700"), Some("0"))
+                ),
+                (
+                    ItemContext(6),
+                    LexItem::FieldRef(None, Some("035"), Some("a"))
+                ),
+                (
+                    ItemContext(11),
+                    LexItem::FieldRef(None, Some("245"), Some("*"))
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokenize_indicator() -> Result<(), String> {
+        let tokens = lex("245.ind2 856#1 245.i")?;
+        assert_eq!(
+            tokens,
+            vec![
+                (
+                    ItemContext(0),
+                    LexItem::FieldRef(None, Some("245"), Some("ind2"))
+                ),
+                (
+                    ItemContext(9),
+                    LexItem::FieldRef(None, Some("856"), Some("ind1"))
+                ),
+                (
+                    ItemContext(15),
+                    LexItem::FieldRef(None, Some("245"), Some("i"))
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokenize_slice() -> Result<(), String> {
+        let tokens = lex("008[35:38] leader[6] 245.a[2:]")?;
+        assert_eq!(
+            tokens,
+            vec![
+                (ItemContext(0), LexItem::FieldRef(None, Some("008"), None)),
+                (ItemContext(3), LexItem::Slice(35, Some(38))),
+                (
+                    ItemContext(11),
+                    LexItem::FieldRef(None, Some("leader"), None)
+                ),
+                (ItemContext(17), LexItem::Slice(6, Some(7))),
+                (
+                    ItemContext(21),
+                    LexItem::FieldRef(None, Some("245"), Some("a"))
+                ),
+                (ItemContext(26), LexItem::Slice(2, None)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokenize_literals() -> Result<(), String> {
+        let tokens = lex("150.a != 'Katze' -12 +3.5")?;
+        assert_eq!(
+            tokens,
+            vec![
+                (
+                    ItemContext(0),
+                    LexItem::FieldRef(None, Some("150"), Some("a"))
+                ),
+                (ItemContext(6), LexItem::InfixFunction(InfixFn::NotEqOp)),
+                (ItemContext(9), LexItem::StringLit("Katze")),
+                (ItemContext(17), LexItem::NumberLit("-12")),
+                (ItemContext(21), LexItem::NumberLit("+3.5")),
+            ]
+        );
+        let tokens = lex("<= >= < >")?;
+        assert_eq!(
+            tokens.into_iter().map(|x| x.1).collect::<Vec<_>>(),
+            vec![
+                LexItem::InfixFunction(InfixFn::LeOp),
+                LexItem::InfixFunction(InfixFn::GeOp),
+                LexItem::InfixFunction(InfixFn::LtOp),
+                LexItem::InfixFunction(InfixFn::GtOp),
+            ]
+        );
+        assert_eq!(unescape_str(r"it\'s a \\ \d"), r"it's a \ \d");
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokenize_order_by() -> Result<(), String> {
+        let tokens = lex("order by 001 desc, 150.a asc, or")?;
+        assert_eq!(
+            tokens.into_iter().map(|x| x.1).collect::<Vec<_>>(),
+            vec![
+                LexItem::KW(Keyword::OrderBy),
+                LexItem::FieldRef(None, Some("001"), None),
+                LexItem::KW(Keyword::Desc),
+                LexItem::Punctuation(Punctuation::Comma),
+                LexItem::FieldRef(None, Some("150"), Some("a")),
+                LexItem::KW(Keyword::Asc),
+                LexItem::Punctuation(Punctuation::Comma),
+                LexItem::InfixFunction(InfixFn::Or),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokenize_words() -> Result<(), String> {
+        let tokens = lex("SELECT 001 From orders Where andere = 'x' OR 001 IN ('Käse')")?;
+        assert_eq!(
+            tokens.into_iter().map(|x| x.1).collect::<Vec<_>>(),
+            vec![
+                LexItem::KW(Keyword::Select),
+                LexItem::FieldRef(None, Some("001"), None),
+                LexItem::KW(Keyword::FromKW),
+                LexItem::Identifier("orders"),
+                LexItem::KW(Keyword::Where),
+                LexItem::Identifier("andere"),
+                LexItem::InfixFunction(InfixFn::EqOp),
+                LexItem::StringLit("x"),
+                LexItem::InfixFunction(InfixFn::Or),
+                LexItem::FieldRef(None, Some("001"), None),
+                LexItem::InfixFunction(InfixFn::In),
+                LexItem::Punctuation(Punctuation::Paren),
+                LexItem::StringLit("Käse"),
+                LexItem::Punctuation(Punctuation::Paren),
+            ]
+        );
+        let tokens = lex("Group\n  By selection order inner Join on.001")?;
+        assert_eq!(
+            tokens.into_iter().map(|x| x.1).collect::<Vec<_>>(),
+            vec![
+                LexItem::KW(Keyword::GroupBy),
+                LexItem::Identifier("selection"),
+                LexItem::Identifier("order"),
+                LexItem::KW(Keyword::Join),
+                LexItem::FieldRef(Some("on"), Some("001"), None),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokenize_functions() -> Result<(), String> {
+        let tokens = lex("NOT(Not_Null(001)) and EXISTS (select 001 from t) or Upper(245)")?;
+        let names: Vec<&str> = tokens
+            .iter()
+            .filter_map(|x| match x.1 {
+                LexItem::Identifier(n) => Some(n),
+                _ => None,
+            })
+            .collect();
+        // the others are looked up ignoring case
+        assert_eq!(names, vec!["not", "not_null", "exists", "t", "Upper"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokenize_values() -> Result<(), String> {
+        let tokens = lex(
+            "select 700, substr(001, 2, 3) from t where 008[7:11] = 2019 and 001 in (1, 2) \
+             and 005 > 008[0:6] and 001 in (select 001, 245 from u)",
+        )?;
+        let numbers: Vec<&str> = tokens
+            .iter()
+            .filter_map(|x| match x.1 {
+                LexItem::NumberLit(n) => Some(n),
+                _ => None,
+            })
+            .collect();
+        // 2019 after = is a field type
+        assert_eq!(numbers, vec!["2", "3", "1", "2"]);
+        assert_eq!(
+            tokens.last().unwrap().1,
+            LexItem::Punctuation(Punctuation::Paren)
+        );
+        assert_eq!(
+            tokens[tokens.len() - 4].1,
+            LexItem::FieldRef(None, Some("245"), None)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokenize_comments() -> Result<(), String> {
+        let tokens = lex("select 001 -- the id\n/* all of them\n */from my-titles--x\n")?;
+        assert_eq!(
+            tokens,
+            vec![
+                (ItemContext(0), LexItem::KW(Keyword::Select)),
+                (ItemContext(7), LexItem::FieldRef(None, Some("001"), None)),
+                (ItemContext(39), LexItem::KW(Keyword::FromKW)),
+                (ItemContext(44), LexItem::Identifier("my-titles")),
+            ]
+        );
+        let e = lex("select /* 001").unwrap_err();
+        assert_eq!(e.to_string(), "the comment is never closed at 7");
+        let e = lex("select 'x").unwrap_err();
+        assert_eq!(e.to_string(), "the quote is never closed at 7");
+        let e = lex("008[3:x]").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "expected a slice like [6], [0:3] or [6:] at 3"
+        );
+        let e = lex("select 001 % 2").unwrap_err();
+        assert_eq!(e.to_string(), "unrecognized token % at 11");
+        Ok(())
+    }
+
     #[test]
     fn test_tokenize1() -> Result<(), ()> {
         let input1 = "a.123.b";
         let r1 = lex(input1);
-        dbg!(&r1);
         if let Ok(tokens) = r1 {
             assert_eq!(tokens.len(), 1);
             Ok(())
@@ -1,28 +1,40 @@
 #![allow(dead_code)]
 use std::env;
-use std::fs::File;
-use std::io::{Read, Seek};
+use std::io::Read;
+pub mod aggregate;
 pub mod compiler;
+pub mod distinct;
+pub mod error;
+pub mod explain;
 pub mod exprparse;
 pub mod field_expression;
 pub mod filter;
+pub mod join;
+pub mod leader;
 pub mod lexer;
 pub mod marcrecord;
+pub mod output;
 pub mod ownedrecord;
 pub mod parsedrecord;
 pub mod parser;
+pub mod profile;
 pub mod projection;
 pub mod record;
+pub mod scalar;
+pub mod sort;
+pub mod spill;
+pub mod table;
 pub mod util;
 
 //use filter::*;
 use marcrecord::MarcHeader;
 use marcrecord::MarcReader;
+use marcrecord::MarcRecordBatch;
 //use marcrecord::MarcRecord;
 //use parsedrecord::*;
 use record::*;
 
-fn get_header(data: &[u8]) -> MarcHeader {
+fn get_header(data: &[u8]) -> MarcHeader<'_> {
     MarcHeader {
         header: &data[0..24],
     }
@@ -35,58 +47,383 @@ pub fn print_record(r: &dyn Record) {
     }
 }
 
-fn find_table(table_name: &str) -> Result<MarcReader<File>, std::io::Error> {
-    let reader = File::open(format!("{}.mrc", table_name))?;
-    Ok(MarcReader::new(reader))
+// the table "stdin" reads records from standard input, so dumps can be piped
+// through zcat, ssh and friends
+fn find_table(
+    part: &table::TablePart,
+    lenient: bool,
+) -> Result<MarcReader<Box<dyn Read>>, std::io::Error> {
+    let reader: Box<dyn Read> = match part {
+        table::TablePart::Stdin => Box::new(std::io::stdin().lock()),
+        table::TablePart::File(path) => Box::new(std::fs::File::open(path)?),
+    };
+    if lenient {
+        Ok(MarcReader::lenient(reader))
+    } else {
+        Ok(MarcReader::new(reader))
+    }
+}
+
+// rows go to the sorter if there is an order by
+fn pass_on(
+    sorter: Option<&mut sort::ExternalSorter>,
+    emit: &mut dyn FnMut(&dyn Record) -> bool,
+    keys: sort::KeyValues,
+    record: &dyn Record,
+) -> std::io::Result<bool> {
+    match sorter {
+        Some(sorter) => sorter.push(keys, record).map(|_| true),
+        None => Ok(emit(record)),
+    }
+}
+
+// reads the next batch, explain analyze counts what was read
+fn read_batch<'s, T: Read>(
+    reader: &mut MarcReader<T>,
+    mem: &'s mut [u8],
+    stats: Option<&profile::ReaderStats>,
+) -> Result<Option<MarcRecordBatch<'s>>, std::io::Error> {
+    let start = std::time::Instant::now();
+    let position = reader.position();
+    let batch = reader.read_batch(mem)?;
+    if let Some(stats) = stats {
+        let records = batch.as_ref().map_or(0, |x| x.records.len());
+        stats.add(records, reader.position() - position, start.elapsed());
+    }
+    Ok(batch)
+}
+
+// opens the parts of the tables, like find_table
+type MakeReader<'a, T> = dyn Fn(&table::TablePart) -> Result<MarcReader<T>, std::io::Error> + 'a;
+
+// loads the records of the build side of a join
+fn build_join<T: Read>(
+    hash_join: &mut join::HashJoin,
+    tables: &[table::TableRef],
+    make_reader: &MakeReader<'_, T>,
+    mem: &mut [u8],
+    stats: Option<&profile::ReaderStats>,
+) -> Result<(), String> {
+    for table in tables {
+        for part in table.parts().map_err(|x| format!("{}: {}", table, x))? {
+            let mut reader = make_reader(&part).map_err(|x| format!("{}: {}", part, x))?;
+            while let Some(batch) =
+                read_batch(&mut reader, mem, stats).map_err(|x| format!("{}: {}", part, x))?
+            {
+                for r in &batch.records {
+                    hash_join.add(r);
+                }
+                for e in reader.take_errors() {
+                    eprintln!("{}: skipped malformed record: {}", part, e);
+                }
+            }
+        }
+    }
+    Ok(())
 }
 
-pub fn run_sql<T, H>(
-    sql_text: &str,
-    make_reader: fn(&str) -> Result<MarcReader<T>, std::io::Error>,
+// fills in the result of a subquery for the filters of the query
+fn run_subquery<T: Read>(
+    subquery: compiler::Subquery,
+    make_reader: &MakeReader<'_, T>,
+) -> Result<(), String> {
+    match subquery.result {
+        compiler::SubqueryResult::Values(cell) => {
+            let mut values = filter::ValueSet::default();
+            execute(subquery.query, make_reader, &mut |r: &dyn Record| {
+                for field in r.field_iter(None) {
+                    values.insert(filter::Literal::Str(field.data.to_vec()));
+                }
+            })?;
+            let _ = cell.set(values);
+        }
+        compiler::SubqueryResult::Exists(cell) => {
+            let mut found = false;
+            execute(subquery.query, make_reader, &mut |_: &dyn Record| {
+                found = true
+            })?;
+            let _ = cell.set(found);
+        }
+    }
+    Ok(())
+}
+
+pub fn run_sql<T, M, H>(sql_text: &str, make_reader: M, handle_record: H) -> Result<(), String>
+where
+    T: Read,
+    M: Fn(&table::TablePart) -> Result<MarcReader<T>, std::io::Error>,
+    H: FnMut(&dyn Record),
+{
+    run_query(compiler::compile(sql_text)?, make_reader, handle_record)
+}
+
+pub fn run_query<T, M, H>(
+    compile_result: compiler::CompilationResult,
+    make_reader: M,
     mut handle_record: H,
 ) -> Result<(), String>
 where
-    T: Seek + Read,
+    T: Read,
+    M: Fn(&table::TablePart) -> Result<MarcReader<T>, std::io::Error>,
     H: FnMut(&dyn Record),
 {
-    let mut compile_result = compiler::compile(sql_text)?;
-    let mut marc_reader = make_reader(&compile_result.table_name).unwrap();
-    let projection = compile_result.projection;
-    let filter = compile_result.filter_expr;
+    execute(compile_result, &make_reader, &mut handle_record)
+}
 
+fn execute<T: Read>(
+    compile_result: compiler::CompilationResult,
+    make_reader: &MakeReader<'_, T>,
+    handle_record: &mut dyn FnMut(&dyn Record),
+) -> Result<(), String> {
+    // uncorrelated subqueries run once, before the query
+    for subquery in compile_result.subqueries {
+        run_subquery(subquery, make_reader)?;
+    }
     let cap = 128 * 1024 * 1024;
     let mut mem: Vec<u8> = vec![0; cap];
+    let reader_stats = compile_result.reader_stats;
 
-    while let Some(batch) = marc_reader
-        .read_batch(mem.as_mut_slice())
-        .map_err(|x| format!("{}", x))?
-    {
-        let mut boxs: Vec<Box<dyn Record>> = batch
+    // the smaller side of a join is loaded first, the other one is read below
+    let mut tables = compile_result.tables;
+    let hash_join = match compile_result.join {
+        Some(join) => {
+            let build_left = join::build_left(&tables, &join.table);
+            let mut build_tables = vec![join.table.clone()];
+            if build_left {
+                std::mem::swap(&mut tables, &mut build_tables);
+            }
+            let mut hash_join = join::HashJoin::new(join, build_left);
+            build_join(
+                &mut hash_join,
+                &build_tables,
+                make_reader,
+                &mut mem,
+                reader_stats.as_deref(),
+            )?;
+            Some(hash_join)
+        }
+        None => None,
+    };
+    let mut tables = tables.iter();
+    let mut parts = Vec::new().into_iter();
+    let mut part = None;
+    let mut marc_reader = None;
+    let projection = compile_result.projection;
+    let filter = compile_result.filter_expr;
+    let mut aggregation = compile_result.aggregation;
+    let mut distinct = compile_result.distinct;
+    let mut sorter = if compile_result.order_by.is_empty() {
+        None
+    } else {
+        Some(sort::ExternalSorter::new(
+            compile_result.order_by,
+            sort::DEFAULT_MEMORY_BUDGET,
+        ))
+    };
+
+    // skips the offset and tells whether more records are wanted
+    let limit = compile_result.limit;
+    let offset = compile_result.offset;
+    let mut skipped = 0;
+    let mut emitted = 0;
+    let mut emit = |r: &dyn Record| -> bool {
+        if skipped < offset {
+            skipped += 1;
+        } else {
+            handle_record(r);
+            emitted += 1;
+        }
+        limit.is_none_or(|x| emitted < x)
+    };
+
+    let mut done = limit == Some(0);
+    while !done {
+        // the tables and their files are read one after the other
+        if marc_reader.is_none() {
+            part = parts.next();
+            match &part {
+                Some(p) => {
+                    marc_reader = Some(make_reader(p).map_err(|x| format!("{}: {}", p, x))?);
+                }
+                None => match tables.next() {
+                    Some(t) => {
+                        parts = t.parts().map_err(|x| format!("{}: {}", t, x))?.into_iter();
+                        continue;
+                    }
+                    None => break,
+                },
+            }
+        }
+        let part = part.as_ref().unwrap();
+        let reader = marc_reader.as_mut().unwrap();
+        let batch = match read_batch(reader, mem.as_mut_slice(), reader_stats.as_deref())
+            .map_err(|x| format!("{}: {}", part, x))?
+        {
+            Some(batch) => batch,
+            None => {
+                marc_reader = None;
+                continue;
+            }
+        };
+        for e in reader.take_errors() {
+            eprintln!("{}: skipped malformed record: {}", part, e);
+        }
+        let records: Vec<Box<dyn Record>> = batch
             .records
             .into_iter()
             .map(|x| -> Box<dyn Record> { Box::new(x) })
             .collect();
+        let probed;
+        let mut boxs: Vec<Box<dyn Record>> = match hash_join.as_ref() {
+            Some(hash_join) => {
+                probed = records;
+                probed
+                    .iter()
+                    .flat_map(|r| hash_join.probe(&**r))
+                    .map(|x| -> Box<dyn Record> { Box::new(x) })
+                    .collect()
+            }
+            None => records,
+        };
         let remaining = filter
             .as_ref()
             .map(|x| x.filter(&mut boxs).0)
             .unwrap_or(boxs.len());
+        if let Some(aggregation) = aggregation.as_mut() {
+            for r in &boxs[..remaining] {
+                aggregation.add(&**r);
+            }
+            continue;
+        }
+        // sort keys and distinct on are computed on the record before projection
+        let keys: Vec<sort::KeyValues> = match sorter.as_ref() {
+            Some(sorter) => boxs[..remaining]
+                .iter()
+                .map(|r| sorter.key_values(&**r))
+                .collect(),
+            None => vec![Vec::new(); remaining],
+        };
+        let distinct_keys: Vec<Option<distinct::RowKey>> = match distinct.as_ref() {
+            Some(distinct) => boxs[..remaining]
+                .iter()
+                .map(|r| distinct.source_key(&**r))
+                .collect(),
+            None => (0..remaining).map(|_| None).collect(),
+        };
         projection.project(&mut boxs[..remaining]);
-        for r in boxs.into_iter().take(remaining) {
-            //for r in batch.records {
-            handle_record(&*r);
-            //r.to_marc21(&mut stdout);
-            //stdout.write(b"\n");
+        let mut forward =
+            |k: sort::KeyValues, r: &dyn Record| pass_on(sorter.as_mut(), &mut emit, k, r);
+        for ((r, k), key) in boxs.into_iter().zip(keys).zip(distinct_keys) {
+            let more = match distinct.as_mut() {
+                Some(distinct) => {
+                    let key = key.unwrap_or_else(|| distinct::HashDistinct::record_key(&*r));
+                    distinct.insert(key, k, &*r, &mut forward)
+                }
+                None => forward(k, &*r),
+            }
+            .map_err(|x| format!("writing temporary file failed: {}", x))?;
+            if !more {
+                // stop reading, we have enough records
+                done = true;
+                break;
+            }
+        }
+    }
+    if let Some(aggregation) = aggregation {
+        let mut forward = |_: sort::KeyValues, r: &dyn Record| Ok(emit(r));
+        for row in aggregation.rows() {
+            let more = match distinct.as_mut() {
+                Some(distinct) => {
+                    let key = distinct::HashDistinct::record_key(&row);
+                    distinct.insert(key, Vec::new(), &row, &mut forward)
+                }
+                None => forward(Vec::new(), &row),
+            }
+            .map_err(|x| format!("writing temporary file failed: {}", x))?;
+            if !more {
+                done = true;
+                break;
+            }
         }
     }
+    if let Some(distinct) = distinct.filter(|_| !done) {
+        let mut forward =
+            |k: sort::KeyValues, r: &dyn Record| pass_on(sorter.as_mut(), &mut emit, k, r);
+        distinct
+            .finish(&mut forward)
+            .map_err(|x| format!("writing temporary file failed: {}", x))?;
+    }
+    if let Some(sorter) = sorter.filter(|_| !done) {
+        sorter
+            .finish(&mut emit)
+            .map_err(|x| format!("sorting failed: {}", x))?;
+    }
     Ok(())
 }
 
 fn main() -> Result<(), String> {
     let args: Vec<String> = env::args().collect();
-    run_sql(&args[1], find_table, |x: &dyn Record| {
-        print_record(x);
+    let lenient = args[1..].iter().any(|x| x == "--lenient");
+    let table = args[1..].iter().any(|x| x == "--table");
+    let json = args[1..].iter().any(|x| x == "--json");
+    let queries: Vec<&String> = args[1..].iter().filter(|x| !x.starts_with("--")).collect();
+    if queries.len() != 1 {
+        return Err(format!(
+            "usage: {} [--lenient] [--table|--json] '<query>'",
+            args[0]
+        ));
+    }
+    // query errors show the query with a caret under the offending token
+    let compile_result = match compiler::compile(queries[0]) {
+        Ok(compile_result) => compile_result,
+        Err(e) => {
+            eprintln!("{}", e.render(queries[0]));
+            std::process::exit(1);
+        }
+    };
+    let make_reader = |part: &table::TablePart| find_table(part, lenient);
+    if compile_result.analyze {
+        print!("{}", explain::analyze(compile_result, make_reader)?);
+        return Ok(());
+    }
+    if compile_result.explain.is_some() {
+        print!("{}", explain::plan(&compile_result));
+        return Ok(());
+    }
+    // the named columns of the select list, or the fields of the records
+    let stdout = std::io::BufWriter::new(std::io::stdout().lock());
+    let mut writer: Option<Box<dyn output::RowWriter>> = if json {
+        Some(Box::new(output::JsonWriter::new(
+            stdout,
+            &compile_result.columns,
+            &compile_result.count_columns,
+        )))
+    } else if table {
+        Some(Box::new(
+            output::TableWriter::new(stdout, &compile_result.columns)
+                .map_err(|x| format!("{}", x))?,
+        ))
+    } else {
+        None
+    };
+    let mut write_error = None;
+    run_query(compile_result, make_reader, |x: &dyn Record| {
+        match writer.as_mut() {
+            Some(writer) => {
+                if let Err(e) = writer.write_row(x) {
+                    write_error.get_or_insert(e);
+                }
+            }
+            None => print_record(x),
+        }
     })?;
-    Ok(())
+    if let Some(writer) = writer.as_mut() {
+        writer.finish().map_err(|x| format!("{}", x))?;
+    }
+    match write_error {
+        Some(e) => Err(format!("{}", e)),
+        None => Ok(()),
+    }
     //    let filename = &args[1];
     //    let filter_str = &args[2];
     //    //let reader = BufReader::new(File::open(filename).unwrap());
@@ -224,10 +561,14 @@ fn main() -> Result<(), String> {
 
 #[cfg(test)]
 mod test {
+    use crate::compiler;
     use crate::marcrecord::MarcReader;
+    use crate::output::{self, RowWriter};
     use crate::ownedrecord::*;
+    use crate::parsedrecord::ParsedRecord;
     use crate::record::*;
-    use crate::{print_record, run_sql};
+    use crate::table::TablePart;
+    use crate::{run_query, run_sql};
     use std::io::BufReader;
     use std::io::Cursor;
 
@@ -299,7 +640,7 @@ mod test {
 1203058578DE-10120200120180536.0cr||||||||||||200118s2019    gw |||||o|||| 00||||eng  7 2DE-101a1203058578  a1479-58767 a10.1186/s12967-019-2032-y2doi7 2urnaurn:nbn:de:101:1-2020011823361862943632  a(DE-599)DNB1203058578  a(OCoLC)1196655458  a1140bgercDE-101d9999  aeng  cXA-DE7481\\pa616.994qDE-101223kdnb7 82\\pa610qDE-101223sdnb  aR-RZ2lcc1 aZeng, Jiang-huieVerfasser4aut10aPrognosis of clear cell renal cell carcinoma (ccRCC) based on a six-lncRNA-based risk score: an investigation based on RNA-sequencing datacby Jiang-hui Zeng, Wei Lu, Liang Liang, Gang Chen, Hui-hua Lan, Xiu-Yun Liang, Xu Zhu  aOnline-Ressourcebonline resource.  aTextbtxt2rdacontent  aComputermedienbc2rdamedia  aOnline-Ressourcebcr2rdacarrier0 aOpen AccessfUnrestricted online accessuhttp://purl.org/coar/access_right/c_abf22star1 aArchivierung/Langzeitarchivierung gewährleistet5DE-1012pdager 0a(lcsh)Medicine. 0aBiomedicine, general. 0aMedicine/Public Health, general.1 aLu, WeieVerfasser4aut1 aLiang, LiangeVerfasser4aut1 aChen, GangeVerfasser4aut1 aLan, Hui-huaeVerfasser4aut1 aLiang, Xiu-YuneVerfasser4aut1 aZhu, XueVerfasser4aut2 aSpringerLink (Online service)eSonstige4oth187|||sgvolume:17gnumber:1gday:23gmonth:8gyear:2019gpages:1-20gdate:12.201908iEnthalten intJournal of translational medicinedLondon : BioMed Central, 2003-hOnline-Ressourceg17, Heft 1 (23.8.2019), 1-20, 12.2019w(DE-600)2118570-0w(DE-101)02505497Xx1479-5876  aDE-101aaDE-101b40uhttps://doi.org/10.1186/s12967-019-2032-yxResolving-System40uhttps://nbn-resolving.org/urn:nbn:de:101:1-2020011823361862943632xResolving-System 0uhttps://d-nb.info/1203058578/34xLangzeitarchivierung Nationalbibliothek4 uhttps://doi.org/10.1186/s12967-019-2032-y0 81\\paaepknc0,98426d20200119qDE-101uhttps://d-nb.info/provenance/plan#aepkn0 82\\paaepsgc0,99929d20200119qDE-101uhttps://d-nb.info/provenance/plan#aepsg".as_bytes();
 
     fn test_reader(
-        _: &str,
+        _: &TablePart,
     ) -> Result<MarcReader<BufReader<Cursor<&'static [u8]>>>, std::io::Error> {
         let c = Cursor::new(STR);
         let breader = BufReader::new(c);
@@ -435,6 +776,786 @@ mod test {
         assert_eq!(v.len(), 1);
         let num_fields: Vec<usize> = v.iter().map(|x| x.field_iter(None).count()).collect();
         assert_eq!(num_fields, vec![18]);
+        // function names are case-insensitive like keywords
+        assert_eq!(
+            query_rows("SELECT 001 FROM bla WHERE NOT (001 ~ '^0') AND NOT_NULL(001)")?,
+            vec!["1203058578"]
+        );
+        assert_eq!(
+            query_rows("select Upper(150.a) from bla where Is_Null(100)")?,
+            vec!["A 302 D"]
+        );
+        assert_eq!(query_rows("select COUNT(*) from bla")?, vec!["2"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_types() -> Result<(), String> {
+        let mut mem = vec![0; 10000];
+        let mut reader = test_reader(&TablePart::Stdin).map_err(|x| x.to_string())?;
+        let batch = reader
+            .read_batch(&mut mem)
+            .map_err(|x| x.to_string())?
+            .ok_or("no records")?;
+        let types: Vec<Option<RecordType>> =
+            batch.records.iter().map(|x| x.record_type()).collect();
+        assert_eq!(
+            types,
+            vec![
+                Some(RecordType::Authority),
+                Some(RecordType::LanguageMaterial)
+            ]
+        );
+        let parsed = ParsedRecord::new(&batch.records[1]).map_err(|x| x.to_string())?;
+        assert_eq!(parsed.record_type(), Some(RecordType::LanguageMaterial));
+        assert_eq!(parsed.field_iter(Some(700)).count(), 6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_type_qualifier() -> Result<(), String> {
+        let mut v: Vec<OwnedRecord> = Vec::new();
+        run_sql(
+            "select z.1, a.245 from bla where not_null(a.*)",
+            test_reader,
+            |r: &dyn Record| {
+                let mut or = OwnedRecord::new();
+                or.add_field_from_iter(&mut r.field_iter(None));
+                v.push(or);
+            },
+        )?;
+        let fields: Vec<Vec<usize>> = v.iter().map(|x| x.field_types.clone()).collect();
+        assert_eq!(fields, vec![vec![1]]);
+        // a. is short for authority records, language material is type_a.
+        v.clear();
+        run_sql(
+            "select z.1, type_a.245 from bla where not_null(type_a.*)",
+            test_reader,
+            |r: &dyn Record| {
+                let mut or = OwnedRecord::new();
+                or.add_field_from_iter(&mut r.field_iter(None));
+                v.push(or);
+            },
+        )?;
+        let fields: Vec<Vec<usize>> = v.iter().map(|x| x.field_types.clone()).collect();
+        assert_eq!(fields, vec![vec![245]]);
+        Ok(())
+    }
+
+    fn subfield_summary(r: &dyn Record) -> Vec<String> {
+        let mut result = Vec::new();
+        for f in r
+            .field_iter(None)
+            .filter(|f| [1, 100, 773].contains(&f.field_type))
+        {
+            let mut s = format!(
+                "{} {} {}",
+                f.field_type,
+                f.is_control_field(),
+                f.indicator1().map(|x| x as char).unwrap_or('-')
+            );
+            for (code, value) in f.subfields() {
+                s += &format!(" ${}{}", code as char, std::str::from_utf8(value).unwrap());
+            }
+            let ws: Vec<&[u8]> = f.subfields_by_code(b'w').collect();
+            s += &format!(" w:{} a:{:?}", ws.len(), f.subfield(b'a').map(|x| x.len()));
+            result.push(s);
+        }
+        result
+    }
+
+    #[test]
+    fn test_subfields() -> Result<(), String> {
+        let mut mem = vec![0; 10000];
+        let mut reader = test_reader(&TablePart::Stdin).map_err(|x| x.to_string())?;
+        let batch = reader
+            .read_batch(&mut mem)
+            .map_err(|x| x.to_string())?
+            .ok_or("no records")?;
+        let record = &batch.records[1];
+        let summary = subfield_summary(record);
+        assert_eq!(
+            summary[..2],
+            [
+                "1 true - w:0 a:None".to_string(),
+                "100 false 1 $aZeng, Jiang-hui $eVerfasser $4aut w:0 a:Some(15)".to_string()
+            ]
+        );
+        assert_eq!(summary.len(), 4);
+        assert!(summary[3].ends_with(" w:2 a:None"));
+        let parsed = ParsedRecord::new(record).map_err(|x| x.to_string())?;
+        assert_eq!(subfield_summary(&parsed), summary);
+        assert_eq!(subfield_summary(&record.to_owned()), summary);
+        Ok(())
+    }
+
+    #[test]
+    fn test_subfield_ref() -> Result<(), String> {
+        let mut v: Vec<Vec<String>> = Vec::new();
+        run_sql(
+            "select 700.a, 100.e from bla where 100.a ~ '^Zeng' and not_null(773.w)",
+            test_reader,
+            |r: &dyn Record| {
+                v.push(
+                    r.field_iter(None)
+                        .map(|x| x.utf8_data().to_string())
+                        .collect(),
+                );
+            },
+        )?;
+        assert_eq!(v.len(), 1);
+        assert_eq!(
+            v[0],
+            vec![
+                "Lu, Wei",
+                "Liang, Liang",
+                "Chen, Gang",
+                "Lan, Hui-hua",
+                "Liang, Xiu-Yun",
+                "Zhu, Xu",
+                "Verfasser"
+            ]
+        );
+        v.clear();
+        run_sql(
+            "select 035.a from bla where is_null(100.x) and 079.* ~ '^s$' and not_null(079.a)",
+            test_reader,
+            |r: &dyn Record| {
+                v.push(
+                    r.field_iter(None)
+                        .map(|x| x.utf8_data().to_string())
+                        .collect(),
+                );
+            },
+        )?;
+        assert_eq!(v, vec![vec!["(DE-101)040000028", "(DE-588)4000002-3"]]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_indicator_ref() -> Result<(), String> {
+        let mut v: Vec<Vec<String>> = Vec::new();
+        run_sql(
+            "select 245.ind1, 856#2 from bla where 653.ind2 ~ '0' and 245.ind1 = 700.ind1 and not_null(245)",
+            test_reader,
+            |r: &dyn Record| {
+                v.push(
+                    r.field_iter(None)
+                        .map(|x| x.utf8_data().to_string())
+                        .collect(),
+                );
+            },
+        )?;
+        assert_eq!(v, vec![vec!["1", "0", "0", "0", " "]]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_positions() -> Result<(), String> {
+        let mut v: Vec<Vec<String>> = Vec::new();
+        run_sql(
+            "select leader[6], 008[7:11], 008[35:38], 001[8:] from bla where leader[7] ~ 'a'",
+            test_reader,
+            |r: &dyn Record| {
+                v.push(
+                    r.field_iter(None)
+                        .map(|x| x.utf8_data().to_string())
+                        .collect(),
+                );
+            },
+        )?;
+        assert_eq!(v, vec![vec!["a", "2019", "eng", "78"]]);
+        // 583 has a combining diaeresis at bytes 42 and 43
+        v.clear();
+        run_sql(
+            "select 583[43:45], 583[40:43] from bla where not_null(583)",
+            test_reader,
+            |r: &dyn Record| {
+                v.push(
+                    r.field_iter(None)
+                        .map(|x| x.utf8_data().to_string())
+                        .collect(),
+                );
+            },
+        )?;
+        assert_eq!(v, vec![vec!["\u{308}h", "wa\u{308}"]]);
+        v.clear();
+        run_sql(
+            "select leader from bla where is_null(001[9:])",
+            test_reader,
+            |r: &dyn Record| {
+                v.push(
+                    r.field_iter(None)
+                        .map(|x| x.utf8_data().to_string())
+                        .collect(),
+                );
+            },
+        )?;
+        assert_eq!(v, vec![vec!["00827nz  a2200241nc 4500"]]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_literals() -> Result<(), String> {
+        assert_eq!(
+            query_columns("select 150.a from bla where 035.a = '(DE-588)4000002-3'")?,
+            vec![vec!["A 302 D"]]
+        );
+        // records without 100.e are neither equal nor unequal
+        assert_eq!(
+            query_columns("select 100.e from bla where 100.e != 'Autor'")?,
+            vec![vec!["Verfasser"]]
+        );
+        assert!(query_columns("select 100.e from bla where 100.e != 'Verfasser'")?.is_empty());
+        assert_eq!(
+            query_columns("select 008[7:11] from bla where 008[7:11] = +2019")?,
+            vec![vec!["2019"]]
+        );
+        assert!(query_columns("select 001 from bla where 008[7:11] = '+2019'")?.is_empty());
+        assert_eq!(
+            query_columns("select 001 from bla where 001 = +1203058578 or 001 = '040000028'")?,
+            vec![vec!["040000028"], vec!["1203058578"]]
+        );
+        assert_eq!(
+            query_columns("select 001 from bla where 008[7:11] > +2000 and 008[7:11] < +2100")?,
+            vec![vec!["1203058578"]]
+        );
+        // a bare number after a comparison is a field type like on the left
+        assert_eq!(
+            query_columns("select 001 from bla where 001 = 001")?,
+            vec![vec!["040000028"], vec!["1203058578"]]
+        );
+        assert!(query_columns("select 001 from bla where 001 = 1203058578")?.is_empty());
+        assert_eq!(
+            query_columns("select 001 from bla where 008[7:11] = 008[7:11] and 001 != 003")?,
+            vec![vec!["040000028"], vec!["1203058578"]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_ordering_comparisons() -> Result<(), String> {
+        // 005 is a number, so the comparison is numeric
+        assert_eq!(
+            query_columns(
+                "select 001 from bla where 005 > '20200101000000' and 008[7:11] >= +2019"
+            )?
+            .concat(),
+            vec!["1203058578"]
+        );
+        assert_eq!(
+            query_columns("select 001 from bla where 005 <= +20100106125650")?.concat(),
+            vec!["040000028"]
+        );
+        // '||az' is not a number, so the comparison is lexicographic
+        assert_eq!(
+            query_columns("select 001 from bla where 008[7:11] > 'A'")?.concat(),
+            vec!["040000028"]
+        );
+        // no 100.e in the authority record
+        assert!(query_columns("select 001 from bla where 100.e < 'Verfasser'")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_order_by() -> Result<(), String> {
+        assert_eq!(
+            query_columns("select 001 from bla order by 001")?.concat(),
+            vec!["040000028", "1203058578"]
+        );
+        assert_eq!(
+            query_columns("select 001 from bla order by 001 desc")?.concat(),
+            vec!["1203058578", "040000028"]
+        );
+        // the authority record has no 700, missing values sort last
+        assert_eq!(
+            query_columns("select 001 from bla order by 700.a, 001 desc")?.concat(),
+            vec!["1203058578", "040000028"]
+        );
+        assert_eq!(
+            query_columns("select 001 from bla where 001 ~ '^0' order by 005 desc")?.concat(),
+            vec!["040000028"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_limit() -> Result<(), String> {
+        assert_eq!(
+            query_columns("select 001 from bla limit 1")?.concat(),
+            vec!["040000028"]
+        );
+        assert_eq!(
+            query_columns("select 001 from bla limit +1")?.concat(),
+            vec!["040000028"]
+        );
+        assert_eq!(
+            query_columns("select 001 from bla limit 1 offset 1")?.concat(),
+            vec!["1203058578"]
+        );
+        assert!(query_columns("select 001 from bla limit 0")?.is_empty());
+        assert!(query_columns("select 001 from bla limit 5 offset 2")?.is_empty());
+        assert_eq!(
+            query_columns("select 001 from bla order by 001 desc limit 1")?.concat(),
+            vec!["1203058578"]
+        );
+        assert_eq!(
+            query_columns("select 001 from bla order by 001 desc limit 1 offset 1")?.concat(),
+            vec!["040000028"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_by() -> Result<(), String> {
+        assert_eq!(
+            query_columns("select leader[6], count(*) from bla group by leader[6]")?,
+            vec![vec!["z", "1"], vec!["a", "1"]]
+        );
+        assert_eq!(
+            query_columns(
+                "select count(*), count(700), count(distinct 700.4), min(001), max(001) from bla"
+            )?,
+            vec![vec!["2", "6", "1", "040000028", "1203058578"]]
+        );
+        // one group per distinct value of a repeated field, missing values first for desc
+        assert_eq!(
+            query_columns(
+                "select 700.4, count(*), count(700.4) from bla group by 700.4 order by 700.4 desc"
+            )?,
+            vec![vec!["1", "0"], vec!["aut", "1", "6"]]
+        );
+        assert_eq!(
+            query_columns("select count(*) from bla where 001 = 'nope'")?,
+            vec![vec!["0"]]
+        );
+        assert!(query_columns("select 001, count(*) from bla group by 003").is_err());
+        assert!(query_columns("select sum(001) from bla").is_err());
+        match compiler::compile("select count(distinct *) from bla") {
+            Ok(_) => panic!("count(distinct *) compiles"),
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "count(distinct *) is not supported, use count(distinct <field>) at 22"
+            ),
+        }
+        assert!(query_columns("select 001 from bla group by 001 order by 003").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_having() -> Result<(), String> {
+        assert_eq!(
+            query_columns("select 040.b, count(*) from bla group by 040.b having count(*) > +1")?,
+            vec![vec!["ger", "2"]]
+        );
+        assert!(
+            query_columns("select 040.b from bla group by 040.b having count(*) > +2")?.is_empty()
+        );
+        // the aggregate in having doesn't have to be selected
+        assert_eq!(
+            query_columns("select 040.b from bla group by 040.b having count(distinct 001) >= +2 and 040.b ~ '^g'")?,
+            vec![vec!["ger"]]
+        );
+        assert_eq!(
+            query_columns(
+                "select leader[6] from bla group by leader[6] having max(001) = '040000028'"
+            )?,
+            vec![vec!["z"]]
+        );
+        // operands are columns by their tokens, like in where
+        assert_eq!(
+            query_columns("select 001 from bla group by 001 having 001 = 001")?,
+            vec![vec!["040000028"], vec!["1203058578"]]
+        );
+        assert_eq!(
+            query_columns("select 001, count(*) from bla group by 001 having 001 = +1203058578")?,
+            vec![vec!["1203058578", "1"]]
+        );
+        assert!(query_columns("select 040.b from bla group by 040.b having 245.a > 1").is_err());
+        assert!(query_columns("select 001 from bla where count(*) > 1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_distinct() -> Result<(), String> {
+        // the test records twice, like overlapping delta files
+        type Twice = std::io::Chain<&'static [u8], &'static [u8]>;
+        let twice = |_: &TablePart| -> Result<MarcReader<Twice>, std::io::Error> {
+            Ok(MarcReader::new(std::io::Read::chain(STR, STR)))
+        };
+        let query = |sql: &str| query_fields(sql, twice, |x| x.utf8_data().to_string());
+        assert_eq!(query("select 001 from bla")?.len(), 4);
+        assert_eq!(
+            query("select distinct 001 from bla")?,
+            vec![vec!["040000028"], vec!["1203058578"]]
+        );
+        assert_eq!(query("select distinct 040.b from bla")?, vec![vec!["ger"]]);
+        assert_eq!(query("select distinct * from bla")?.len(), 2);
+        assert_eq!(
+            query("select distinct on (040.b) 001 from bla")?,
+            vec![vec!["040000028"]]
+        );
+        assert_eq!(
+            query("select distinct 001 from bla order by 001 desc limit 1")?,
+            vec![vec!["1203058578"]]
+        );
+        assert_eq!(
+            query("select distinct count(*) from bla group by leader[6]")?,
+            vec![vec!["2"]]
+        );
+        assert!(query("select distinct on (001) count(*) from bla").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_scalar_functions() -> Result<(), String> {
+        // every value with its field type
+        let query = |sql: &str| {
+            query_fields(sql, test_reader, |x| {
+                format!("{} {}", x.field_type, x.utf8_data())
+            })
+        };
+        // the gnd id without its prefix
+        assert_eq!(
+            query("select replace(035.a, '(DE-588)', '') from bla where 035.a ~ 'DE-588'")?,
+            vec![vec!["35 (DE-101)040000028", "35 4000002-3"]]
+        );
+        assert_eq!(
+            query("select regex_replace(035.a, '^\\(DE-588\\)(.*)$', '$1') from bla where 001 = '040000028'")?,
+            vec![vec!["35 (DE-101)040000028", "35 4000002-3"]]
+        );
+        assert_eq!(
+            query("select upper(lower(150.a)), length(150.a), substr(001, 3, 4), substr(001, +8) from bla where 150.a ~ 'A'")?,
+            vec![vec!["150 A 302 D", "150 7", "1 0000", "1 28"]]
+        );
+        assert_eq!(
+            query("select split(150.a, ' '), concat('<', 150.a, '>'), trim(' x ') from bla where 001 = '040000028'")?,
+            vec![vec!["150 A", "150 302", "150 D", "150 <A 302 D>", "0 x"]]
+        );
+        assert_eq!(
+            query("select coalesce(150.a, 100.a) from bla")?,
+            vec![vec!["150 A 302 D"], vec!["100 Zeng, Jiang-hui"]]
+        );
+        // the default is a number, not the leader
+        assert_eq!(
+            query("select coalesce(150.a, 0) from bla")?,
+            vec![vec!["150 A 302 D"], vec!["0 0"]]
+        );
+        assert!(query("select upper(150.a, 1) from bla").is_err());
+        assert!(query("select frobnicate(150.a) from bla").is_err());
+        assert!(query("select regex_replace(150.a, '(', '') from bla").is_err());
+        assert!(query("select upper(count(*)) from bla").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_aliases() -> Result<(), String> {
+        let compile_result = compiler::compile(
+            "select 001 as id, 150.a as 'the heading', count(*) from bla group by 001, 150.a order by 001",
+        )?;
+        assert_eq!(
+            compile_result.columns,
+            vec!["id", "the heading", "count(*)"]
+        );
+        let mut out = Vec::new();
+        let mut writer = output::JsonWriter::new(
+            &mut out,
+            &compile_result.columns,
+            &compile_result.count_columns,
+        );
+        run_query(compile_result, test_reader, |r: &dyn Record| {
+            writer.write_row(r).unwrap();
+        })?;
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"id\": \"040000028\", \"the heading\": \"A 302 D\", \"count(*)\": 1}\n\
+             {\"id\": \"1203058578\", \"the heading\": null, \"count(*)\": 1}\n"
+        );
+
+        let compile_result = compiler::compile(
+            "select 700.a as author, leader[6], 008[7:11] from bla limit 1 offset 1",
+        )?;
+        assert_eq!(
+            compile_result.columns,
+            vec!["author", "leader[6:7]", "008[7:11]"]
+        );
+        let mut out = Vec::new();
+        let mut writer = output::TableWriter::new(&mut out, &compile_result.columns).unwrap();
+        run_query(compile_result, test_reader, |r: &dyn Record| {
+            writer.write_row(r).unwrap();
+        })?;
+        let table = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "author\tleader[6:7]\t008[7:11]");
+        assert_eq!(
+            lines[1],
+            "Lu, Wei|Liang, Liang|Chen, Gang|Lan, Hui-hua|Liang, Xiu-Yun|Zhu, Xu\ta\t2019"
+        );
+        assert!(compiler::compile("select 001 as from bla").is_err());
+        Ok(())
+    }
+
+    // the rows of the query on make_reader, each as its fields written by field
+    fn query_fields<T, M>(
+        sql: &str,
+        make_reader: M,
+        field: fn(&RecordField) -> String,
+    ) -> Result<Vec<Vec<String>>, String>
+    where
+        T: std::io::Read,
+        M: Fn(&TablePart) -> Result<MarcReader<T>, std::io::Error>,
+    {
+        let mut rows = Vec::new();
+        run_sql(sql, make_reader, |r: &dyn Record| {
+            rows.push(r.field_iter(None).map(|x| field(&x)).collect());
+        })?;
+        Ok(rows)
+    }
+
+    // the values of the columns of every row
+    fn query_columns(sql: &str) -> Result<Vec<Vec<String>>, String> {
+        query_fields(sql, test_reader, |x| x.utf8_data().to_string())
+    }
+
+    fn query_rows(sql: &str) -> Result<Vec<String>, String> {
+        let compile_result = compiler::compile(sql)?;
+        let mut out = Vec::new();
+        let mut writer = output::TableWriter::new(&mut out, &compile_result.columns).unwrap();
+        run_query(compile_result, test_reader, |r: &dyn Record| {
+            writer.write_row(r).unwrap();
+        })?;
+        let table = String::from_utf8(out).unwrap();
+        Ok(table.lines().skip(1).map(|x| x.to_string()).collect())
+    }
+
+    #[test]
+    fn test_query_errors() {
+        let error = |sql: &str| match compiler::compile(sql) {
+            Ok(_) => panic!("{} compiles", sql),
+            Err(e) => e,
+        };
+        let e = error("select 001 frm titles");
+        assert_eq!(e.to_string(), "expected ',' or from, found frm at 11");
+        assert_eq!(
+            e.render("select 001 frm titles"),
+            "expected ',' or from, found frm at line 1, column 12:\n\
+             select 001 frm titles\n           ^"
+        );
+        assert_eq!(
+            error("select 001 from titles where").message(),
+            "expected a field ref, a literal, a function or '(', found the end of the query"
+        );
+        assert_eq!(
+            error("select 001 from titles where 245.a ~ '(x'").to_string(),
+            "invalid regex, unclosed group at 37"
+        );
+        assert_eq!(
+            error("select 001 from titles where 001 = '1' and 245").to_string(),
+            "expected a condition, found 245 at 43"
+        );
+        assert_eq!(
+            error("select 001 from titles where not(001)").to_string(),
+            "expected a condition, found 001 at 33"
+        );
+        assert_eq!(
+            error("select 001 from titles where 001").to_string(),
+            "expected a condition, found 001 at 29"
+        );
+        assert_eq!(
+            error("select 001 from titles where (001 = '1') = 002").to_string(),
+            "= can't compare a condition with a value at 41"
+        );
+        assert_eq!(
+            error("select 001 from titles where not_null(001, 002)").to_string(),
+            "not_null takes exactly one argument at 29"
+        );
+        assert_eq!(
+            error("select 001 from titles limit 99999999999999999999").message(),
+            "invalid number 99999999999999999999: number too large to fit in target type"
+        );
+        assert_eq!(
+            error("select foo.001 from titles").to_string(),
+            "unknown table alias or record type foo at 7"
+        );
+    }
+
+    #[test]
+    fn test_explain_analyze() -> Result<(), String> {
+        let query = compiler::compile(
+            "explain analyze select 001 from titles where 001 = 'x' or not(001 = 'x')",
+        )?;
+        let text = crate::explain::analyze(query, test_reader)?;
+        // the times vary, the counts don't
+        let lines: Vec<&str> = text
+            .lines()
+            .map(|x| x.split(", 0.").next().unwrap())
+            .collect();
+        assert!(lines.contains(&"reader (2 records"));
+        let filter = lines.iter().position(|x| *x == "filter:").unwrap();
+        assert_eq!(
+            lines[filter + 1..filter + 5],
+            [
+                "  OrFilter (evaluated 2, true 2, false 0, null 0",
+                "    EqFilter(001 = 'x') (evaluated 2, true 0, false 2, null 0",
+                "    NotFilter (evaluated 2, true 2, false 0, null 0",
+                "      EqFilter(001 = 'x') (evaluated 2, true 0, false 2, null 0",
+            ]
+        );
+        assert!(text.ends_with(" ms\n"));
+        assert!(lines.last().unwrap().starts_with("2 rows in "));
+        Ok(())
+    }
+
+    #[test]
+    fn test_join() -> Result<(), String> {
+        assert_eq!(
+            query_rows(
+                "select t.001, g.001, g.leader[6] from titles t join gnd g on t.001 = g.001"
+            )?,
+            vec!["040000028\t040000028\tz", "1203058578\t1203058578\ta"]
+        );
+        // the authority shares both 035.a values with itself and joins once
+        assert_eq!(
+            query_rows(
+                "select count(*) from titles t join gnd g on g.035.a = t.035.a \
+                 where t.leader[6] = 'z'"
+            )?,
+            vec!["1"]
+        );
+        assert_eq!(
+            query_rows("select titles.001 from titles join gnd on gnd.150.a = titles.150.a")?,
+            vec!["040000028"]
+        );
+        // * is every field of both records
+        assert_eq!(
+            query_rows("select * from titles t join gnd g on t.001 = g.001 limit 1")?[0]
+                .matches("040000028")
+                .count(),
+            4
+        );
+        for sql in [
+            "select 001 from titles t join gnd g on t.001 = g.001",
+            "select t.001 from titles t join gnd g on t.001 = t.001",
+            "select t.001 from titles t join gnd g on t.001 = h.001",
+            "select t.001 from titles t join gnd t on t.001 = t.001",
+            "select t.001 from titles t join gnd g on t.001 ~ 'x'",
+        ]
+        .iter()
+        {
+            assert!(compiler::compile(sql).is_err(), "{}", sql);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_in() -> Result<(), String> {
+        assert_eq!(
+            query_rows("select 001 from bla where 001 in ('x', '1203058578')")?,
+            vec!["1203058578"]
+        );
+        assert_eq!(
+            query_rows("select 001 from bla where 001 in (40000028, +7)")?,
+            vec!["040000028"]
+        );
+        let path = std::env::temp_dir().join(format!("marc21-ids-{}.txt", std::process::id()));
+        std::fs::write(&path, "(DE-588)4000002-3\r\n\nx\n").unwrap();
+        let sql = format!(
+            "select 001 from bla where 035.a in file('{}') or 001 in ('1203058578')",
+            path.display()
+        );
+        assert_eq!(query_rows(&sql)?, vec!["040000028", "1203058578"]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(compiler::compile(&sql).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_subqueries() -> Result<(), String> {
+        assert_eq!(
+            query_rows(
+                "select 001 from bla where 001 in (select 001 from gnd where leader[6] = 'z')"
+            )?,
+            vec!["040000028"]
+        );
+        assert_eq!(
+            query_rows(
+                "select 001 from bla where 001 in (select 001 from a \
+                 where 001 in (select 001 from b where leader[6] = 'a'))"
+            )?,
+            vec!["1203058578"]
+        );
+        assert!(query_rows(
+            "select 001 from bla where exists (select 001 from gnd where 001 = 'x')"
+        )?
+        .is_empty());
+        assert_eq!(
+            query_rows(
+                "select 001 from bla where not(exists (select 001 from gnd where 001 = 'x')) \
+                 and exists (select 001 from gnd)"
+            )?,
+            vec!["040000028", "1203058578"]
+        );
+        assert!(
+            compiler::compile("select 001 from bla where 001 in (select 001, 245 from gnd)")
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_union_all() -> Result<(), String> {
+        let dir = std::env::temp_dir().join(format!("marc21-union-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["title_1.mrc", "title_2.mrc", "gnd.mrc"].iter() {
+            std::fs::write(dir.join(name), STR).unwrap();
+        }
+        let make_reader = |part: &TablePart| crate::find_table(part, false);
+        let query = |sql: &str| {
+            query_fields(sql, make_reader, |x| x.utf8_data().to_string()).map(|x| x.concat())
+        };
+        let pattern = format!("{}/title_*.mrc", dir.display());
+        assert_eq!(
+            query(&format!("select 001 from '{}'", pattern))?,
+            vec!["040000028", "1203058578", "040000028", "1203058578"]
+        );
+        let gnd = format!("{}/gnd.mrc", dir.display());
+        assert_eq!(
+            query(&format!(
+                "select 001 from '{}' union all '{}' where leader[6] = 'z'",
+                pattern, gnd
+            ))?,
+            vec!["040000028", "040000028", "040000028"]
+        );
+        assert_eq!(
+            query(&format!(
+                "select 001 from '{}' union all '{}' limit 1 offset 4",
+                gnd, pattern
+            ))?,
+            vec!["040000028"]
+        );
+        let missing = format!("{}/missing_*.mrc", dir.display());
+        assert!(query(&format!("select 001 from '{}'", missing)).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+        Ok(())
+    }
+
+    // a record cut off at the end of a file isn't continued by the next one
+    #[test]
+    fn test_truncated_part() -> Result<(), String> {
+        let dir = std::env::temp_dir().join(format!("marc21-parts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("part_1.mrc"), &STR[..STR.len() - 10]).unwrap();
+        std::fs::write(dir.join("part_2.mrc"), STR).unwrap();
+        let sql = format!("select 001 from '{}/part_*.mrc'", dir.display());
+        let query = |lenient: bool| {
+            let make_reader = |part: &TablePart| crate::find_table(part, lenient);
+            query_fields(&sql, make_reader, |x| x.utf8_data().to_string()).map(|x| x.concat())
+        };
+        assert_eq!(query(true)?, vec!["040000028", "040000028", "1203058578"]);
+        let e = query(false).unwrap_err();
+        assert!(e.starts_with(&format!("{}: ", dir.join("part_1.mrc").display())));
+        assert!(e.contains("truncated record"));
+        std::fs::remove_dir_all(&dir).unwrap();
         Ok(())
     }
 }
@@ -1,8 +1,7 @@
+use crate::leader::*;
 use crate::util::*;
 use memchr::memchr;
 use std::io::Read;
-use std::io::Seek;
-use std::io::SeekFrom;
 
 use crate::ownedrecord::OwnedRecord;
 use crate::record::*;
@@ -24,7 +23,7 @@ pub struct MarcRecordEntries<'s> {
 }
 
 impl<'s> MarcHeader<'s> {
-    pub fn new(data: &'s [u8]) -> MarcHeader {
+    pub fn new(data: &'s [u8]) -> MarcHeader<'s> {
         assert!(data.len() == 24);
         MarcHeader { header: data }
     }
@@ -32,11 +31,73 @@ impl<'s> MarcHeader<'s> {
     pub fn record_length(&self) -> usize {
         parse_usize5(&self.header[0..5])
     }
-    pub fn record_type(&self) -> RecordType {
-        match self.header[6] {
-            b'z' => RecordType::Authority,
-            _ => todo!(),
+
+    fn code<T>(&self, position: usize, from_byte: fn(u8) -> Option<T>) -> Result<T, LeaderError> {
+        let b = self.header[position];
+        from_byte(b).ok_or_else(|| LeaderError::new(position, b))
+    }
+
+    fn number(&self, start: usize, end: usize) -> Result<usize, LeaderError> {
+        for position in start..end {
+            if !self.header[position].is_ascii_digit() {
+                return Err(LeaderError::new(position, self.header[position]));
+            }
         }
+        Ok(parse_usize(&self.header[start..end]))
+    }
+
+    pub fn record_status(&self) -> Result<RecordStatus, LeaderError> {
+        self.code(5, RecordStatus::from_byte)
+    }
+
+    pub fn record_type(&self) -> Result<RecordType, LeaderError> {
+        self.code(6, RecordType::from_byte)
+    }
+
+    pub fn bibliographic_level(&self) -> Result<BibliographicLevel, LeaderError> {
+        self.code(7, BibliographicLevel::from_byte)
+    }
+
+    pub fn type_of_control(&self) -> Result<TypeOfControl, LeaderError> {
+        self.code(8, TypeOfControl::from_byte)
+    }
+
+    pub fn character_coding_scheme(&self) -> Result<CharacterCodingScheme, LeaderError> {
+        self.code(9, CharacterCodingScheme::from_byte)
+    }
+
+    pub fn indicator_count(&self) -> Result<usize, LeaderError> {
+        self.number(10, 11)
+    }
+
+    pub fn subfield_code_count(&self) -> Result<usize, LeaderError> {
+        self.number(11, 12)
+    }
+
+    pub fn base_address_of_data(&self) -> Result<usize, LeaderError> {
+        self.number(12, 17)
+    }
+
+    pub fn encoding_level(&self) -> Result<EncodingLevel, LeaderError> {
+        self.code(17, EncodingLevel::from_byte)
+    }
+
+    pub fn descriptive_cataloging_form(&self) -> Result<DescriptiveCatalogingForm, LeaderError> {
+        self.code(18, DescriptiveCatalogingForm::from_byte)
+    }
+
+    pub fn multipart_resource_record_level(
+        &self,
+    ) -> Result<MultipartResourceRecordLevel, LeaderError> {
+        self.code(19, MultipartResourceRecordLevel::from_byte)
+    }
+
+    pub fn entry_map(&self) -> Result<EntryMap, LeaderError> {
+        Ok(EntryMap {
+            length_of_field_length: self.number(20, 21)?,
+            starting_position_length: self.number(21, 22)?,
+            implementation_defined_length: self.number(22, 23)?,
+        })
     }
 }
 
@@ -57,13 +118,16 @@ impl<'s> MarcDirectoryEntryRef<'s> {
     pub fn len(&self) -> usize {
         parse_usize4(&self.entry[3..7])
     }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
     pub fn start(&self) -> usize {
         parse_usize5(&self.entry[7..12])
     }
 }
 
 impl<'s> MarcDirectory<'s> {
-    pub fn get_entry(&self, i: usize) -> MarcDirectoryEntryRef {
+    pub fn get_entry(&self, i: usize) -> MarcDirectoryEntryRef<'_> {
         MarcDirectoryEntryRef {
             entry: &self.directory[12 * i..12 * (i + 1)],
         }
@@ -81,16 +145,9 @@ fn end_of_entry_position(data: &[u8]) -> Option<usize> {
     memchr(b'\x1e', data)
 }
 
-fn end_of_subfield_position(data: &[u8]) -> Option<usize> {
-    data.iter().position(|&x| x == b'\x1f')
-}
-
 impl<'s> MarcRecord<'s> {
     pub fn new(h: MarcHeader<'s>, data: &'s [u8]) -> MarcRecord<'s> {
-        MarcRecord {
-            header: h,
-            data: &data,
-        }
+        MarcRecord { header: h, data }
     }
 
     pub fn header(&self) -> &MarcHeader<'s> {
@@ -98,14 +155,14 @@ impl<'s> MarcRecord<'s> {
     }
 
     pub fn data(&self) -> &[u8] {
-        &self.data
+        self.data
     }
 
     pub fn record_length(&self) -> usize {
         self.data.len() + 24
     }
     pub fn directory(&self) -> MarcDirectory<'s> {
-        let directory_end = end_of_entry_position(&self.data);
+        let directory_end = end_of_entry_position(self.data);
         MarcDirectory {
             directory: &self.data[0..directory_end.expect("malformed entry")],
         }
@@ -157,7 +214,7 @@ impl<'s> Iterator for MarcRecordFieldIter<'s> {
             let entry_ref = self.entries.directory.get_entry(self.idx);
             self.idx += 1;
             let entry_type = entry_ref.entry_type();
-            if self.field_type.map_or(true, |t| t == entry_type) {
+            if self.field_type.is_none_or(|t| t == entry_type) {
                 // +1 because we want to skip the field separator
                 let start = entry_ref.start() + 1;
                 return Some(RecordField {
@@ -209,15 +266,24 @@ impl<'s> Iterator for MarcRecordFieldIterVec<'s> {
 }
 
 impl<'s> Record for MarcRecord<'s> {
-    fn record_type(&self) -> RecordType {
-        self.header().record_type()
+    fn header(&self) -> &[u8] {
+        self.header.header
     }
-    fn field_iter(&self, field_type: Option<usize>) -> Box<dyn Iterator<Item = RecordField> + '_> {
-        Box::new(MarcRecordFieldIter::new(&self, field_type))
+    fn record_type(&self) -> Option<RecordType> {
+        self.header().record_type().ok()
+    }
+    fn field_iter(
+        &self,
+        field_type: Option<usize>,
+    ) -> Box<dyn Iterator<Item = RecordField<'_>> + '_> {
+        Box::new(MarcRecordFieldIter::new(self, field_type))
     }
 
-    fn field_iter_vec(&self, field_types: &[usize]) -> Box<dyn Iterator<Item = RecordField> + '_> {
-        Box::new(MarcRecordFieldIterVec::new(&self, field_types))
+    fn field_iter_vec(
+        &self,
+        field_types: &[usize],
+    ) -> Box<dyn Iterator<Item = RecordField<'_>> + '_> {
+        Box::new(MarcRecordFieldIterVec::new(self, field_types))
     }
 
     fn to_marc21(&self, writer: &mut dyn std::io::Write) -> std::io::Result<()> {
@@ -233,22 +299,186 @@ pub struct MarcRecordBatch<'s> {
     pub records: Vec<MarcRecord<'s>>,
 }
 
+// a record the reader could not make sense of
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordError {
+    // position of the start of the record in the input
+    pub offset: u64,
+    // number of records (good or bad) in front of this one
+    pub record_number: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "record {} at byte {}: {}",
+            self.record_number, self.offset, self.message
+        )
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+enum RecordCheck {
+    Complete(usize),
+    Incomplete,
+    Invalid(String),
+}
+
+// checks that the structure of a record is sound enough that iterating
+// over its fields can't go out of bounds
+pub fn validate_record(record: &[u8]) -> Result<(), String> {
+    if record.len() < 26 {
+        return Err(format!("record length {} is too short", record.len()));
+    }
+    if record[record.len() - 1] != b'\x1d' {
+        return Err("record does not end with a record terminator".to_string());
+    }
+    let data = &record[24..];
+    let directory_len = end_of_entry_position(data)
+        .ok_or_else(|| "directory does not end with a field terminator".to_string())?;
+    if directory_len % 12 != 0 {
+        return Err(format!(
+            "directory length {} is not a multiple of 12",
+            directory_len
+        ));
+    }
+    let directory = &data[..directory_len];
+    if !directory.iter().all(u8::is_ascii_digit) {
+        return Err("directory contains non-numeric entries".to_string());
+    }
+    let payload_len = data.len() - directory_len;
+    let directory = MarcDirectory { directory };
+    for i in 0..directory.num_entries() {
+        let entry = directory.get_entry(i);
+        if entry.is_empty() || entry.start() + entry.len() > payload_len {
+            return Err(format!(
+                "directory entry {} for field {:03} points outside of the record",
+                i,
+                entry.entry_type()
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn check_record(data: &[u8]) -> RecordCheck {
+    if data.len() < 24 {
+        return RecordCheck::Incomplete;
+    }
+    if !data[0..5].iter().all(u8::is_ascii_digit) {
+        return RecordCheck::Invalid("record length is not numeric".to_string());
+    }
+    let record_length = parse_usize5(&data[0..5]);
+    if record_length < 26 {
+        return RecordCheck::Invalid(format!("record length {} is too short", record_length));
+    }
+    if record_length > data.len() {
+        return RecordCheck::Incomplete;
+    }
+    match validate_record(&data[..record_length]) {
+        Ok(()) => RecordCheck::Complete(record_length),
+        Err(msg) => RecordCheck::Invalid(msg),
+    }
+}
+
 #[derive(Debug)]
 pub struct MarcReader<R>
 where
-    R: Read + Seek,
+    R: Read,
 {
     base_reader: R,
+    // the bytes of a record that was cut off at the end of the last batch,
+    // they are moved to the front of the next batch
+    carry: Vec<u8>,
+    eof: bool,
+    // skip malformed records instead of failing
+    lenient: bool,
+    errors: Vec<RecordError>,
+    // position of the first byte of the next batch in the input
+    offset: u64,
+    record_number: usize,
 }
 
 impl<R> MarcReader<R>
 where
-    R: Read + Seek,
+    R: Read,
 {
-    // TODO maybe take R instead of BufReader
     pub fn new(reader: R) -> MarcReader<R> {
         MarcReader {
             base_reader: reader,
+            carry: Vec::new(),
+            eof: false,
+            lenient: false,
+            errors: Vec::new(),
+            offset: 0,
+            record_number: 0,
+        }
+    }
+
+    // a reader that skips to the next record terminator when it finds a
+    // malformed record, the problems are collected in errors()
+    pub fn lenient(reader: R) -> MarcReader<R> {
+        let mut r = MarcReader::new(reader);
+        r.lenient = true;
+        r
+    }
+
+    pub fn errors(&self) -> &[RecordError] {
+        &self.errors
+    }
+
+    pub fn take_errors(&mut self) -> Vec<RecordError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    // the number of bytes of the input that were handed out or skipped
+    pub fn position(&self) -> u64 {
+        self.offset
+    }
+
+    // fill mem with the carried over bytes and as much fresh input as fits,
+    // returns the number of valid bytes in mem
+    fn fill(&mut self, mem: &mut [u8]) -> Result<usize, std::io::Error> {
+        use std::io::{Error, ErrorKind};
+        let mut filled = self.carry.len();
+        if filled > mem.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "record is larger than the batch buffer",
+            ));
+        }
+        mem[..filled].copy_from_slice(&self.carry);
+        self.carry.clear();
+        // a single read on a pipe or socket usually returns much less than
+        // we asked for, so keep going until the buffer is full
+        while filled < mem.len() && !self.eof {
+            match self.base_reader.read(&mut mem[filled..]) {
+                Ok(0) => self.eof = true,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(filled)
+    }
+
+    // records a problem with the record starting at mem offset i, fails in strict mode
+    fn report(&mut self, i: usize, message: String) -> Result<(), std::io::Error> {
+        let error = RecordError {
+            offset: self.offset + i as u64,
+            record_number: self.record_number,
+            message,
+        };
+        self.record_number += 1;
+        if self.lenient {
+            self.errors.push(error);
+            Ok(())
+        } else {
+            use std::io::{Error, ErrorKind};
+            Err(Error::new(ErrorKind::InvalidData, error))
         }
     }
 
@@ -256,46 +486,58 @@ where
         &mut self,
         mem: &'s mut [u8],
     ) -> Result<Option<MarcRecordBatch<'s>>, std::io::Error> {
-        let mut records: Vec<MarcRecord> = Vec::with_capacity(mem.len() / 10000);
-        let mut i = 0;
-        let start_pos = self.base_reader.stream_position().unwrap();
-        let read = self.base_reader.read(mem)?;
-        if read == 0 {
+        let filled = self.fill(mem)?;
+        if filled == 0 {
             return Ok(None);
         }
-        while i + 24 < read {
-            let header = MarcHeader {
-                header: &mem[i..i + 24],
-            };
-            let record_length = header.record_length();
-            //assert!(record_length < mem.len());
-            if record_length + i <= read {
-                // still fits in mem
-                records.push(MarcRecord::new(header, &mem[i + 24..i + record_length]));
-                i += record_length;
-            } else {
-                break;
+        let mem: &'s [u8] = mem;
+        let mut records: Vec<MarcRecord> = Vec::with_capacity(mem.len() / 10000);
+        let mut i = 0;
+        while i < filled {
+            match check_record(&mem[i..filled]) {
+                RecordCheck::Complete(record_length) => {
+                    let header = MarcHeader {
+                        header: &mem[i..i + 24],
+                    };
+                    records.push(MarcRecord::new(header, &mem[i + 24..i + record_length]));
+                    self.record_number += 1;
+                    i += record_length;
+                }
+                RecordCheck::Incomplete => break,
+                RecordCheck::Invalid(msg) => {
+                    if !self.lenient && i > 0 {
+                        // hand out the good records first, fail on the next call
+                        break;
+                    }
+                    // resynchronise at the next record terminator
+                    match memchr(b'\x1d', &mem[i..filled]) {
+                        Some(end) => {
+                            self.report(i, msg)?;
+                            i += end + 1;
+                        }
+                        None if self.eof => {
+                            self.report(i, msg)?;
+                            i = filled;
+                        }
+                        None => break,
+                    }
+                }
             }
         }
-        if i == 0 {
-            use std::io::{Error, ErrorKind};
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                "failed to read a single record",
-            ));
+        if i < filled && (i == 0 || (self.eof && self.lenient)) {
+            // either the input ends in the middle of a record or a single
+            // record doesn't fit into mem
+            let msg = if self.eof {
+                "truncated record at the end of the input"
+            } else {
+                "failed to read a single record"
+            };
+            self.report(i, msg.to_string())?;
+            i = filled;
         }
-        // mem full, backpedal
-        //self.base_reader.seek_relative(-24);
-        // TODO seek_relative is unstable in my version of rust
-        self.base_reader
-            .seek(SeekFrom::Start(start_pos + i as u64))?;
-        //        let num_bytes = records
-        //            .iter()
-        //            .map(|r| r.header().record_length())
-        //            .sum::<usize>() as u64;
-        //        let stream_pos = self.base_reader.stream_position().unwrap();
-        //        let bytes_consumed = stream_pos - start_pos;
-        //        assert!(bytes_consumed == (num_bytes));
+        // mem full, keep the partial record for the next batch
+        self.carry.extend_from_slice(&mem[i..filled]);
+        self.offset += i as u64;
 
         Ok(Some(MarcRecordBatch { records }))
     }
@@ -303,6 +545,8 @@ where
 
 #[cfg(test)]
 mod tests {
+    use crate::leader::*;
+    use crate::marcrecord::MarcHeader;
     use crate::record::*;
     use crate::MarcReader;
     use std::io::BufReader;
@@ -334,8 +578,7 @@ mod tests {
         let c = Cursor::new(STR);
         let breader = BufReader::new(c);
         let mut mreader = MarcReader::new(breader);
-        let mut v: Vec<u8> = Vec::new();
-        v.resize(10000, 0);
+        let mut v: Vec<u8> = vec![0; 10000];
         let r = mreader.read_batch(&mut v);
         match r {
             Ok(Some(batch)) => {
@@ -364,8 +607,8 @@ mod tests {
                     assert_eq!(entry.start(), entry_starts[i], "i {}", i);
                 }
                 let mut it = record.field_iter(None);
-                let first = it.next().ok_or_else(|| "not enough elements")?;
-                let last = it.last().ok_or_else(|| "not enough elements")?;
+                let first = it.next().ok_or("not enough elements")?;
+                let last = it.last().ok_or("not enough elements")?;
                 assert_eq!(first.utf8_data(), "040000028");
                 assert_eq!(last.utf8_data(), "  SswdisaA 302 D0(DE-588c)4000002-3");
                 Ok(())
@@ -379,8 +622,7 @@ mod tests {
         let c = Cursor::new(STR);
         let breader = BufReader::new(c);
         let mut mreader = MarcReader::new(breader);
-        let mut v: Vec<u8> = Vec::new();
-        v.resize(10000, 0);
+        let mut v: Vec<u8> = vec![0; 10000];
         let r = mreader.read_batch(&mut v);
         match r {
             Ok(Some(batch)) => {
@@ -394,4 +636,148 @@ mod tests {
             _ => Err("something bad".to_string()),
         }
     }
+
+    // behaves like a pipe: not seekable and only a few bytes per read
+    struct Trickle<'s> {
+        data: &'s [u8],
+    }
+
+    impl<'s> std::io::Read for Trickle<'s> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = std::cmp::min(std::cmp::min(buf.len(), 7), self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn read_stream() -> Result<(), String> {
+        let data = [STR, STR, STR].concat();
+        let mut mreader = MarcReader::new(Trickle { data: &data });
+        // room for one and a bit records, so every batch carries a partial record
+        let mut v: Vec<u8> = vec![0; 1000];
+        let mut num_records = 0;
+        while let Some(batch) = mreader.read_batch(&mut v).map_err(|x| x.to_string())? {
+            assert_eq!(batch.records.len(), 1);
+            let mut result: Vec<u8> = Vec::new();
+            batch.records[0].to_marc21(&mut result).unwrap();
+            assert_eq!(result, STR);
+            num_records += 1;
+        }
+        assert_eq!(num_records, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn read_truncated() {
+        let mut mreader = MarcReader::new(Trickle {
+            data: &STR[..STR.len() - 10],
+        });
+        let mut v: Vec<u8> = vec![0; 10000];
+        assert!(mreader.read_batch(&mut v).is_err());
+    }
+
+    fn read_all<R: std::io::Read>(
+        mreader: &mut MarcReader<R>,
+        buffer_size: usize,
+    ) -> Result<usize, std::io::Error> {
+        let mut v: Vec<u8> = vec![0; buffer_size];
+        let mut num_records = 0;
+        while let Some(batch) = mreader.read_batch(&mut v)? {
+            num_records += batch.records.len();
+        }
+        Ok(num_records)
+    }
+
+    #[test]
+    fn read_lenient() -> Result<(), String> {
+        let mut bad_length = STR.to_vec();
+        bad_length[0..5].copy_from_slice(b"00800");
+        let mut bad_directory = STR.to_vec();
+        bad_directory[24 + 12 * 18] = b'x';
+        let data = [STR, &bad_length, b"junk", STR, &bad_directory, STR].concat();
+        for buffer_size in [1000, 10000] {
+            let mut mreader = MarcReader::lenient(Trickle { data: &data });
+            let num_records = read_all(&mut mreader, buffer_size).map_err(|x| x.to_string())?;
+            assert_eq!(num_records, 2);
+            let errors = mreader.take_errors();
+            let offsets: Vec<u64> = errors.iter().map(|x| x.offset).collect();
+            let record_numbers: Vec<usize> = errors.iter().map(|x| x.record_number).collect();
+            // the junk is skipped together with the record behind it
+            assert_eq!(offsets, vec![827, 2 * 827, 3 * 827 + 4]);
+            assert_eq!(record_numbers, vec![1, 2, 3]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn read_strict_malformed() {
+        let mut bad_length = STR.to_vec();
+        bad_length[0..5].copy_from_slice(b"0x827");
+        let data = [STR, &bad_length].concat();
+        let mut mreader = MarcReader::new(Trickle { data: &data });
+        let mut v: Vec<u8> = vec![0; 10000];
+        assert_eq!(
+            mreader.read_batch(&mut v).unwrap().unwrap().records.len(),
+            1
+        );
+        let e = mreader.read_batch(&mut v).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "record 1 at byte 827: record length is not numeric"
+        );
+    }
+
+    #[test]
+    fn decode_leader() -> Result<(), LeaderError> {
+        let header = MarcHeader::new(&STR[..24]);
+        assert_eq!(header.record_length(), 827);
+        assert_eq!(header.record_status()?, RecordStatus::New);
+        assert_eq!(header.record_type()?, RecordType::Authority);
+        assert_eq!(
+            header.bibliographic_level()?,
+            BibliographicLevel::Unspecified
+        );
+        assert_eq!(header.type_of_control()?, TypeOfControl::Unspecified);
+        assert_eq!(
+            header.character_coding_scheme()?,
+            CharacterCodingScheme::Unicode
+        );
+        assert_eq!(header.indicator_count()?, 2);
+        assert_eq!(header.subfield_code_count()?, 2);
+        assert_eq!(header.base_address_of_data()?, 241);
+        assert_eq!(header.encoding_level()?, EncodingLevel::Complete);
+        assert_eq!(
+            header.descriptive_cataloging_form()?,
+            DescriptiveCatalogingForm::IsbdPunctuationOmitted
+        );
+        assert_eq!(
+            header.multipart_resource_record_level()?,
+            MultipartResourceRecordLevel::Unspecified
+        );
+        assert_eq!(
+            header.entry_map()?,
+            EntryMap {
+                length_of_field_length: 4,
+                starting_position_length: 5,
+                implementation_defined_length: 0,
+            }
+        );
+
+        let mut broken = STR[..24].to_vec();
+        broken[5] = b'q';
+        broken[14] = b'x';
+        let header = MarcHeader::new(&broken);
+        assert_eq!(header.record_status(), Err(LeaderError::new(5, b'q')));
+        assert_eq!(
+            header.base_address_of_data(),
+            Err(LeaderError::new(14, b'x'))
+        );
+        assert_eq!(
+            header.record_status().unwrap_err().to_string(),
+            "invalid value 'q' at leader position 05"
+        );
+        Ok(())
+    }
 }
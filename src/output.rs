@@ -0,0 +1,226 @@
+// Writers for the rows of a query that use the column names.
+// A column can have any number of values, rows without column information
+// (records) have all their values in the first column.
+use crate::record::Record;
+use std::io::Write;
+
+pub trait RowWriter {
+    fn write_row(&mut self, row: &dyn Record) -> std::io::Result<()>;
+    fn finish(&mut self) -> std::io::Result<()>;
+}
+
+fn column_values(row: &dyn Record, n: usize) -> Vec<Vec<&[u8]>> {
+    let mut values = vec![Vec::new(); n];
+    let columns = row.columns();
+    for (i, field) in row.field_iter(None).enumerate() {
+        let column = columns.map_or(0, |x| x[i]);
+        if column < n {
+            values[column].push(field.data);
+        }
+    }
+    values
+}
+
+// tab separated values with a header line, the values of a column are
+// separated by |
+pub struct TableWriter<W: Write> {
+    writer: W,
+    columns: usize,
+}
+
+impl<W: Write> TableWriter<W> {
+    pub fn new(mut writer: W, names: &[String]) -> std::io::Result<TableWriter<W>> {
+        for (i, name) in names.iter().enumerate() {
+            if i > 0 {
+                writer.write_all(b"\t")?;
+            }
+            TableWriter::write_escaped(&mut writer, name.as_bytes())?;
+        }
+        writer.write_all(b"\n")?;
+        Ok(TableWriter {
+            writer,
+            columns: names.len(),
+        })
+    }
+
+    fn write_escaped(writer: &mut W, data: &[u8]) -> std::io::Result<()> {
+        for b in data {
+            match b {
+                b'\t' => writer.write_all(b"\\t")?,
+                b'\n' => writer.write_all(b"\\n")?,
+                b'\r' => writer.write_all(b"\\r")?,
+                b'\\' => writer.write_all(b"\\\\")?,
+                b'|' => writer.write_all(b"\\|")?,
+                _ => writer.write_all(&[*b])?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> RowWriter for TableWriter<W> {
+    fn write_row(&mut self, row: &dyn Record) -> std::io::Result<()> {
+        for (i, values) in column_values(row, self.columns).iter().enumerate() {
+            if i > 0 {
+                self.writer.write_all(b"\t")?;
+            }
+            for (j, value) in values.iter().enumerate() {
+                if j > 0 {
+                    self.writer.write_all(b"|")?;
+                }
+                TableWriter::write_escaped(&mut self.writer, value)?;
+            }
+        }
+        self.writer.write_all(b"\n")
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// one json object per line, a column is null without values, a string
+// with one value and an array of strings with more
+pub struct JsonWriter<W: Write> {
+    writer: W,
+    names: Vec<String>,
+    // whether the values of a column are numbers, counts are
+    numbers: Vec<bool>,
+}
+
+impl<W: Write> JsonWriter<W> {
+    pub fn new(writer: W, names: &[String], count_columns: &[usize]) -> JsonWriter<W> {
+        JsonWriter {
+            writer,
+            names: names.to_vec(),
+            numbers: (0..names.len())
+                .map(|i| count_columns.contains(&i))
+                .collect(),
+        }
+    }
+
+    fn write_value(writer: &mut W, number: bool, data: &[u8]) -> std::io::Result<()> {
+        if number {
+            writer.write_all(data)
+        } else {
+            JsonWriter::write_string(writer, data)
+        }
+    }
+
+    fn write_string(writer: &mut W, data: &[u8]) -> std::io::Result<()> {
+        writer.write_all(b"\"")?;
+        for c in String::from_utf8_lossy(data).chars() {
+            match c {
+                '"' => writer.write_all(b"\\\"")?,
+                '\\' => writer.write_all(b"\\\\")?,
+                '\n' => writer.write_all(b"\\n")?,
+                '\r' => writer.write_all(b"\\r")?,
+                '\t' => writer.write_all(b"\\t")?,
+                c if (c as u32) < 0x20 => write!(writer, "\\u{:04x}", c as u32)?,
+                c => write!(writer, "{}", c)?,
+            }
+        }
+        writer.write_all(b"\"")
+    }
+}
+
+impl<W: Write> RowWriter for JsonWriter<W> {
+    fn write_row(&mut self, row: &dyn Record) -> std::io::Result<()> {
+        self.writer.write_all(b"{")?;
+        let values = column_values(row, self.names.len());
+        for (i, (name, values)) in self.names.iter().zip(values).enumerate() {
+            if i > 0 {
+                self.writer.write_all(b", ")?;
+            }
+            JsonWriter::write_string(&mut self.writer, name.as_bytes())?;
+            self.writer.write_all(b": ")?;
+            match values.as_slice() {
+                [] => self.writer.write_all(b"null")?,
+                [value] => JsonWriter::write_value(&mut self.writer, self.numbers[i], value)?,
+                _ => {
+                    self.writer.write_all(b"[")?;
+                    for (j, value) in values.iter().enumerate() {
+                        if j > 0 {
+                            self.writer.write_all(b", ")?;
+                        }
+                        JsonWriter::write_value(&mut self.writer, self.numbers[i], value)?;
+                    }
+                    self.writer.write_all(b"]")?;
+                }
+            }
+        }
+        self.writer.write_all(b"}\n")
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::output::*;
+    use crate::ownedrecord::*;
+    use crate::record::*;
+
+    fn row() -> OwnedRecord {
+        let mut r = OwnedRecord::new();
+        for (column, data) in [(0, "040000028"), (2, "A\t\"302\""), (2, "D|E")].iter() {
+            r.add_field(OwnedRecordField {
+                field_type: 150,
+                data: data.as_bytes().to_vec(),
+            });
+            r.set_column(*column);
+        }
+        r
+    }
+
+    fn names() -> Vec<String> {
+        vec![
+            "id".to_string(),
+            "missing".to_string(),
+            "heading".to_string(),
+        ]
+    }
+
+    #[test]
+    fn test_table_writer() -> std::io::Result<()> {
+        let mut out = Vec::new();
+        let mut writer = TableWriter::new(&mut out, &names())?;
+        writer.write_row(&row())?;
+        writer.finish()?;
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "id\tmissing\theading\n040000028\t\tA\\t\"302\"|D\\|E\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_writer() -> std::io::Result<()> {
+        let mut out = Vec::new();
+        let mut writer = JsonWriter::new(&mut out, &names(), &[]);
+        writer.write_row(&row())?;
+        writer.finish()?;
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"id\": \"040000028\", \"missing\": null, \"heading\": [\"A\\t\\\"302\\\"\", \"D|E\"]}\n"
+        );
+
+        // counts are numbers
+        let mut count = OwnedRecord::new();
+        count.add_field(OwnedRecordField {
+            field_type: 0,
+            data: b"2".to_vec(),
+        });
+        count.set_column(1);
+        let mut out = Vec::new();
+        let mut writer = JsonWriter::new(&mut out, &names(), &[1]);
+        writer.write_row(&count)?;
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"id\": null, \"missing\": 2, \"heading\": null}\n"
+        );
+        Ok(())
+    }
+}
@@ -1,9 +1,18 @@
+use crate::marcrecord::MarcHeader;
 use crate::record::*;
 use crate::util::write_usize;
 pub struct OwnedRecord {
     pub header: [u8; 24],
     pub field_types: Vec<usize>,
     pub field_data: Vec<Vec<u8>>,
+    // empty, or the select column of every field for projected rows
+    pub columns: Vec<usize>,
+}
+
+impl Default for OwnedRecord {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl OwnedRecord {
@@ -14,9 +23,28 @@ impl OwnedRecord {
             ],
             field_types: Vec::new(),
             field_data: Vec::new(),
+            columns: Vec::new(),
         }
     }
 
+    // a copy of the header and the fields of any record
+    pub fn from_record(record: &dyn Record) -> OwnedRecord {
+        let mut result = OwnedRecord::new();
+        let header = record.header();
+        let n = std::cmp::min(header.len(), result.header.len());
+        result.header[..n].copy_from_slice(&header[..n]);
+        result.add_field_from_iter(&mut record.field_iter(None));
+        if let Some(columns) = record.columns() {
+            result.columns = columns.to_vec();
+        }
+        result
+    }
+
+    // the fields that don't have a column yet belong to this one
+    pub fn set_column(&mut self, column: usize) {
+        self.columns.resize(self.field_types.len(), column);
+    }
+
     pub fn add_field(&mut self, field: OwnedRecordField) {
         self.field_types.push(field.field_type);
         self.field_data.push(field.data);
@@ -53,7 +81,7 @@ impl<'s> Iterator for OwnedRecordFieldIter<'s> {
             let idx = self.i;
             self.i += 1;
             let field_type = self.record.field_types[idx];
-            if self.field_types.binary_search(&field_type).is_ok() || self.field_types.len() == 0 {
+            if self.field_types.binary_search(&field_type).is_ok() || self.field_types.is_empty() {
                 let field_data = &self.record.field_data[idx];
                 return Some(RecordField {
                     field_type,
@@ -66,18 +94,27 @@ impl<'s> Iterator for OwnedRecordFieldIter<'s> {
 }
 
 impl Record for OwnedRecord {
-    fn record_type(&self) -> RecordType {
-        todo!();
+    fn header(&self) -> &[u8] {
+        &self.header
+    }
+    fn record_type(&self) -> Option<RecordType> {
+        MarcHeader::new(&self.header).record_type().ok()
     }
-    fn field_iter_vec(&self, field_types: &[usize]) -> Box<dyn Iterator<Item = RecordField> + '_> {
+    fn field_iter_vec(
+        &self,
+        field_types: &[usize],
+    ) -> Box<dyn Iterator<Item = RecordField<'_>> + '_> {
         Box::new(OwnedRecordFieldIter {
             i: 0,
             field_types: field_types.to_vec(),
-            record: &self,
+            record: self,
         })
     }
 
-    fn field_iter(&self, field_types: Option<usize>) -> Box<dyn Iterator<Item = RecordField> + '_> {
+    fn field_iter(
+        &self,
+        field_types: Option<usize>,
+    ) -> Box<dyn Iterator<Item = RecordField<'_>> + '_> {
         // todo we probably don't want to alloc a vec here
         if let Some(x) = field_types {
             self.field_iter_vec(&[x])
@@ -85,6 +122,14 @@ impl Record for OwnedRecord {
             self.field_iter_vec(&Vec::new())
         }
     }
+    fn columns(&self) -> Option<&[usize]> {
+        if self.columns.is_empty() {
+            None
+        } else {
+            Some(&self.columns)
+        }
+    }
+
     fn to_marc21(&self, writer: &mut dyn std::io::Write) -> std::io::Result<()> {
         writer.write_all(&self.header)?;
         let prefix_length = 0; //self.header.len() + 12*self.field_types.len();
@@ -96,21 +141,33 @@ impl Record for OwnedRecord {
             write_usize(start, 5, writer)?;
             start += field_len;
         }
-        writer.write_all(&[b'\x1e'])?;
+        writer.write_all(b"\x1e")?;
         for field in self.field_data.iter() {
             writer.write_all(field.as_slice())?;
-            writer.write_all(&[b'\x1e'])?;
+            writer.write_all(b"\x1e")?;
         }
-        writer.write_all(&[b'\x1d'])?;
+        writer.write_all(b"\x1d")?;
         Ok(())
     }
 }
 
+// a record with the given fields, for the tests of the operators
+#[cfg(test)]
+pub fn test_record(fields: &[(usize, &str)]) -> OwnedRecord {
+    let mut r = OwnedRecord::new();
+    for (field_type, data) in fields {
+        r.add_field(OwnedRecordField {
+            field_type: *field_type,
+            data: data.as_bytes().to_vec(),
+        });
+    }
+    r
+}
+
 #[cfg(test)]
 mod tests {
     use crate::marcrecord::*;
     use crate::ownedrecord::*;
-    use crate::record::*;
     use crate::MarcReader;
     use std::io::BufReader;
     use std::io::Cursor;
@@ -140,8 +197,7 @@ mod tests {
         let c = Cursor::new(STR);
         let breader = BufReader::new(c);
         let mut mreader = MarcReader::new(breader);
-        let mut v: Vec<u8> = Vec::new();
-        v.resize(10000, 0);
+        let mut v: Vec<u8> = vec![0; 10000];
         let r = mreader.read_batch(&mut v);
         match r {
             Ok(Some(batch)) => {
@@ -1,70 +1,26 @@
 #![allow(dead_code)]
+use crate::leader::*;
 use crate::marcrecord::*;
 use crate::record::*;
 
-pub enum AuthorityRecordStatus {
-    IncreaseEncodingLevel = b'a' as isize,
-    Corrected = b'c' as isize,
-    Deleted = b'd' as isize,
-    New = b'n' as isize,
-    Obsolete = b'o' as isize,
-    Split = b's' as isize,
-    Replaced = b'x' as isize,
-}
-
-pub enum AuthorityRecordCharacterCodingScheme {
-    Marc8 = b'#' as isize,
-    Unicode = b'a' as isize,
-}
-
-pub struct AuthorityRecordMeta {
-    record_type: RecordType,
-    status: AuthorityRecordStatus,
-    character_coding_scheme: AuthorityRecordCharacterCodingScheme,
+// where the fields of a record are in its field data
+pub struct FieldIndex {
     // TODO we probably want to use an arena for these
     field_types: Vec<usize>,
     field_offsets: Vec<usize>,
     field_lengths: Vec<usize>,
 }
 
-// TODO we could implement a builder pattern to reuse things we already
-// parsed during pre-filtering
-impl AuthorityRecordMeta {
-    pub fn empty_new() -> AuthorityRecordMeta {
-        AuthorityRecordMeta {
-            record_type: RecordType::Authority,
-            status: AuthorityRecordStatus::New,
-            character_coding_scheme: AuthorityRecordCharacterCodingScheme::Unicode,
+impl FieldIndex {
+    pub fn empty_new() -> FieldIndex {
+        FieldIndex {
             field_types: Vec::new(),
             field_offsets: Vec::new(),
             field_lengths: Vec::new(),
         }
     }
 
-    pub fn new(r: &MarcRecord, dir: &MarcDirectory) -> AuthorityRecordMeta {
-        let t = r.header().record_type();
-        assert!(t == RecordType::Authority);
-
-        // todo check whether other record types than authority parse differently here
-        // and maybe move stuff to MarcHeader
-        let s = match r.header().header[5] {
-            b'a' => AuthorityRecordStatus::IncreaseEncodingLevel,
-            b'c' => AuthorityRecordStatus::Corrected,
-            b'd' => AuthorityRecordStatus::Deleted,
-            b'n' => AuthorityRecordStatus::New,
-            b'o' => AuthorityRecordStatus::Obsolete,
-            b's' => AuthorityRecordStatus::Split,
-            b'x' => AuthorityRecordStatus::Replaced,
-            _ => panic!("oopsie"),
-        };
-
-        let coding_scheme = match r.header().header[9] {
-            b'a' => AuthorityRecordCharacterCodingScheme::Unicode,
-            _ => unimplemented!(),
-        };
-
-        // todo the remaining fields of the header
-
+    pub fn new(dir: &MarcDirectory) -> FieldIndex {
         let dir_len = dir.num_entries();
 
         let mut field_types = Vec::with_capacity(dir_len);
@@ -78,15 +34,13 @@ impl AuthorityRecordMeta {
             field_lengths.push(entry.len() - 1);
         }
 
-        AuthorityRecordMeta {
-            record_type: t,
-            status: s,
-            character_coding_scheme: coding_scheme,
+        FieldIndex {
             field_types,
             field_offsets,
             field_lengths,
         }
     }
+
     pub fn num_fields(&self) -> usize {
         self.field_types.len()
     }
@@ -97,51 +51,233 @@ impl AuthorityRecordMeta {
         self.field_offsets.push(field_start);
         self.field_lengths.push(field_len);
     }
-    pub fn record_type(&self) -> RecordType {
-        self.record_type.clone()
+}
+
+// a leader position with a byte the standard doesn't know, the record is
+// still usable
+fn or_other<T>(value: Result<T, LeaderError>, other: fn(u8) -> T) -> T {
+    value.unwrap_or_else(|e| other(e.value))
+}
+
+pub struct AuthorityRecordMeta {
+    status: RecordStatus,
+    character_coding_scheme: CharacterCodingScheme,
+    encoding_level: EncodingLevel,
+    punctuation_policy: DescriptiveCatalogingForm,
+    fields: FieldIndex,
+}
+
+// TODO we could implement a builder pattern to reuse things we already
+// parsed during pre-filtering
+impl AuthorityRecordMeta {
+    pub fn empty_new() -> AuthorityRecordMeta {
+        AuthorityRecordMeta {
+            status: RecordStatus::New,
+            character_coding_scheme: CharacterCodingScheme::Unicode,
+            encoding_level: EncodingLevel::Complete,
+            punctuation_policy: DescriptiveCatalogingForm::NonIsbd,
+            fields: FieldIndex::empty_new(),
+        }
+    }
+
+    pub fn new(r: &MarcRecord, dir: &MarcDirectory) -> Result<AuthorityRecordMeta, LeaderError> {
+        let h = r.header();
+        Ok(AuthorityRecordMeta {
+            status: or_other(h.record_status(), RecordStatus::Other),
+            character_coding_scheme: or_other(
+                h.character_coding_scheme(),
+                CharacterCodingScheme::Other,
+            ),
+            encoding_level: or_other(h.encoding_level(), EncodingLevel::Other),
+            punctuation_policy: or_other(
+                h.descriptive_cataloging_form(),
+                DescriptiveCatalogingForm::Other,
+            ),
+            fields: FieldIndex::new(dir),
+        })
+    }
+}
+
+pub struct BibliographicRecordMeta {
+    record_type: RecordType,
+    status: RecordStatus,
+    bibliographic_level: BibliographicLevel,
+    type_of_control: TypeOfControl,
+    character_coding_scheme: CharacterCodingScheme,
+    encoding_level: EncodingLevel,
+    descriptive_cataloging_form: DescriptiveCatalogingForm,
+    multipart_resource_record_level: MultipartResourceRecordLevel,
+    fields: FieldIndex,
+}
+
+impl BibliographicRecordMeta {
+    pub fn empty_new(record_type: RecordType) -> BibliographicRecordMeta {
+        BibliographicRecordMeta {
+            record_type,
+            status: RecordStatus::New,
+            bibliographic_level: BibliographicLevel::Monograph,
+            type_of_control: TypeOfControl::Unspecified,
+            character_coding_scheme: CharacterCodingScheme::Unicode,
+            encoding_level: EncodingLevel::Full,
+            descriptive_cataloging_form: DescriptiveCatalogingForm::IsbdPunctuationIncluded,
+            multipart_resource_record_level: MultipartResourceRecordLevel::Unspecified,
+            fields: FieldIndex::empty_new(),
+        }
+    }
+
+    pub fn new(
+        r: &MarcRecord,
+        dir: &MarcDirectory,
+    ) -> Result<BibliographicRecordMeta, LeaderError> {
+        let h = r.header();
+        Ok(BibliographicRecordMeta {
+            record_type: h.record_type()?,
+            status: or_other(h.record_status(), RecordStatus::Other),
+            bibliographic_level: or_other(h.bibliographic_level(), BibliographicLevel::Other),
+            type_of_control: or_other(h.type_of_control(), TypeOfControl::Other),
+            character_coding_scheme: or_other(
+                h.character_coding_scheme(),
+                CharacterCodingScheme::Other,
+            ),
+            encoding_level: or_other(h.encoding_level(), EncodingLevel::Other),
+            descriptive_cataloging_form: or_other(
+                h.descriptive_cataloging_form(),
+                DescriptiveCatalogingForm::Other,
+            ),
+            multipart_resource_record_level: or_other(
+                h.multipart_resource_record_level(),
+                MultipartResourceRecordLevel::Other,
+            ),
+            fields: FieldIndex::new(dir),
+        })
+    }
+}
+
+// holdings, classification and community records only have the leader
+// positions that all formats share
+pub struct BasicRecordMeta {
+    record_type: RecordType,
+    status: RecordStatus,
+    character_coding_scheme: CharacterCodingScheme,
+    encoding_level: EncodingLevel,
+    fields: FieldIndex,
+}
+
+impl BasicRecordMeta {
+    pub fn empty_new(record_type: RecordType) -> BasicRecordMeta {
+        BasicRecordMeta {
+            record_type,
+            status: RecordStatus::New,
+            character_coding_scheme: CharacterCodingScheme::Unicode,
+            encoding_level: EncodingLevel::NotApplicable,
+            fields: FieldIndex::empty_new(),
+        }
+    }
+
+    pub fn new(r: &MarcRecord, dir: &MarcDirectory) -> Result<BasicRecordMeta, LeaderError> {
+        let h = r.header();
+        Ok(BasicRecordMeta {
+            record_type: h.record_type()?,
+            status: or_other(h.record_status(), RecordStatus::Other),
+            character_coding_scheme: or_other(
+                h.character_coding_scheme(),
+                CharacterCodingScheme::Other,
+            ),
+            encoding_level: or_other(h.encoding_level(), EncodingLevel::Other),
+            fields: FieldIndex::new(dir),
+        })
     }
 }
 
 pub enum RecordMeta {
     AuthorityMeta(AuthorityRecordMeta),
+    BibliographicMeta(BibliographicRecordMeta),
+    HoldingsMeta(BasicRecordMeta),
+    ClassificationMeta(BasicRecordMeta),
+    CommunityMeta(BasicRecordMeta),
 }
 
 impl RecordMeta {
-    pub fn empty_new(_t: RecordType) -> RecordMeta {
-        //todo other types
-        assert!(_t == RecordType::Authority);
-        RecordMeta::AuthorityMeta(AuthorityRecordMeta::empty_new())
-    }
-    pub fn new(r: &MarcRecord, d: &MarcDirectory) -> RecordMeta {
-        match r.header().record_type() {
-            RecordType::Authority => RecordMeta::AuthorityMeta(AuthorityRecordMeta::new(r, d)),
+    pub fn empty_new(t: RecordType) -> RecordMeta {
+        match t.format() {
+            RecordFormat::Authority => RecordMeta::AuthorityMeta(AuthorityRecordMeta::empty_new()),
+            RecordFormat::Bibliographic => {
+                RecordMeta::BibliographicMeta(BibliographicRecordMeta::empty_new(t))
+            }
+            RecordFormat::Holdings => RecordMeta::HoldingsMeta(BasicRecordMeta::empty_new(t)),
+            RecordFormat::Classification => {
+                RecordMeta::ClassificationMeta(BasicRecordMeta::empty_new(t))
+            }
+            RecordFormat::Community => RecordMeta::CommunityMeta(BasicRecordMeta::empty_new(t)),
         }
     }
 
+    pub fn new(r: &MarcRecord, d: &MarcDirectory) -> Result<RecordMeta, LeaderError> {
+        Ok(match r.header().record_type()?.format() {
+            RecordFormat::Authority => RecordMeta::AuthorityMeta(AuthorityRecordMeta::new(r, d)?),
+            RecordFormat::Bibliographic => {
+                RecordMeta::BibliographicMeta(BibliographicRecordMeta::new(r, d)?)
+            }
+            RecordFormat::Holdings => RecordMeta::HoldingsMeta(BasicRecordMeta::new(r, d)?),
+            RecordFormat::Classification => {
+                RecordMeta::ClassificationMeta(BasicRecordMeta::new(r, d)?)
+            }
+            RecordFormat::Community => RecordMeta::CommunityMeta(BasicRecordMeta::new(r, d)?),
+        })
+    }
+
     pub fn record_type(&self) -> RecordType {
         match self {
-            Self::AuthorityMeta(record_meta) => record_meta.record_type(),
+            Self::AuthorityMeta(_) => RecordType::Authority,
+            Self::BibliographicMeta(record_meta) => record_meta.record_type,
+            Self::HoldingsMeta(record_meta)
+            | Self::ClassificationMeta(record_meta)
+            | Self::CommunityMeta(record_meta) => record_meta.record_type,
         }
     }
 
-    pub fn get_field_type(&self, idx: usize) -> usize {
+    pub fn status(&self) -> RecordStatus {
         match self {
-            Self::AuthorityMeta(record_meta) => record_meta.field_types[idx],
+            Self::AuthorityMeta(record_meta) => record_meta.status,
+            Self::BibliographicMeta(record_meta) => record_meta.status,
+            Self::HoldingsMeta(record_meta)
+            | Self::ClassificationMeta(record_meta)
+            | Self::CommunityMeta(record_meta) => record_meta.status,
         }
     }
 
-    pub fn get_field_offset(&self, idx: usize) -> usize {
+    fn fields(&self) -> &FieldIndex {
         match self {
-            Self::AuthorityMeta(record_meta) => record_meta.field_offsets[idx],
+            Self::AuthorityMeta(record_meta) => &record_meta.fields,
+            Self::BibliographicMeta(record_meta) => &record_meta.fields,
+            Self::HoldingsMeta(record_meta)
+            | Self::ClassificationMeta(record_meta)
+            | Self::CommunityMeta(record_meta) => &record_meta.fields,
         }
     }
 
-    pub fn get_field_length(&self, idx: usize) -> usize {
+    fn fields_mut(&mut self) -> &mut FieldIndex {
         match self {
-            Self::AuthorityMeta(record_meta) => record_meta.field_lengths[idx],
+            Self::AuthorityMeta(record_meta) => &mut record_meta.fields,
+            Self::BibliographicMeta(record_meta) => &mut record_meta.fields,
+            Self::HoldingsMeta(record_meta)
+            | Self::ClassificationMeta(record_meta)
+            | Self::CommunityMeta(record_meta) => &mut record_meta.fields,
         }
     }
 
+    pub fn get_field_type(&self, idx: usize) -> usize {
+        self.fields().field_types[idx]
+    }
+
+    pub fn get_field_offset(&self, idx: usize) -> usize {
+        self.fields().field_offsets[idx]
+    }
+
+    pub fn get_field_length(&self, idx: usize) -> usize {
+        self.fields().field_lengths[idx]
+    }
+
     pub fn get_field<'s>(&self, idx: usize, record_data: &'s [u8]) -> RecordField<'s> {
         let field_type = self.get_field_type(idx);
         let field_offset = self.get_field_offset(idx);
@@ -153,42 +289,48 @@ impl RecordMeta {
     }
 
     pub fn num_fields(&self) -> usize {
-        match self {
-            Self::AuthorityMeta(record_meta) => record_meta.num_fields(),
-        }
+        self.fields().num_fields()
     }
 
     pub fn add_field(&mut self, field_type: usize, field_start: usize, field_len: usize) {
-        match self {
-            Self::AuthorityMeta(record_meta) => {
-                record_meta.add_field(field_type, field_start, field_len)
-            }
-        }
+        self.fields_mut()
+            .add_field(field_type, field_start, field_len)
     }
 }
 
 pub struct ParsedRecord {
+    header: [u8; 24],
     meta: RecordMeta,
     // Todo we definitely want to use an arena for this
     field_data: Vec<u8>,
 }
 
 impl ParsedRecord {
-    pub fn new(r: &MarcRecord) -> ParsedRecord {
+    pub fn new(r: &MarcRecord) -> Result<ParsedRecord, LeaderError> {
         let dir = r.directory();
-        ParsedRecord {
-            meta: RecordMeta::new(r, &dir),
+        let mut header = [0; 24];
+        header.copy_from_slice(r.header().header);
+        Ok(ParsedRecord {
+            header,
+            meta: RecordMeta::new(r, &dir)?,
             field_data: r.data()[dir.byte_len()..].to_vec(),
-        }
+        })
     }
 
     pub fn empty_new(t: RecordType) -> ParsedRecord {
+        let mut header = *b"00000n   a2200000   4500";
+        header[6] = t as u8;
         ParsedRecord {
+            header,
             meta: RecordMeta::empty_new(t),
             field_data: Vec::new(),
         }
     }
 
+    pub fn status(&self) -> RecordStatus {
+        self.meta.status()
+    }
+
     pub fn add_field(&mut self, field_type: usize, field_data: &[u8]) {
         self.meta
             .add_field(field_type, self.field_data.len(), field_data.len());
@@ -202,20 +344,29 @@ impl ParsedRecord {
         self.meta.num_fields()
     }
 
-    pub fn get_field(&self, idx: usize) -> RecordField {
+    pub fn get_field(&self, idx: usize) -> RecordField<'_> {
         self.meta.get_field(idx, self.field_data())
     }
 }
 
 impl Record for ParsedRecord {
-    fn record_type(&self) -> RecordType {
-        self.meta.record_type()
+    fn header(&self) -> &[u8] {
+        &self.header
+    }
+    fn record_type(&self) -> Option<RecordType> {
+        Some(self.meta.record_type())
     }
-    fn field_iter(&self, field_type: Option<usize>) -> Box<dyn Iterator<Item = RecordField> + '_> {
+    fn field_iter(
+        &self,
+        field_type: Option<usize>,
+    ) -> Box<dyn Iterator<Item = RecordField<'_>> + '_> {
         Box::new(ParsedRecordFieldIter::new(self, field_type))
     }
 
-    fn field_iter_vec(&self, field_type: &[usize]) -> Box<dyn Iterator<Item = RecordField> + '_> {
+    fn field_iter_vec(
+        &self,
+        _field_types: &[usize],
+    ) -> Box<dyn Iterator<Item = RecordField<'_>> + '_> {
         todo!()
     }
 
@@ -249,7 +400,7 @@ impl<'s> Iterator for ParsedRecordFieldIter<'s> {
             // optimization
             let field = self.record.get_field(self.idx);
             self.idx += 1;
-            if self.field_type.map_or(true, |t| t == field.field_type) {
+            if self.field_type.is_none_or(|t| t == field.field_type) {
                 return Some(field);
             }
         }
@@ -259,11 +410,12 @@ impl<'s> Iterator for ParsedRecordFieldIter<'s> {
 
 #[cfg(test)]
 mod tests {
+    use crate::leader::RecordStatus;
     use crate::marcrecord::MarcHeader;
     use crate::marcrecord::MarcRecord;
     use crate::parsedrecord::ParsedRecord;
     use crate::record::*;
-    static str : &[u8]= "00827nz  a2200241nc 4500\
+    static STR : &[u8]= "00827nz  a2200241nc 4500\
 001001000000\
 003000700010\
 005001700017\
@@ -285,9 +437,9 @@ mod tests {
 040000028DE-10120100106125650.0880701n||azznnbabn           | ana    |c7 a4000002-30http://d-nb.info/gnd/4000002-32gnd  a(DE-101)040000028  a(DE-588)4000002-3  z(DE-588c)4000002-39v:zg  aDE-101cDE-1019r:DE-101bgerd0832  agnd1  a31.9b2sswd  bs2gndgen  agqs04a621.3815379d:29t:2010-01-06223/ger  aA 302 D  0(DE-101)0402724270(DE-588)4027242-40https://d-nb.info/gnd/4027242-4aIntegrierte Schaltung4obal4https://d-nb.info/standards/elementset/gnd#broaderTermGeneralwriOberbegriff allgemein  aVorlage  SswdisaA 302 D0(DE-588c)4000002-3".as_bytes();
     #[test]
     fn parse_one() -> Result<(), String> {
-        let header = MarcHeader::new(&str[..24]);
-        let unparsed_record = MarcRecord::new(header, &str[24..]);
-        let parsed_record = ParsedRecord::new(&unparsed_record);
+        let header = MarcHeader::new(&STR[..24]);
+        let unparsed_record = MarcRecord::new(header, &STR[24..]);
+        let parsed_record = ParsedRecord::new(&unparsed_record).map_err(|x| x.to_string())?;
         assert_eq!(parsed_record.num_fields(), 18);
         assert_eq!(
             parsed_record.field_iter(None).count(),
@@ -295,10 +447,22 @@ mod tests {
         );
         assert_eq!(parsed_record.field_iter(Some(35)).count(), 3);
         let mut it = parsed_record.field_iter(None);
-        let first = it.next().ok_or_else(|| "not enough elements")?;
-        let last = it.last().ok_or_else(|| "not enough elements")?;
+        let first = it.next().ok_or("not enough elements")?;
+        let last = it.last().ok_or("not enough elements")?;
         assert_eq!(first.utf8_data(), "040000028");
         assert_eq!(last.utf8_data(), "  SswdisaA 302 D0(DE-588c)4000002-3");
         Ok(())
     }
+
+    #[test]
+    fn parse_unknown_leader_values() -> Result<(), String> {
+        let mut leader = STR[..24].to_vec();
+        leader[5] = b'q';
+        leader[17] = b'#';
+        let unparsed_record = MarcRecord::new(MarcHeader::new(&leader), &STR[24..]);
+        let parsed_record = ParsedRecord::new(&unparsed_record).map_err(|x| x.to_string())?;
+        assert_eq!(parsed_record.status(), RecordStatus::Other(b'q'));
+        assert_eq!(parsed_record.num_fields(), 18);
+        Ok(())
+    }
 }
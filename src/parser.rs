@@ -1,12 +1,30 @@
 /*
-STMT -> select COLUMN_EXPR_LIST from TABLE WHERE_CLAUSE
-COLUMN_EXPR_LIST -> COLUMN_EXPR | COLUMN_EXPR, COLUMN_EXPR_LIST
+QUERY -> STMT | explain STMT | explain analyze STMT
+STMT -> select DISTINCT_CLAUSE COLUMN_LIST from TABLE_LIST WHERE_CLAUSE GROUP_CLAUSE HAVING_CLAUSE ORDER_CLAUSE
+        LIMIT_CLAUSE
+DISTINCT_CLAUSE -> | distinct | distinct on ( FIELD_REF_LIST )
+COLUMN_LIST -> COLUMN | COLUMN, COLUMN_LIST
+COLUMN -> COLUMN_EXPR | COLUMN_EXPR as NAME
+NAME -> identifier | 'string'
+TABLE_LIST -> TABLE | TABLE union all TABLE_LIST | TABLE join TABLE on EXPR
+TABLE -> TABLE_NAME | TABLE_NAME alias
+TABLE_NAME -> identifier | 'path'
 WHERE_CLAUSE -> | where EXPR
+GROUP_CLAUSE -> | group by FIELD_REF_LIST
+FIELD_REF_LIST -> FIELD_REF | FIELD_REF, FIELD_REF_LIST
+HAVING_CLAUSE -> | having EXPR
+ORDER_CLAUSE -> | order by SORT_KEY_LIST
+SORT_KEY_LIST -> SORT_KEY | SORT_KEY, SORT_KEY_LIST
+SORT_KEY -> COLUMN_EXPR | COLUMN_EXPR asc | COLUMN_EXPR desc
+LIMIT_CLAUSE -> | limit n | limit n offset n
 */
+#![allow(non_snake_case)]
 
+pub use crate::error::QueryError;
 use crate::exprparse::*;
 pub use crate::lexer::*;
 
+// pre tells whether to visit the children of a node, post whether to go on
 pub trait ParseTreeVisitor<'a> {
     fn pre(&mut self, node: &ParseNode<'a>) -> bool;
     fn post(&mut self, node: &ParseNode<'a>) -> bool;
@@ -48,89 +66,374 @@ impl<'a> ParseNode<'a> {
         visitor(self);
     }
 
-    pub fn visit(&self, visitor: &mut impl ParseTreeVisitor<'a>) -> bool {
-        if !visitor.pre(self) {
-            return false;
+    // the expression as it is written in a query, names unnamed columns
+    pub fn text(&self) -> String {
+        match &self.entry {
+            LexItem::FieldRef(record_type, field_type, subfield_type) => {
+                let mut text = String::new();
+                if let Some(record_type) = record_type {
+                    text.push_str(record_type);
+                    text.push('.');
+                }
+                text.push_str(field_type.unwrap_or(""));
+                if let Some(subfield_type) = subfield_type {
+                    text.push('.');
+                    text.push_str(subfield_type);
+                }
+                for c in &self.children {
+                    text.push_str(&c.text());
+                }
+                text
+            }
+            LexItem::Slice(start, Some(end)) => format!("[{}:{}]", start, end),
+            LexItem::Slice(start, None) => format!("[{}:]", start),
+            LexItem::Identifier(name) => {
+                let arguments: Vec<String> = self.children.iter().map(|x| x.text()).collect();
+                format!("{}({})", name, arguments.join(", "))
+            }
+            LexItem::KW(Keyword::Distinct) => {
+                let arguments: Vec<String> = self.children.iter().map(|x| x.text()).collect();
+                format!("distinct {}", arguments.join(", "))
+            }
+            LexItem::StringLit(s) | LexItem::RegexStr(s) => format!("'{}'", s),
+            LexItem::NumberLit(n) => n.to_string(),
+            x => format!("{:?}", x),
         }
+    }
+
+    // one node per line, indented by depth, with its position in the query
+    pub fn tree(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(0, &mut out);
+        out
+    }
+
+    fn write_tree(&self, depth: usize, out: &mut String) {
+        out.push_str(&format!(
+            "{}{:?} at {}\n",
+            "  ".repeat(depth),
+            self.entry,
+            self.context.0
+        ));
         for c in &self.children {
-            if !c.visit(visitor) {
-                return false;
+            c.write_tree(depth + 1, out);
+        }
+    }
+
+    // the same expression, possibly at a different position
+    pub fn same_as(&self, other: &ParseNode) -> bool {
+        self.entry == other.entry
+            && self.children.len() == other.children.len()
+            && self
+                .children
+                .iter()
+                .zip(other.children.iter())
+                .all(|(a, b)| a.same_as(b))
+    }
+
+    pub fn visit(&self, visitor: &mut impl ParseTreeVisitor<'a>) -> bool {
+        if visitor.pre(self) {
+            for c in &self.children {
+                if !c.visit(visitor) {
+                    return false;
+                }
             }
         }
         visitor.post(self)
     }
 }
 
-pub fn parse(input: &str) -> Result<ParseNode, String> {
+pub fn parse(input: &str) -> Result<ParseNode<'_>, QueryError> {
     let tokens = lex(input)?;
-    parse_SELECT(&tokens, 0).and_then(|(n, i)| {
+    // the select is the last child of an explain node, after analyze
+    let parsed = match tokens.first() {
+        Some((context, LexItem::KW(Keyword::Explain))) => {
+            let mut explain = ParseNode::new(LexItem::KW(Keyword::Explain), context.clone());
+            let mut offset = 1;
+            if let Some((context, LexItem::KW(Keyword::Analyze))) = tokens.get(1) {
+                explain.children.push(ParseNode::new(
+                    LexItem::KW(Keyword::Analyze),
+                    context.clone(),
+                ));
+                offset = 2;
+            }
+            parse_SELECT(&tokens, offset).map(|(select, i)| {
+                explain.children.push(select);
+                (explain, i)
+            })
+        }
+        _ => parse_SELECT(&tokens, 0),
+    };
+    parsed.and_then(|(n, i)| {
         if i == tokens.len() {
             Ok(n)
         } else {
-            Err(format!(
-                "Expected end of input, found {:?} at {}",
-                tokens.get(i),
-                i
-            ))
+            Err(QueryError::expected("the end of the query", tokens.get(i)))
         }
     })
 }
 
-fn parse_SELECT<'a>(
+pub fn parse_SELECT<'a>(
     input: &[(ItemContext, LexItem<'a>)],
     offset: usize,
-) -> Result<(ParseNode<'a>, usize), String> {
+) -> Result<(ParseNode<'a>, usize), QueryError> {
     let c = input.get(offset);
     match c {
         Some((context, LexItem::KW(Keyword::Select))) => {
             let mut select_clause = ParseNode::new(LexItem::KW(Keyword::Select), context.clone());
-            // parse projection list
             let mut next_offset = offset + 1;
+            if let Some((context, LexItem::KW(Keyword::Distinct))) = input.get(next_offset) {
+                let (distinct_node, recurse_offset) =
+                    parse_DISTINCT(input, next_offset + 1, context)?;
+                next_offset = recurse_offset;
+                select_clause.children.push(distinct_node);
+            }
+            // parse projection list
             'the_loop: loop {
-                if let Some((context, LexItem::FieldRef(record_type, field_type, subfield_type))) =
-                    input.get(next_offset)
-                {
-                    let fieldref_node = ParseNode::new(
-                        LexItem::FieldRef(*record_type, *field_type, *subfield_type),
-                        context.clone(),
-                    );
-                    select_clause.children.push(fieldref_node);
-                    next_offset += 1;
-                    match input.get(next_offset) {
-                        Some((context, LexItem::Punctuation(Punctuation::Comma))) => {
-                            next_offset += 1;
-                        }
-                        Some((context, LexItem::KW(Keyword::FromKW))) => {
-                            next_offset += 1; // skip the from so we're at the talbe ref after the loop
-                            break 'the_loop;
-                        }
-                        _ => {
-                            return Err("expected comma or from".to_string());
-                        }
+                let (column_node, offset) = parse_COLUMN(input, next_offset)?;
+                select_clause.children.push(column_node);
+                next_offset = offset;
+                match input.get(next_offset) {
+                    Some((_, LexItem::Punctuation(Punctuation::Comma))) => {
+                        next_offset += 1;
+                    }
+                    Some((_, LexItem::KW(Keyword::FromKW))) => {
+                        break 'the_loop;
+                    }
+                    x => {
+                        return Err(QueryError::expected("',' or from", x));
                     }
-                } else {
-                    return Err("expectod a field ref".to_string());
                 }
             }
-            // now we should be at the table ref
-            if let Some((context, LexItem::Identifier(table_name))) = input.get(next_offset) {
-                let table_ref_node =
-                    ParseNode::new(LexItem::Identifier(table_name), context.clone());
-                select_clause.children.push(table_ref_node);
-            } else {
-                return Err("expected table ref".to_string());
-            }
-            next_offset += 1;
+            // now we should be at the from
+            let (from_node, recurse_offset) = parse_FROM(input, next_offset)?;
+            next_offset = recurse_offset;
+            select_clause.children.push(from_node);
             // maybe we have a where clause
-            if let Some((context, LexItem::KW(Keyword::Where))) = input.get(next_offset) {
+            if let Some((_, LexItem::KW(Keyword::Where))) = input.get(next_offset) {
                 let (filter_node, recurse_offset) = parse_expr(input, next_offset + 1)?;
                 next_offset = recurse_offset;
                 select_clause.children.push(filter_node);
             }
+            if let Some((context, LexItem::KW(Keyword::GroupBy))) = input.get(next_offset) {
+                let mut group_node = ParseNode::new(LexItem::KW(Keyword::GroupBy), context.clone());
+                next_offset += 1;
+                let (fields, recurse_offset) = parse_FIELD_REF_LIST(input, next_offset)
+                    .ok_or_else(|| {
+                        QueryError::expected("a field ref after group by", input.get(next_offset))
+                    })?;
+                group_node.children = fields;
+                next_offset = recurse_offset;
+                select_clause.children.push(group_node);
+            }
+            if let Some((context, LexItem::KW(Keyword::Having))) = input.get(next_offset) {
+                let mut having_node = ParseNode::new(LexItem::KW(Keyword::Having), context.clone());
+                let (filter_node, recurse_offset) = parse_expr(input, next_offset + 1)?;
+                next_offset = recurse_offset;
+                having_node.children.push(filter_node);
+                select_clause.children.push(having_node);
+            }
+            if let Some((context, LexItem::KW(Keyword::OrderBy))) = input.get(next_offset) {
+                let (order_node, recurse_offset) = parse_ORDER_BY(input, next_offset + 1, context)?;
+                next_offset = recurse_offset;
+                select_clause.children.push(order_node);
+            }
+            if let Some((context, LexItem::KW(Keyword::Limit))) = input.get(next_offset) {
+                let mut limit_node = parse_count(input, next_offset + 1, Keyword::Limit, context)?;
+                next_offset += 2;
+                if let Some((context, LexItem::KW(Keyword::Offset))) = input.get(next_offset) {
+                    limit_node.children.push(parse_count(
+                        input,
+                        next_offset + 1,
+                        Keyword::Offset,
+                        context,
+                    )?);
+                    next_offset += 2;
+                }
+                select_clause.children.push(limit_node);
+            }
 
             Ok((select_clause, next_offset))
         }
-        _ => Err("expected a select".to_string()),
+        x => Err(QueryError::expected("select", x)),
+    }
+}
+
+// the tables are the children of the from node, a join node with the joined
+// table and the condition follows them
+fn parse_FROM<'a>(
+    input: &[(ItemContext, LexItem<'a>)],
+    offset: usize,
+) -> Result<(ParseNode<'a>, usize), QueryError> {
+    let mut from_node = match input.get(offset) {
+        Some((context, LexItem::KW(Keyword::FromKW))) => {
+            ParseNode::new(LexItem::KW(Keyword::FromKW), context.clone())
+        }
+        x => return Err(QueryError::expected("from", x)),
+    };
+    let mut next_offset = offset + 1;
+    loop {
+        let (table_node, recurse_offset) = parse_TABLE(input, next_offset)?;
+        from_node.children.push(table_node);
+        next_offset = recurse_offset;
+        match input.get(next_offset) {
+            Some((_, LexItem::KW(Keyword::UnionAll))) => next_offset += 1,
+            Some((context, LexItem::KW(Keyword::Join))) => {
+                let mut join_node = ParseNode::new(LexItem::KW(Keyword::Join), context.clone());
+                let (table_node, recurse_offset) = parse_TABLE(input, next_offset + 1)?;
+                join_node.children.push(table_node);
+                next_offset = recurse_offset;
+                match input.get(next_offset) {
+                    Some((_, LexItem::KW(Keyword::On))) => {}
+                    x => return Err(QueryError::expected("on after the joined table", x)),
+                }
+                let (condition, recurse_offset) = parse_expr(input, next_offset + 1)?;
+                join_node.children.push(condition);
+                from_node.children.push(join_node);
+                return Ok((from_node, recurse_offset));
+            }
+            _ => return Ok((from_node, next_offset)),
+        }
+    }
+}
+
+// the alias of a table is its child
+fn parse_TABLE<'a>(
+    input: &[(ItemContext, LexItem<'a>)],
+    offset: usize,
+) -> Result<(ParseNode<'a>, usize), QueryError> {
+    let mut table_node = match input.get(offset) {
+        Some((context, table @ (LexItem::Identifier(_) | LexItem::StringLit(_)))) => {
+            ParseNode::new(table.clone(), context.clone())
+        }
+        x => return Err(QueryError::expected("a table", x)),
+    };
+    match input.get(offset + 1) {
+        Some((context, alias @ LexItem::Identifier(_))) => {
+            table_node
+                .children
+                .push(ParseNode::new(alias.clone(), context.clone()));
+            Ok((table_node, offset + 2))
+        }
+        _ => Ok((table_node, offset + 1)),
+    }
+}
+
+// an alias makes the column the first child of an as node, the name is the second
+fn parse_COLUMN<'a>(
+    input: &[(ItemContext, LexItem<'a>)],
+    offset: usize,
+) -> Result<(ParseNode<'a>, usize), QueryError> {
+    let (column_node, next_offset) = parse_COLUMN_EXPR(input, offset)?;
+    match input.get(next_offset) {
+        Some((context, LexItem::KW(Keyword::As))) => match input.get(next_offset + 1) {
+            Some((name_context, name @ (LexItem::Identifier(_) | LexItem::StringLit(_)))) => {
+                let mut as_node = ParseNode::new(LexItem::KW(Keyword::As), context.clone());
+                as_node.children.push(column_node);
+                as_node
+                    .children
+                    .push(ParseNode::new(name.clone(), name_context.clone()));
+                Ok((as_node, next_offset + 2))
+            }
+            x => Err(QueryError::expected("a column name after as", x)),
+        },
+        _ => Ok((column_node, next_offset)),
+    }
+}
+
+fn parse_FIELD_REF_LIST<'a>(
+    input: &[(ItemContext, LexItem<'a>)],
+    offset: usize,
+) -> Option<(Vec<ParseNode<'a>>, usize)> {
+    let mut fields = Vec::new();
+    let mut next_offset = offset;
+    loop {
+        let (fieldref_node, offset) = parse_FIELD_REF(input, next_offset)?;
+        fields.push(fieldref_node);
+        next_offset = offset;
+        match input.get(next_offset) {
+            Some((_, LexItem::Punctuation(Punctuation::Comma))) => next_offset += 1,
+            _ => return Some((fields, next_offset)),
+        }
+    }
+}
+
+// a distinct node, the field refs of distinct on are its children
+fn parse_DISTINCT<'a>(
+    input: &[(ItemContext, LexItem<'a>)],
+    offset: usize,
+    context: &ItemContext,
+) -> Result<(ParseNode<'a>, usize), QueryError> {
+    let mut distinct_node = ParseNode::new(LexItem::KW(Keyword::Distinct), context.clone());
+    if let Some((_, LexItem::KW(Keyword::On))) = input.get(offset) {
+        match input.get(offset + 1) {
+            Some((_, LexItem::Punctuation(Punctuation::Paren))) => {}
+            x => return Err(QueryError::expected("'(' after distinct on", x)),
+        }
+        let (fields, next_offset) = parse_FIELD_REF_LIST(input, offset + 2).ok_or_else(|| {
+            QueryError::expected("a field ref after distinct on", input.get(offset + 2))
+        })?;
+        match input.get(next_offset) {
+            Some((_, LexItem::Punctuation(Punctuation::Paren))) => {
+                distinct_node.children = fields;
+                return Ok((distinct_node, next_offset + 1));
+            }
+            x => return Err(QueryError::expected("',' or ')' after the field refs", x)),
+        }
+    }
+    Ok((distinct_node, offset))
+}
+
+// limit and offset take a number that isn't negative
+fn parse_count<'a>(
+    input: &[(ItemContext, LexItem<'a>)],
+    offset: usize,
+    keyword: Keyword,
+    context: &ItemContext,
+) -> Result<ParseNode<'a>, QueryError> {
+    match input.get(offset) {
+        Some((ctx, LexItem::NumberLit(n))) if n.starts_with('-') => Err(QueryError::invalid(
+            ctx,
+            format!("{} can't be negative", keyword.text()),
+        )),
+        Some((ctx, LexItem::NumberLit(n))) => {
+            let mut node = ParseNode::new(LexItem::KW(keyword), context.clone());
+            node.children
+                .push(ParseNode::new(LexItem::NumberLit(n), ctx.clone()));
+            Ok(node)
+        }
+        x => Err(QueryError::expected(
+            &format!("a number after {}", keyword.text()),
+            x,
+        )),
+    }
+}
+
+// every sort key is an asc or desc node with the field ref as its child
+fn parse_ORDER_BY<'a>(
+    input: &[(ItemContext, LexItem<'a>)],
+    offset: usize,
+    context: &ItemContext,
+) -> Result<(ParseNode<'a>, usize), QueryError> {
+    let mut order_node = ParseNode::new(LexItem::KW(Keyword::OrderBy), context.clone());
+    let mut next_offset = offset;
+    loop {
+        let (column_node, offset) = parse_COLUMN_EXPR(input, next_offset)?;
+        next_offset = offset;
+        let direction = match input.get(next_offset) {
+            Some((_, LexItem::KW(kw @ (Keyword::Asc | Keyword::Desc)))) => {
+                next_offset += 1;
+                kw.clone()
+            }
+            _ => Keyword::Asc,
+        };
+        let mut key_node = ParseNode::new(LexItem::KW(direction), column_node.context.clone());
+        key_node.children.push(column_node);
+        order_node.children.push(key_node);
+        match input.get(next_offset) {
+            Some((_, LexItem::Punctuation(Punctuation::Comma))) => next_offset += 1,
+            _ => return Ok((order_node, next_offset)),
+        }
     }
 }
 
@@ -151,7 +454,11 @@ mod test {
             x.children[1].entry,
             LexItem::FieldRef(Some("a"), Some("150"), Some("b"))
         );
-        assert_eq!(x.children[2].entry, LexItem::Identifier("some_table"));
+        assert_eq!(x.children[2].entry, LexItem::KW(Keyword::FromKW));
+        assert_eq!(
+            x.children[2].children[0].entry,
+            LexItem::Identifier("some_table")
+        );
         Ok(())
     }
 
@@ -164,7 +471,11 @@ mod test {
             x.children[0].entry,
             LexItem::FieldRef(None, Some("*"), None)
         );
-        assert_eq!(x.children[1].entry, LexItem::Identifier("some_table"));
+        assert_eq!(x.children[1].entry, LexItem::KW(Keyword::FromKW));
+        assert_eq!(
+            x.children[1].children[0].entry,
+            LexItem::Identifier("some_table")
+        );
         assert_eq!(
             x.children[2].entry,
             LexItem::InfixFunction(InfixFn::MatchOp)
@@ -188,7 +499,11 @@ mod test {
             x.children[0].entry,
             LexItem::FieldRef(None, Some("*"), None)
         );
-        assert_eq!(x.children[1].entry, LexItem::Identifier("some_table"));
+        assert_eq!(x.children[1].entry, LexItem::KW(Keyword::FromKW));
+        assert_eq!(
+            x.children[1].children[0].entry,
+            LexItem::Identifier("some_table")
+        );
         assert_eq!(x.children[2].entry, LexItem::InfixFunction(InfixFn::EqOp));
         let eqop = &x.children[2];
         assert_eq!(eqop.children.len(), 2);
@@ -196,10 +511,208 @@ mod test {
             eqop.children[0].entry,
             LexItem::FieldRef(None, Some("150"), None)
         );
+        // both sides are fields, a number would be +142
         assert_eq!(
             eqop.children[1].entry,
             LexItem::FieldRef(None, Some("142"), None)
         );
         Ok(())
     }
+
+    #[test]
+    fn parse_slice() -> Result<(), String> {
+        let x = parse("select 008[35:38] from some_table where leader[6] ~ 'a'")?;
+        assert_eq!(x.children.len(), 3);
+        assert_eq!(
+            x.children[0].entry,
+            LexItem::FieldRef(None, Some("008"), None)
+        );
+        assert_eq!(
+            x.children[0].children[0].entry,
+            LexItem::Slice(35, Some(38))
+        );
+        let matchop = &x.children[2];
+        assert_eq!(
+            matchop.children[0].entry,
+            LexItem::FieldRef(None, Some("leader"), None)
+        );
+        assert_eq!(
+            matchop.children[0].children[0].entry,
+            LexItem::Slice(6, Some(7))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_order_by() -> Result<(), String> {
+        let x = parse("select 245.a from some_table where 150 ~ 'a' order by 001 desc, 150.a")?;
+        assert_eq!(x.children.len(), 4);
+        let order = &x.children[3];
+        assert_eq!(order.entry, LexItem::KW(Keyword::OrderBy));
+        let keys: Vec<(LexItem, LexItem)> = order
+            .children
+            .iter()
+            .map(|x| (x.entry.clone(), x.children[0].entry.clone()))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                (
+                    LexItem::KW(Keyword::Desc),
+                    LexItem::FieldRef(None, Some("001"), None)
+                ),
+                (
+                    LexItem::KW(Keyword::Asc),
+                    LexItem::FieldRef(None, Some("150"), Some("a"))
+                ),
+            ]
+        );
+        assert!(parse("select * from some_table order by").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_limit() -> Result<(), String> {
+        let x = parse("select 001 from some_table order by 001 limit 10 offset 5")?;
+        let limit = &x.children[3];
+        assert_eq!(limit.entry, LexItem::KW(Keyword::Limit));
+        assert_eq!(limit.children[0].entry, LexItem::NumberLit("10"));
+        assert_eq!(limit.children[1].entry, LexItem::KW(Keyword::Offset));
+        assert_eq!(limit.children[1].children[0].entry, LexItem::NumberLit("5"));
+        assert!(parse("select 001 from some_table limit 245.a").is_err());
+        assert!(parse("select 001 from some_table offset 5").is_err());
+        let x = parse("select 001 from some_table limit +5")?;
+        assert_eq!(x.children[2].children[0].entry, LexItem::NumberLit("+5"));
+        let e = parse("select 001 from some_table limit 5 offset -1").unwrap_err();
+        assert_eq!(e.to_string(), "offset can't be negative at 42");
+        Ok(())
+    }
+
+    #[test]
+    fn parse_group_by() -> Result<(), String> {
+        let x = parse(
+            "select 040.b, count(*), count(distinct 700.a) from some_table group by 040.b order by count(*) desc",
+        )?;
+        assert_eq!(x.children.len(), 6);
+        let count = &x.children[1];
+        assert_eq!(count.entry, LexItem::Identifier("count"));
+        assert_eq!(
+            count.children[0].entry,
+            LexItem::FieldRef(None, Some("*"), None)
+        );
+        let distinct = &x.children[2].children[0];
+        assert_eq!(distinct.entry, LexItem::KW(Keyword::Distinct));
+        assert_eq!(
+            distinct.children[0].entry,
+            LexItem::FieldRef(None, Some("700"), Some("a"))
+        );
+        assert_eq!(x.children[4].entry, LexItem::KW(Keyword::GroupBy));
+        assert!(x.children[4].children[0].same_as(&x.children[0]));
+        let key = &x.children[5].children[0];
+        assert_eq!(key.entry, LexItem::KW(Keyword::Desc));
+        assert!(key.children[0].same_as(count));
+        assert!(!key.children[0].same_as(&x.children[2]));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_distinct() -> Result<(), String> {
+        let x = parse("select distinct 040.b from some_table")?;
+        assert_eq!(x.children.len(), 3);
+        assert_eq!(x.children[0].entry, LexItem::KW(Keyword::Distinct));
+        assert!(x.children[0].children.is_empty());
+        let x = parse("select distinct on (001, 035.a) * from some_table")?;
+        assert_eq!(x.children.len(), 3);
+        let distinct = &x.children[0];
+        assert_eq!(
+            distinct.children[1].entry,
+            LexItem::FieldRef(None, Some("035"), Some("a"))
+        );
+        assert_eq!(
+            x.children[1].entry,
+            LexItem::FieldRef(None, Some("*"), None)
+        );
+        assert!(parse("select distinct on 001 * from some_table").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_alias() -> Result<(), String> {
+        let x = parse("select 001 as id, upper(150.a) as 'heading' from some_table")?;
+        assert_eq!(x.children.len(), 3);
+        let id = &x.children[0];
+        assert_eq!(id.entry, LexItem::KW(Keyword::As));
+        assert_eq!(
+            id.children[0].entry,
+            LexItem::FieldRef(None, Some("001"), None)
+        );
+        assert_eq!(id.children[1].entry, LexItem::Identifier("id"));
+        assert_eq!(x.children[1].children[0].text(), "upper(150.a)");
+        assert_eq!(
+            x.children[1].children[1].entry,
+            LexItem::StringLit("heading")
+        );
+        assert!(parse("select 001 as from some_table").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_union_all() -> Result<(), String> {
+        let x = parse("select 001 from titles union all 'dumps/title_*.mrc' where 001 = 1")?;
+        assert_eq!(x.children.len(), 3);
+        let from = &x.children[1];
+        assert_eq!(from.children[0].entry, LexItem::Identifier("titles"));
+        assert_eq!(
+            from.children[1].entry,
+            LexItem::StringLit("dumps/title_*.mrc")
+        );
+        assert!(parse("select 001 from titles union all where 001 = 1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_explain() -> Result<(), String> {
+        let x = parse("explain select 001 from titles")?;
+        assert_eq!(x.entry, LexItem::KW(Keyword::Explain));
+        assert_eq!(x.children[0].entry, LexItem::KW(Keyword::Select));
+        assert_eq!(
+            x.children[0].tree(),
+            "KW(Select) at 8\n  FieldRef(None, Some(\"001\"), None) at 15\n  \
+             KW(FromKW) at 19\n    Identifier(\"titles\") at 24\n"
+        );
+        assert!(parse("select 001 from titles explain").is_err());
+        let x = parse("explain analyze select 001 from titles")?;
+        assert_eq!(x.children[0].entry, LexItem::KW(Keyword::Analyze));
+        assert_eq!(x.children[1].entry, LexItem::KW(Keyword::Select));
+        assert!(parse("explain analyze").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_join() -> Result<(), String> {
+        let x = parse("select t.245.a, g.150.a from titles t join gnd g on t.700.0 = g.035.a")?;
+        let from = &x.children[2];
+        assert_eq!(from.children.len(), 2);
+        assert_eq!(from.children[0].entry, LexItem::Identifier("titles"));
+        assert_eq!(from.children[0].children[0].entry, LexItem::Identifier("t"));
+        let join = &from.children[1];
+        assert_eq!(join.entry, LexItem::KW(Keyword::Join));
+        assert_eq!(join.children[0].entry, LexItem::Identifier("gnd"));
+        assert_eq!(join.children[0].children[0].entry, LexItem::Identifier("g"));
+        assert_eq!(
+            join.children[1].entry,
+            LexItem::InfixFunction(InfixFn::EqOp)
+        );
+        assert_eq!(
+            join.children[1].children[0].entry,
+            LexItem::FieldRef(Some("t"), Some("700"), Some("0"))
+        );
+        let e = parse("select 001 from titles t join gnd g where 001 = 1").unwrap_err();
+        assert_eq!(e.context, Some(ItemContext(36)));
+        assert_eq!(
+            e.message(),
+            "expected on after the joined table, found where"
+        );
+        Ok(())
+    }
 }
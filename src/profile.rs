@@ -0,0 +1,188 @@
+// Counters for explain analyze. Every node of the filter tree is wrapped so
+// it counts the records it evaluates, its results and the time it takes,
+// the time of a node includes that of its inputs. The reader counts the
+// records and bytes it hands out and the time it spends reading them.
+use crate::filter::{ExistsFilter, Filter};
+use crate::util::TriStateBool;
+use crate::Record;
+use std::any::Any;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+fn millis(time: Duration) -> f64 {
+    time.as_secs_f64() * 1000.0
+}
+
+#[derive(Default)]
+pub struct FilterStats {
+    evaluated: Cell<u64>,
+    true_count: Cell<u64>,
+    false_count: Cell<u64>,
+    null_count: Cell<u64>,
+    time: Cell<Duration>,
+}
+
+impl FilterStats {
+    fn add(&self, result: TriStateBool, time: Duration) {
+        self.evaluated.set(self.evaluated.get() + 1);
+        let count = match result {
+            TriStateBool::True => &self.true_count,
+            TriStateBool::False => &self.false_count,
+            TriStateBool::Null => &self.null_count,
+        };
+        count.set(count.get() + 1);
+        self.time.set(self.time.get() + time);
+    }
+
+    pub fn evaluated(&self) -> u64 {
+        self.evaluated.get()
+    }
+
+    // the number of true, false and null results
+    pub fn results(&self) -> (u64, u64, u64) {
+        (
+            self.true_count.get(),
+            self.false_count.get(),
+            self.null_count.get(),
+        )
+    }
+}
+
+impl std::fmt::Display for FilterStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (t, fa, n) = self.results();
+        write!(
+            f,
+            "evaluated {}, true {}, false {}, null {}, {:.3} ms",
+            self.evaluated(),
+            t,
+            fa,
+            n,
+            millis(self.time.get())
+        )
+    }
+}
+
+pub struct ProfiledFilter {
+    inner: Box<dyn Filter>,
+    stats: Rc<FilterStats>,
+}
+
+impl Filter for ProfiledFilter {
+    fn evaluate_predicate(&self, r: &dyn Record) -> TriStateBool {
+        let start = Instant::now();
+        let result = self.inner.evaluate_predicate(r);
+        self.stats.add(result, start.elapsed());
+        result
+    }
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn describe(&self) -> String {
+        self.inner.describe()
+    }
+    fn inputs(&self) -> Vec<&dyn Filter> {
+        self.inner.inputs()
+    }
+    fn inputs_mut(&mut self) -> Vec<&mut Box<dyn Filter>> {
+        self.inner.inputs_mut()
+    }
+    fn stats(&self) -> Option<Rc<FilterStats>> {
+        Some(self.stats.clone())
+    }
+}
+
+// wraps the filter and all of its inputs
+pub fn profile(mut filter: Box<dyn Filter>) -> Box<dyn Filter> {
+    for input in filter.inputs_mut() {
+        // stands in while the input is moved into its wrapper
+        let stand_in: Box<dyn Filter> = Box::new(ExistsFilter::new(Rc::default()));
+        let child = std::mem::replace(input, stand_in);
+        *input = profile(child);
+    }
+    Box::new(ProfiledFilter {
+        inner: filter,
+        stats: Rc::default(),
+    })
+}
+
+#[derive(Default)]
+pub struct ReaderStats {
+    records: Cell<u64>,
+    bytes: Cell<u64>,
+    time: Cell<Duration>,
+}
+
+impl ReaderStats {
+    pub fn add(&self, records: usize, bytes: u64, time: Duration) {
+        self.records.set(self.records.get() + records as u64);
+        self.bytes.set(self.bytes.get() + bytes);
+        self.time.set(self.time.get() + time);
+    }
+
+    pub fn records(&self) -> u64 {
+        self.records.get()
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes.get()
+    }
+}
+
+impl std::fmt::Display for ReaderStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let secs = self.time.get().as_secs_f64();
+        let rate = |x: f64| if secs > 0.0 { x / secs } else { 0.0 };
+        let mb = self.bytes() as f64 / 1_000_000.0;
+        write!(
+            f,
+            "{} records, {:.1} MB in {:.3} ms, {:.0} records/s, {:.1} MB/s",
+            self.records(),
+            mb,
+            millis(self.time.get()),
+            rate(self.records() as f64),
+            rate(mb)
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::field_expression::*;
+    use crate::filter::*;
+    use crate::ownedrecord::*;
+    use crate::profile::*;
+    use crate::record::*;
+
+    #[test]
+    fn test_profile() {
+        let field =
+            |x: &str| FilterInput::FieldRef(Box::new(FieldRefExpr::new(None, Some(x), None)));
+        let literal = |x: &str| FilterInput::Literal(Literal::Str(x.as_bytes().to_vec()));
+        // 001 = '1' or 002 = 'x'
+        let filter = profile(Box::new(OrFilter::new(vec![
+            Box::new(EqFilter::new(field("001"), literal("1"))),
+            Box::new(EqFilter::new(field("002"), literal("x"))),
+        ])));
+        let mut records: Vec<Box<dyn Record>> = Vec::new();
+        for i in 0..4 {
+            let mut r = OwnedRecord::new();
+            r.add_field(OwnedRecordField {
+                field_type: 1,
+                data: i.to_string().into_bytes(),
+            });
+            records.push(Box::new(r));
+        }
+        assert_eq!(filter.filter(&mut records), (1, 4));
+        let stats = filter.stats().unwrap();
+        assert_eq!(stats.evaluated(), 4);
+        assert_eq!(stats.results(), (1, 0, 3));
+        assert_eq!(filter.describe(), "OrFilter");
+        let inputs = filter.inputs();
+        // the second input only sees the records the first one didn't match
+        assert_eq!(inputs[0].stats().unwrap().results(), (1, 3, 0));
+        assert_eq!(inputs[1].stats().unwrap().results(), (0, 0, 3));
+        assert_eq!(inputs[1].describe(), "EqFilter(002 = 'x')");
+    }
+}
@@ -1,23 +1,29 @@
-use crate::field_expression::FieldExpression;
 use crate::ownedrecord::OwnedRecord;
+use crate::scalar::ScalarExpression;
 use crate::Record;
 
 pub struct Projection {
-    exprs: Vec<Box<dyn FieldExpression>>,
+    exprs: Vec<Box<dyn ScalarExpression>>,
 }
 
 impl Projection {
-    pub fn new(exprs: Vec<Box<dyn FieldExpression>>) -> Projection {
+    pub fn new(exprs: Vec<Box<dyn ScalarExpression>>) -> Projection {
         Projection { exprs }
     }
+    // the expression of every column
+    pub fn describe(&self) -> Vec<String> {
+        self.exprs.iter().map(|x| x.describe()).collect()
+    }
+
     pub fn project<'a>(&self, values: &mut [Box<dyn Record + 'a>]) -> usize {
-        for i in 0..values.len() {
+        for value in values.iter_mut() {
             // todo this loses header information
             let mut result = OwnedRecord::new();
-            for expr in &self.exprs {
-                result.add_field_from_iter(&mut expr.compute(&*values[i]));
+            for (i, expr) in self.exprs.iter().enumerate() {
+                expr.project(&**value, &mut result);
+                result.set_column(i);
             }
-            values[i] = Box::new(result);
+            *value = Box::new(result);
         }
         0
     }
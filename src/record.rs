@@ -1,3 +1,5 @@
+use memchr::memchr;
+
 pub struct OwnedRecordField {
     pub field_type: usize,
     pub data: Vec<u8>,
@@ -8,6 +10,17 @@ pub struct RecordField<'s> {
     pub data: &'s [u8],
 }
 
+impl OwnedRecordField {
+    pub fn as_record_field(&self) -> RecordField<'_> {
+        RecordField {
+            field_type: self.field_type,
+            data: &self.data,
+        }
+    }
+}
+
+const SUBFIELD_DELIMITER: u8 = b'\x1f';
+
 impl<'s> RecordField<'s> {
     pub fn utf8_data(&self) -> &str {
         std::str::from_utf8(self.data).unwrap()
@@ -18,29 +31,231 @@ impl<'s> RecordField<'s> {
             data: self.data.to_vec(),
         }
     }
+
+    // 00X fields have neither indicators nor subfields
+    pub fn is_control_field(&self) -> bool {
+        self.field_type < 10
+    }
+
+    fn indicator(&self, i: usize) -> Option<u8> {
+        if self.is_control_field() {
+            None
+        } else {
+            self.data.get(i).cloned()
+        }
+    }
+
+    pub fn indicator1(&self) -> Option<u8> {
+        self.indicator(0)
+    }
+
+    pub fn indicator2(&self) -> Option<u8> {
+        self.indicator(1)
+    }
+
+    // (code, value) of every subfield, in order
+    pub fn subfields(&self) -> SubfieldIter<'s> {
+        let data = if self.is_control_field() || self.data.len() < 2 {
+            &[]
+        } else {
+            &self.data[2..]
+        };
+        // anything before the first delimiter isn't part of a subfield
+        let start = memchr(SUBFIELD_DELIMITER, data).unwrap_or(data.len());
+        SubfieldIter {
+            data: &data[start..],
+        }
+    }
+
+    pub fn subfield(&self, code: u8) -> Option<&'s [u8]> {
+        self.subfields_by_code(code).next()
+    }
+
+    pub fn subfields_by_code(&self, code: u8) -> impl Iterator<Item = &'s [u8]> {
+        self.subfields()
+            .filter(move |(c, _)| *c == code)
+            .map(|(_, value)| value)
+    }
+}
+
+pub struct SubfieldIter<'s> {
+    // always starts with a delimiter unless empty
+    data: &'s [u8],
 }
 
-#[derive(std::cmp::PartialEq, Clone)]
+impl<'s> Iterator for SubfieldIter<'s> {
+    type Item = (u8, &'s [u8]);
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.data.len() > 1 {
+            let rest = &self.data[1..];
+            let end = memchr(SUBFIELD_DELIMITER, rest).unwrap_or(rest.len());
+            self.data = &rest[end..];
+            if end > 0 {
+                return Some((rest[0], &rest[1..end]));
+            }
+            // two delimiters in a row, there is no subfield code
+        }
+        self.data = &[];
+        None
+    }
+}
+
+// leader/06
+#[derive(std::cmp::PartialEq, Clone, Copy, Debug)]
 pub enum RecordType {
+    // bibliographic
+    LanguageMaterial = b'a' as isize,
+    NotatedMusic = b'c' as isize,
+    ManuscriptNotatedMusic = b'd' as isize,
+    CartographicMaterial = b'e' as isize,
+    ManuscriptCartographicMaterial = b'f' as isize,
+    ProjectedMedium = b'g' as isize,
+    NonmusicalSoundRecording = b'i' as isize,
+    MusicalSoundRecording = b'j' as isize,
+    TwoDimensionalNonprojectableGraphic = b'k' as isize,
+    ComputerFile = b'm' as isize,
+    Kit = b'o' as isize,
+    MixedMaterials = b'p' as isize,
+    ThreeDimensionalArtifact = b'r' as isize,
+    ManuscriptLanguageMaterial = b't' as isize,
+    // holdings
+    UnknownHoldings = b'u' as isize,
+    MultipartItemHoldings = b'v' as isize,
+    SinglePartItemHoldings = b'x' as isize,
+    SerialItemHoldings = b'y' as isize,
+    Classification = b'w' as isize,
+    Community = b'q' as isize,
     Authority = b'z' as isize,
 }
 
+// the MARC 21 format a record type belongs to
+#[derive(std::cmp::PartialEq, Clone, Copy, Debug)]
+pub enum RecordFormat {
+    Bibliographic,
+    Authority,
+    Holdings,
+    Classification,
+    Community,
+}
+
 impl RecordType {
+    pub fn from_byte(b: u8) -> Option<RecordType> {
+        match b {
+            b'a' => Some(RecordType::LanguageMaterial),
+            b'c' => Some(RecordType::NotatedMusic),
+            b'd' => Some(RecordType::ManuscriptNotatedMusic),
+            b'e' => Some(RecordType::CartographicMaterial),
+            b'f' => Some(RecordType::ManuscriptCartographicMaterial),
+            b'g' => Some(RecordType::ProjectedMedium),
+            b'i' => Some(RecordType::NonmusicalSoundRecording),
+            b'j' => Some(RecordType::MusicalSoundRecording),
+            b'k' => Some(RecordType::TwoDimensionalNonprojectableGraphic),
+            b'm' => Some(RecordType::ComputerFile),
+            b'o' => Some(RecordType::Kit),
+            b'p' => Some(RecordType::MixedMaterials),
+            b'r' => Some(RecordType::ThreeDimensionalArtifact),
+            b't' => Some(RecordType::ManuscriptLanguageMaterial),
+            b'u' => Some(RecordType::UnknownHoldings),
+            b'v' => Some(RecordType::MultipartItemHoldings),
+            b'x' => Some(RecordType::SinglePartItemHoldings),
+            b'y' => Some(RecordType::SerialItemHoldings),
+            b'w' => Some(RecordType::Classification),
+            b'q' => Some(RecordType::Community),
+            b'z' => Some(RecordType::Authority),
+            _ => None,
+        }
+    }
+
+    // the record type qualifier of a field ref, "*" matches all types.
+    // type_ and the leader/06 code selects that type, the code alone too,
+    // except for "a": it meant authority records before the other types
+    // were known, so language material is only type_a.
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<RecordType> {
-        match s {
-            "a" => Some(RecordType::Authority),
-            "*" => None,
-            _ => todo!(),
+        match s.as_bytes() {
+            [b'a'] => Some(RecordType::Authority),
+            [b] => RecordType::from_byte(*b),
+            _ => match s.strip_prefix("type_").map(str::as_bytes) {
+                Some([b]) => RecordType::from_byte(*b),
+                _ => None,
+            },
+        }
+    }
+
+    // the qualifier that selects this type
+    pub fn qualifier(&self) -> String {
+        match self {
+            RecordType::LanguageMaterial => "type_a".to_string(),
+            _ => (*self as u8 as char).to_string(),
+        }
+    }
+
+    pub fn format(&self) -> RecordFormat {
+        match self {
+            RecordType::UnknownHoldings
+            | RecordType::MultipartItemHoldings
+            | RecordType::SinglePartItemHoldings
+            | RecordType::SerialItemHoldings => RecordFormat::Holdings,
+            RecordType::Classification => RecordFormat::Classification,
+            RecordType::Community => RecordFormat::Community,
+            RecordType::Authority => RecordFormat::Authority,
+            _ => RecordFormat::Bibliographic,
         }
     }
 }
 
+// field refs to the leader produce fields with this type, like the "000"
+// tag some systems use for the leader
+pub const LEADER_FIELD_TYPE: usize = 0;
+
 pub trait Record {
-    fn record_type(&self) -> RecordType;
+    // the 24 bytes of the leader
+    fn header(&self) -> &[u8];
+    // None if the leader doesn't hold a known record type
+    fn record_type(&self) -> Option<RecordType>;
     // todo nightly features might avoid the box
     // https://stackoverflow.com/questions/39482131/is-it-possible-to-use-impl-trait-as-a-functions-return-type-in-a-trait-defini/39490692#39490692
-    fn field_iter(&self, field_type: Option<usize>) -> Box<dyn Iterator<Item = RecordField> + '_>;
-    fn field_iter_vec(&self, field_types: &[usize]) -> Box<dyn Iterator<Item = RecordField> + '_>;
+    fn field_iter(
+        &self,
+        field_type: Option<usize>,
+    ) -> Box<dyn Iterator<Item = RecordField<'_>> + '_>;
+    fn field_iter_vec(
+        &self,
+        field_types: &[usize],
+    ) -> Box<dyn Iterator<Item = RecordField<'_>> + '_>;
 
     fn to_marc21(&self, writer: &mut dyn std::io::Write) -> std::io::Result<()>;
+
+    // the select column of every field, only projected rows have them
+    fn columns(&self) -> Option<&[usize]> {
+        None
+    }
+
+    // the record of a table of a join, 0 is the left table and 1 the joined one
+    fn joined(&self, _side: usize) -> Option<&dyn Record> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::record::*;
+
+    #[test]
+    fn malformed_subfields() {
+        let field = RecordField {
+            field_type: 245,
+            data: b"10junk\x1f\x1faTitle\x1fb\x1f",
+        };
+        assert_eq!(field.indicator1(), Some(b'1'));
+        assert_eq!(field.indicator2(), Some(b'0'));
+        let subfields: Vec<(u8, &[u8])> = field.subfields().collect();
+        assert_eq!(subfields, vec![(b'a', &b"Title"[..]), (b'b', &b""[..])]);
+        let short = RecordField {
+            field_type: 245,
+            data: b"1",
+        };
+        assert_eq!(short.indicator2(), None);
+        assert_eq!(short.subfields().count(), 0);
+    }
 }
@@ -0,0 +1,378 @@
+// Scalar functions in the select list. Functions of a single value are
+// applied to every value of their first argument, the other arguments are
+// parameters of which only the first value is used. concat and coalesce
+// combine their arguments instead.
+use crate::field_expression::FieldExpression;
+use crate::ownedrecord::OwnedRecord;
+use crate::record::{OwnedRecordField, Record};
+use crate::util::parse_number;
+use regex::bytes::Regex;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScalarFn {
+    Upper,
+    Lower,
+    Trim,
+    // substr(value, start, length), start counts characters from 1
+    Substr,
+    // in characters
+    Length,
+    Concat,
+    Replace,
+    RegexReplace,
+    // every part becomes a value of its own
+    Split,
+    Coalesce,
+}
+
+impl ScalarFn {
+    // names are case-insensitive, like keywords
+    pub fn from_name(name: &str) -> Option<ScalarFn> {
+        match name.to_ascii_lowercase().as_str() {
+            "upper" => Some(ScalarFn::Upper),
+            "lower" => Some(ScalarFn::Lower),
+            "trim" => Some(ScalarFn::Trim),
+            "substr" => Some(ScalarFn::Substr),
+            "length" => Some(ScalarFn::Length),
+            "concat" => Some(ScalarFn::Concat),
+            "replace" => Some(ScalarFn::Replace),
+            "regex_replace" => Some(ScalarFn::RegexReplace),
+            "split" => Some(ScalarFn::Split),
+            "coalesce" => Some(ScalarFn::Coalesce),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ScalarFn::Upper => "upper",
+            ScalarFn::Lower => "lower",
+            ScalarFn::Trim => "trim",
+            ScalarFn::Substr => "substr",
+            ScalarFn::Length => "length",
+            ScalarFn::Concat => "concat",
+            ScalarFn::Replace => "replace",
+            ScalarFn::RegexReplace => "regex_replace",
+            ScalarFn::Split => "split",
+            ScalarFn::Coalesce => "coalesce",
+        }
+    }
+
+    // the smallest and largest number of arguments
+    pub fn arity(self) -> (usize, Option<usize>) {
+        match self {
+            ScalarFn::Upper | ScalarFn::Lower | ScalarFn::Trim | ScalarFn::Length => (1, Some(1)),
+            ScalarFn::Substr => (2, Some(3)),
+            ScalarFn::Replace | ScalarFn::RegexReplace => (3, Some(3)),
+            ScalarFn::Split => (2, Some(2)),
+            ScalarFn::Concat | ScalarFn::Coalesce => (1, None),
+        }
+    }
+}
+
+pub trait ScalarExpression {
+    fn evaluate(&self, record: &dyn Record) -> Vec<OwnedRecordField>;
+
+    // the expression for explain
+    fn describe(&self) -> String;
+
+    fn is_constant(&self) -> bool {
+        false
+    }
+
+    // adds the values to a projected record
+    fn project(&self, record: &dyn Record, result: &mut OwnedRecord) {
+        for field in self.evaluate(record) {
+            result.add_field(field);
+        }
+    }
+}
+
+// the values of a field ref, unchanged
+pub struct FieldValues {
+    expr: Box<dyn FieldExpression>,
+}
+
+impl FieldValues {
+    pub fn new(expr: Box<dyn FieldExpression>) -> FieldValues {
+        FieldValues { expr }
+    }
+}
+
+impl ScalarExpression for FieldValues {
+    fn evaluate(&self, record: &dyn Record) -> Vec<OwnedRecordField> {
+        self.expr.compute(record).map(|x| x.to_owned()).collect()
+    }
+
+    fn project(&self, record: &dyn Record, result: &mut OwnedRecord) {
+        result.add_field_from_iter(&mut self.expr.compute(record));
+    }
+
+    fn describe(&self) -> String {
+        self.expr.describe()
+    }
+}
+
+// literals have the field type 0
+pub struct ConstantValue {
+    data: Vec<u8>,
+}
+
+impl ConstantValue {
+    pub fn new(data: Vec<u8>) -> ConstantValue {
+        ConstantValue { data }
+    }
+}
+
+impl ScalarExpression for ConstantValue {
+    fn evaluate(&self, _: &dyn Record) -> Vec<OwnedRecordField> {
+        vec![OwnedRecordField {
+            field_type: 0,
+            data: self.data.clone(),
+        }]
+    }
+
+    fn is_constant(&self) -> bool {
+        true
+    }
+
+    fn describe(&self) -> String {
+        format!("'{}'", String::from_utf8_lossy(&self.data))
+    }
+}
+
+pub struct FunctionCall {
+    func: ScalarFn,
+    arguments: Vec<Box<dyn ScalarExpression>>,
+    // the compiled pattern of regex_replace
+    regex: Option<Regex>,
+}
+
+impl FunctionCall {
+    pub fn new(func: ScalarFn, arguments: Vec<Box<dyn ScalarExpression>>) -> FunctionCall {
+        FunctionCall {
+            func,
+            arguments,
+            regex: None,
+        }
+    }
+
+    pub fn with_regex(mut self, regex: Regex) -> FunctionCall {
+        self.regex = Some(regex);
+        self
+    }
+
+    // the first value of a parameter, missing values are empty
+    fn parameter(&self, i: usize, record: &dyn Record) -> Vec<u8> {
+        self.arguments
+            .get(i)
+            .and_then(|x| x.evaluate(record).into_iter().next())
+            .map(|x| x.data)
+            .unwrap_or_default()
+    }
+
+    fn position(&self, i: usize, record: &dyn Record) -> Option<usize> {
+        parse_number(&self.parameter(i, record)).map(|x| if x < 0.0 { 0 } else { x as usize })
+    }
+
+    // the results of the function for one value, none drops the value
+    fn apply(&self, data: &[u8], record: &dyn Record) -> Vec<Vec<u8>> {
+        let text = String::from_utf8_lossy(data);
+        match self.func {
+            ScalarFn::Upper => vec![text.to_uppercase().into_bytes()],
+            ScalarFn::Lower => vec![text.to_lowercase().into_bytes()],
+            ScalarFn::Trim => vec![text.trim().as_bytes().to_vec()],
+            ScalarFn::Length => vec![text.chars().count().to_string().into_bytes()],
+            ScalarFn::Substr => {
+                let start = match self.position(1, record) {
+                    Some(start) => start.saturating_sub(1),
+                    None => return Vec::new(),
+                };
+                let length = if self.arguments.len() > 2 {
+                    match self.position(2, record) {
+                        Some(length) => length,
+                        None => return Vec::new(),
+                    }
+                } else {
+                    usize::MAX
+                };
+                let part: String = text.chars().skip(start).take(length).collect();
+                vec![part.into_bytes()]
+            }
+            ScalarFn::Replace => {
+                let from = String::from_utf8_lossy(&self.parameter(1, record)).into_owned();
+                let to = String::from_utf8_lossy(&self.parameter(2, record)).into_owned();
+                if from.is_empty() {
+                    vec![data.to_vec()]
+                } else {
+                    vec![text.replace(&from, &to).into_bytes()]
+                }
+            }
+            ScalarFn::RegexReplace => {
+                let replacement = self.parameter(2, record);
+                let regex = self.regex.as_ref().unwrap();
+                vec![regex.replace_all(data, replacement.as_slice()).into_owned()]
+            }
+            ScalarFn::Split => {
+                let separator = String::from_utf8_lossy(&self.parameter(1, record)).into_owned();
+                if separator.is_empty() {
+                    vec![data.to_vec()]
+                } else {
+                    text.split(separator.as_str())
+                        .map(|x| x.as_bytes().to_vec())
+                        .collect()
+                }
+            }
+            ScalarFn::Concat | ScalarFn::Coalesce => unreachable!(),
+        }
+    }
+}
+
+impl ScalarExpression for FunctionCall {
+    fn evaluate(&self, record: &dyn Record) -> Vec<OwnedRecordField> {
+        match self.func {
+            ScalarFn::Coalesce => self
+                .arguments
+                .iter()
+                .map(|x| x.evaluate(record))
+                .find(|x| !x.is_empty())
+                .unwrap_or_default(),
+            // the first value of every argument, one value in total
+            ScalarFn::Concat => {
+                let mut field_type = None;
+                let mut data = Vec::new();
+                for argument in &self.arguments {
+                    if let Some(value) = argument.evaluate(record).into_iter().next() {
+                        if !argument.is_constant() && field_type.is_none() {
+                            field_type = Some(value.field_type);
+                        }
+                        data.extend_from_slice(&value.data);
+                    }
+                }
+                vec![OwnedRecordField {
+                    field_type: field_type.unwrap_or(0),
+                    data,
+                }]
+            }
+            _ => self.arguments[0]
+                .evaluate(record)
+                .into_iter()
+                .flat_map(|value| {
+                    self.apply(&value.data, record)
+                        .into_iter()
+                        .map(move |data| OwnedRecordField {
+                            field_type: value.field_type,
+                            data,
+                        })
+                })
+                .collect(),
+        }
+    }
+
+    fn describe(&self) -> String {
+        let arguments: Vec<String> = self.arguments.iter().map(|x| x.describe()).collect();
+        format!("{}({})", self.func.name(), arguments.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::field_expression::*;
+    use crate::ownedrecord::test_record;
+    use crate::scalar::*;
+
+    fn record() -> OwnedRecord {
+        test_record(&[
+            (35, "  \x1fa(DE-588)4030318-1"),
+            (35, "  \x1fa(OCoLC)  1234 "),
+        ])
+    }
+
+    fn field(subfield: &str) -> Box<dyn ScalarExpression> {
+        Box::new(FieldValues::new(Box::new(FieldRefExpr::new(
+            None,
+            Some("035"),
+            Some(subfield),
+        ))))
+    }
+
+    fn constant(s: &str) -> Box<dyn ScalarExpression> {
+        Box::new(ConstantValue::new(s.as_bytes().to_vec()))
+    }
+
+    fn call(func: ScalarFn, arguments: Vec<Box<dyn ScalarExpression>>) -> Vec<String> {
+        FunctionCall::new(func, arguments)
+            .evaluate(&record())
+            .into_iter()
+            .map(|x| format!("{}:{}", x.field_type, String::from_utf8(x.data).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_string_functions() {
+        assert_eq!(
+            call(ScalarFn::Lower, vec![field("a")]),
+            vec!["35:(de-588)4030318-1", "35:(ocolc)  1234 "]
+        );
+        assert_eq!(
+            call(ScalarFn::Trim, vec![field("a")]),
+            vec!["35:(DE-588)4030318-1", "35:(OCoLC)  1234"]
+        );
+        assert_eq!(
+            call(ScalarFn::Length, vec![field("a")]),
+            vec!["35:17", "35:14"]
+        );
+        assert_eq!(
+            call(
+                ScalarFn::Substr,
+                vec![field("a"), constant("2"), constant("2")]
+            ),
+            vec!["35:DE", "35:OC"]
+        );
+        assert_eq!(
+            call(
+                ScalarFn::Replace,
+                vec![field("a"), constant("(DE-588)"), constant("")]
+            ),
+            vec!["35:4030318-1", "35:(OCoLC)  1234 "]
+        );
+        assert_eq!(
+            call(ScalarFn::Split, vec![field("a"), constant(")")]),
+            vec!["35:(DE-588", "35:4030318-1", "35:(OCoLC", "35:  1234 "]
+        );
+    }
+
+    #[test]
+    fn test_regex_replace() {
+        let result: Vec<Vec<u8>> = FunctionCall::new(
+            ScalarFn::RegexReplace,
+            vec![field("a"), constant(""), constant("$1")],
+        )
+        .with_regex(Regex::new(r"^\([^)]*\)\s*(.*?)\s*$").unwrap())
+        .evaluate(&record())
+        .into_iter()
+        .map(|x| x.data)
+        .collect();
+        assert_eq!(result, vec![b"4030318-1".to_vec(), b"1234".to_vec()]);
+    }
+
+    #[test]
+    fn test_combining_functions() {
+        assert_eq!(
+            call(
+                ScalarFn::Concat,
+                vec![constant("<"), field("a"), field("z")]
+            ),
+            vec!["35:<(DE-588)4030318-1"]
+        );
+        assert_eq!(
+            call(ScalarFn::Coalesce, vec![field("z"), constant("none")]),
+            vec!["0:none"]
+        );
+        assert_eq!(call(ScalarFn::Coalesce, vec![field("z")]).len(), 0);
+        assert_eq!(
+            FunctionCall::new(ScalarFn::Concat, vec![constant("<"), field("a")]).describe(),
+            "concat('<', 035.a)"
+        );
+    }
+}
@@ -0,0 +1,231 @@
+// ORDER BY, records are sorted by the first value of every sort key.
+// Records are collected in memory until the memory budget is exhausted,
+// then the run is sorted and spilled to a temporary file. At the end the
+// runs are merged.
+use crate::field_expression::FieldExpression;
+use crate::ownedrecord::OwnedRecord;
+use crate::record::Record;
+use crate::spill::{SpillItem, SpillReader, SpillWriter};
+use crate::util::parse_number;
+use std::cmp::Ordering;
+
+pub const DEFAULT_MEMORY_BUDGET: usize = 512 * 1024 * 1024;
+
+pub struct SortKey {
+    pub expr: Box<dyn FieldExpression>,
+    pub descending: bool,
+}
+
+// the first value of every sort key, None if a record doesn't have it
+pub type KeyValues = Vec<Option<Vec<u8>>>;
+
+// numbers sort before other values and compare by value, missing values
+// sort last. Unlike compare_values this is a total order.
+pub fn compare_value(a: Option<&[u8]>, b: Option<&[u8]>) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => match (parse_number(a), parse_number(b)) {
+            (Some(x), Some(y)) => x
+                .partial_cmp(&y)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.cmp(b)),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => a.cmp(b),
+        },
+    }
+}
+
+pub fn compare_keys(descending: &[bool], a: &KeyValues, b: &KeyValues) -> Ordering {
+    for (i, desc) in descending.iter().enumerate() {
+        let ord = compare_value(a[i].as_deref(), b[i].as_deref());
+        let ord = if *desc { ord.reverse() } else { ord };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    Ordering::Equal
+}
+
+pub struct ExternalSorter {
+    keys: Vec<SortKey>,
+    descending: Vec<bool>,
+    memory_budget: usize,
+    run: Vec<SpillItem>,
+    run_size: usize,
+    spilled: Vec<SpillReader>,
+}
+
+impl ExternalSorter {
+    pub fn new(keys: Vec<SortKey>, memory_budget: usize) -> ExternalSorter {
+        ExternalSorter {
+            descending: keys.iter().map(|x| x.descending).collect(),
+            keys,
+            memory_budget,
+            run: Vec::new(),
+            run_size: 0,
+            spilled: Vec::new(),
+        }
+    }
+
+    // the sort keys have to be computed on the record before projection
+    pub fn key_values(&self, record: &dyn Record) -> KeyValues {
+        self.keys
+            .iter()
+            .map(|key| key.expr.compute(record).next().map(|x| x.data.to_vec()))
+            .collect()
+    }
+
+    pub fn push(&mut self, keys: KeyValues, record: &dyn Record) -> std::io::Result<()> {
+        let item = SpillItem {
+            keys,
+            record: OwnedRecord::from_record(record),
+        };
+        self.run_size += item.size();
+        self.run.push(item);
+        if self.run_size > self.memory_budget {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn sort_run(&mut self) {
+        let descending = &self.descending;
+        // stable, records with equal keys stay in file order
+        self.run
+            .sort_by(|a, b| compare_keys(descending, &a.keys, &b.keys));
+    }
+
+    fn spill(&mut self) -> std::io::Result<()> {
+        self.sort_run();
+        let mut writer = SpillWriter::create()?;
+        for item in &self.run {
+            writer.write_item(item)?;
+        }
+        self.spilled.push(writer.finish()?);
+        self.run.clear();
+        self.run_size = 0;
+        Ok(())
+    }
+
+    pub fn spilled_runs(&self) -> usize {
+        self.spilled.len()
+    }
+
+    // hands the records to handle_record in sort order until it returns false
+    pub fn finish(
+        mut self,
+        handle_record: &mut dyn FnMut(&dyn Record) -> bool,
+    ) -> std::io::Result<()> {
+        self.sort_run();
+        let in_memory = std::mem::take(&mut self.run);
+        // earlier runs come first so that equal keys stay in file order
+        let mut runs: Vec<Box<dyn Iterator<Item = std::io::Result<SpillItem>>>> = Vec::new();
+        for run in std::mem::take(&mut self.spilled) {
+            runs.push(Box::new(run));
+        }
+        runs.push(Box::new(in_memory.into_iter().map(Ok)));
+        let mut heads: Vec<Option<SpillItem>> = Vec::with_capacity(runs.len());
+        for run in runs.iter_mut() {
+            heads.push(run.next().transpose()?);
+        }
+        // there are few runs, so a linear scan for the smallest head is fine
+        loop {
+            let mut min: Option<usize> = None;
+            for (i, head) in heads.iter().enumerate() {
+                if let Some(item) = head {
+                    let smaller = match min {
+                        None => true,
+                        Some(j) => {
+                            let other = heads[j].as_ref().unwrap();
+                            compare_keys(&self.descending, &item.keys, &other.keys)
+                                == Ordering::Less
+                        }
+                    };
+                    if smaller {
+                        min = Some(i);
+                    }
+                }
+            }
+            match min {
+                None => return Ok(()),
+                Some(i) => {
+                    let item = std::mem::replace(&mut heads[i], runs[i].next().transpose()?);
+                    if !handle_record(&item.unwrap().record) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::field_expression::*;
+    use crate::ownedrecord::*;
+    use crate::record::*;
+    use crate::sort::*;
+
+    fn sorted(descending: bool, memory_budget: usize) -> (Vec<String>, usize) {
+        let key = SortKey {
+            expr: Box::new(FieldRefExpr::new(None, Some("150"), None)),
+            descending,
+        };
+        let mut sorter = ExternalSorter::new(vec![key], memory_budget);
+        let data = [
+            ("1", Some("b")),
+            ("2", Some("10")),
+            ("3", None),
+            ("4", Some("9")),
+            ("5", Some("a")),
+            ("6", Some("b")),
+            ("7", Some("10.0")),
+        ];
+        for (id, value) in data.iter() {
+            let r = match value {
+                Some(value) => test_record(&[(1, id), (150, value)]),
+                None => test_record(&[(1, id)]),
+            };
+            let keys = sorter.key_values(&r);
+            sorter.push(keys, &r).unwrap();
+        }
+        let spilled = sorter.spilled_runs();
+        let mut ids = Vec::new();
+        sorter
+            .finish(&mut |r: &dyn Record| {
+                ids.push(
+                    r.field_iter(Some(1))
+                        .next()
+                        .unwrap()
+                        .utf8_data()
+                        .to_string(),
+                );
+                true
+            })
+            .unwrap();
+        (ids, spilled)
+    }
+
+    #[test]
+    fn test_sort_in_memory() {
+        let (ids, spilled) = sorted(false, DEFAULT_MEMORY_BUDGET);
+        assert_eq!(spilled, 0);
+        assert_eq!(ids, vec!["4", "2", "7", "5", "1", "6", "3"]);
+        let (ids, _) = sorted(true, DEFAULT_MEMORY_BUDGET);
+        assert_eq!(ids, vec!["3", "1", "6", "5", "7", "2", "4"]);
+    }
+
+    #[test]
+    fn test_sort_spilled() {
+        // every record gets its own run
+        let (ids, spilled) = sorted(false, 1);
+        assert_eq!(spilled, 7);
+        assert_eq!(ids, vec!["4", "2", "7", "5", "1", "6", "3"]);
+        let (ids, spilled) = sorted(true, 400);
+        assert!(spilled > 1);
+        assert_eq!(ids, vec!["3", "1", "6", "5", "7", "2", "4"]);
+    }
+}
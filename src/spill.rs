@@ -0,0 +1,224 @@
+// Temporary files for operators that don't fit into their memory budget.
+// Items are written once and read back in the same order.
+use crate::ownedrecord::OwnedRecord;
+use crate::sort::KeyValues;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+pub struct SpillItem {
+    pub keys: KeyValues,
+    pub record: OwnedRecord,
+}
+
+impl SpillItem {
+    // roughly what the item occupies on the heap
+    pub fn size(&self) -> usize {
+        let keys: usize = self.keys.iter().flatten().map(|x| x.len() + 24).sum();
+        let fields: usize = self.record.field_data.iter().map(|x| x.len() + 32).sum();
+        keys + fields + 96
+    }
+}
+
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// deleted when dropped
+struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    fn create() -> std::io::Result<(TempFile, File)> {
+        let n = TEMP_FILE_COUNTER.fetch_add(1, AtomicOrdering::SeqCst);
+        let path = std::env::temp_dir().join(format!("marc21-spill-{}-{}", std::process::id(), n));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok((TempFile { path }, file))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn write_len(writer: &mut dyn Write, len: usize) -> std::io::Result<()> {
+    writer.write_all(&(len as u64).to_le_bytes())
+}
+
+fn read_len(reader: &mut dyn Read) -> std::io::Result<usize> {
+    let mut buf = [0_u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf) as usize)
+}
+
+fn read_bytes(reader: &mut dyn Read) -> std::io::Result<Vec<u8>> {
+    let mut buf = vec![0; read_len(reader)?];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn write_item(writer: &mut dyn Write, item: &SpillItem) -> std::io::Result<()> {
+    write_len(writer, item.keys.len())?;
+    for key in &item.keys {
+        match key {
+            None => writer.write_all(&[0])?,
+            Some(value) => {
+                writer.write_all(&[1])?;
+                write_len(writer, value.len())?;
+                writer.write_all(value)?;
+            }
+        }
+    }
+    writer.write_all(&item.record.header)?;
+    write_len(writer, item.record.field_types.len())?;
+    for (field_type, data) in item
+        .record
+        .field_types
+        .iter()
+        .zip(item.record.field_data.iter())
+    {
+        write_len(writer, *field_type)?;
+        write_len(writer, data.len())?;
+        writer.write_all(data)?;
+    }
+    write_len(writer, item.record.columns.len())?;
+    for column in &item.record.columns {
+        write_len(writer, *column)?;
+    }
+    Ok(())
+}
+
+fn read_item(reader: &mut dyn Read) -> std::io::Result<SpillItem> {
+    let key_count = read_len(reader)?;
+    let mut keys = Vec::with_capacity(key_count);
+    for _ in 0..key_count {
+        let mut present = [0_u8; 1];
+        reader.read_exact(&mut present)?;
+        keys.push(if present[0] == 0 {
+            None
+        } else {
+            Some(read_bytes(reader)?)
+        });
+    }
+    let mut record = OwnedRecord::new();
+    reader.read_exact(&mut record.header)?;
+    let field_count = read_len(reader)?;
+    for _ in 0..field_count {
+        record.field_types.push(read_len(reader)?);
+        record.field_data.push(read_bytes(reader)?);
+    }
+    let column_count = read_len(reader)?;
+    for _ in 0..column_count {
+        record.columns.push(read_len(reader)?);
+    }
+    Ok(SpillItem { keys, record })
+}
+
+pub struct SpillWriter {
+    file: TempFile,
+    writer: BufWriter<File>,
+    count: usize,
+}
+
+impl SpillWriter {
+    pub fn create() -> std::io::Result<SpillWriter> {
+        let (file, handle) = TempFile::create()?;
+        Ok(SpillWriter {
+            file,
+            writer: BufWriter::new(handle),
+            count: 0,
+        })
+    }
+
+    pub fn write_item(&mut self, item: &SpillItem) -> std::io::Result<()> {
+        self.count += 1;
+        write_item(&mut self.writer, item)
+    }
+
+    // bytes that precede the next item, the reader has to know their length
+    pub fn write_raw(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.writer.write_all(data)
+    }
+
+    pub fn finish(self) -> std::io::Result<SpillReader> {
+        let mut handle = self.writer.into_inner().map_err(|x| x.into_error())?;
+        handle.flush()?;
+        handle.seek(SeekFrom::Start(0))?;
+        Ok(SpillReader {
+            _file: self.file,
+            reader: BufReader::new(handle),
+            remaining: self.count,
+        })
+    }
+}
+
+pub struct SpillReader {
+    _file: TempFile,
+    reader: BufReader<File>,
+    remaining: usize,
+}
+
+impl SpillReader {
+    pub fn is_empty(&self) -> bool {
+        self.remaining == 0
+    }
+
+    pub fn read_raw(&mut self, data: &mut [u8]) -> std::io::Result<()> {
+        self.reader.read_exact(data)
+    }
+}
+
+impl Iterator for SpillReader {
+    type Item = std::io::Result<SpillItem>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        Some(read_item(&mut self.reader))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ownedrecord::*;
+    use crate::record::*;
+    use crate::spill::*;
+
+    #[test]
+    fn test_round_trip() -> std::io::Result<()> {
+        let mut record = OwnedRecord::new();
+        record.header.copy_from_slice(b"00000nz  a2200000nc 4500");
+        record.add_field(OwnedRecordField {
+            field_type: 150,
+            data: b"\x1faKatze".to_vec(),
+        });
+        let item = SpillItem {
+            keys: vec![Some(b"Katze".to_vec()), None],
+            record,
+        };
+        let mut writer = SpillWriter::create()?;
+        writer.write_raw(b"xy")?;
+        writer.write_item(&item)?;
+        writer.write_item(&item)?;
+        let mut reader = writer.finish()?;
+        let mut raw = [0_u8; 2];
+        reader.read_raw(&mut raw)?;
+        assert_eq!(&raw, b"xy");
+        let items: Vec<SpillItem> = reader.collect::<std::io::Result<_>>()?;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].keys, item.keys);
+        assert_eq!(items[1].record.header, item.record.header);
+        assert_eq!(
+            items[1].record.field_iter(None).next().unwrap().data,
+            b"\x1faKatze"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+// The tables of the from clause. A name is a file <name>.mrc in the current
+// directory, "stdin" is standard input and a quoted path can contain the
+// wildcards * and ?. All files of a table are read one after the other,
+// each by its own reader.
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableRef {
+    Name(String),
+    Path(String),
+}
+
+impl std::fmt::Display for TableRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TableRef::Name(name) => write!(f, "{}", name),
+            TableRef::Path(path) => write!(f, "'{}'", path),
+        }
+    }
+}
+
+impl TableRef {
+    pub fn is_stdin(&self) -> bool {
+        *self == TableRef::Name("stdin".to_string())
+    }
+
+    // the parts of the table in the order they are read
+    pub fn parts(&self) -> std::io::Result<Vec<TablePart>> {
+        if self.is_stdin() {
+            Ok(vec![TablePart::Stdin])
+        } else {
+            Ok(self.paths()?.into_iter().map(TablePart::File).collect())
+        }
+    }
+
+    // the files of the table in the order they are read
+    pub fn paths(&self) -> std::io::Result<Vec<PathBuf>> {
+        match self {
+            TableRef::Name(name) => Ok(vec![PathBuf::from(format!("{}.mrc", name))]),
+            TableRef::Path(path) if !has_wildcards(path) => Ok(vec![PathBuf::from(path)]),
+            TableRef::Path(pattern) => {
+                let paths = glob(pattern)?;
+                if paths.is_empty() {
+                    Err(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        "no files match the pattern",
+                    ))
+                } else {
+                    Ok(paths)
+                }
+            }
+        }
+    }
+}
+
+fn has_wildcards(s: &str) -> bool {
+    s.contains(['*', '?'])
+}
+
+// * matches any number of characters, ? exactly one
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|i| wildcard_match(&pattern[1..], &name[i..])),
+        Some('?') => !name.is_empty() && wildcard_match(&pattern[1..], &name[1..]),
+        Some(c) => name.first() == Some(c) && wildcard_match(&pattern[1..], &name[1..]),
+    }
+}
+
+// the existing paths that match the pattern, sorted. Wildcards don't match
+// a leading dot, like in the shell.
+pub fn glob(pattern: &str) -> std::io::Result<Vec<PathBuf>> {
+    let mut candidates = vec![PathBuf::new()];
+    for component in Path::new(pattern).components() {
+        let part = component.as_os_str().to_string_lossy();
+        if !has_wildcards(&part) {
+            for candidate in candidates.iter_mut() {
+                candidate.push(component);
+            }
+            continue;
+        }
+        let part: Vec<char> = part.chars().collect();
+        let mut matches = Vec::new();
+        for dir in &candidates {
+            let entries = if dir.as_os_str().is_empty() {
+                std::fs::read_dir(".")
+            } else {
+                std::fs::read_dir(dir)
+            };
+            // not a directory
+            let entries = match entries {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries {
+                let name = entry?.file_name();
+                let name: Vec<char> = name.to_string_lossy().chars().collect();
+                if name.first() == Some(&'.') && part.first() != Some(&'.') {
+                    continue;
+                }
+                if wildcard_match(&part, &name) {
+                    matches.push(dir.join(name.iter().collect::<String>()));
+                }
+            }
+        }
+        matches.sort();
+        candidates = matches;
+    }
+    Ok(candidates.into_iter().filter(|x| x.exists()).collect())
+}
+
+// what a single reader reads, errors name it
+#[derive(Debug, Clone, PartialEq)]
+pub enum TablePart {
+    Stdin,
+    File(PathBuf),
+}
+
+impl std::fmt::Display for TablePart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TablePart::Stdin => write!(f, "stdin"),
+            TablePart::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::table::*;
+    use std::fs::File;
+    use std::io::Write;
+
+    #[test]
+    fn test_wildcard_match() {
+        let m = |p: &str, n: &str| {
+            wildcard_match(
+                &p.chars().collect::<Vec<_>>(),
+                &n.chars().collect::<Vec<_>>(),
+            )
+        };
+        assert!(m("title_*.mrc", "title_01.mrc"));
+        assert!(m("title_*.mrc", "title_.mrc"));
+        assert!(!m("title_*.mrc", "title_01.mrc.gz"));
+        assert!(m("title_0?.mrc", "title_01.mrc"));
+        assert!(!m("title_0?.mrc", "title_1.mrc"));
+        assert!(m("*", "x"));
+    }
+
+    #[test]
+    fn test_glob() -> std::io::Result<()> {
+        let dir = std::env::temp_dir().join(format!("marc21-glob-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("b"))?;
+        for name in ["title_2.mrc", "title_1.mrc", "gnd.mrc", "b/title_3.mrc"].iter() {
+            File::create(dir.join(name))?.write_all(name.as_bytes())?;
+        }
+        let pattern = format!("{}/title_*.mrc", dir.display());
+        let paths = glob(&pattern)?;
+        assert_eq!(
+            paths,
+            vec![dir.join("title_1.mrc"), dir.join("title_2.mrc")]
+        );
+        let paths = TableRef::Path(format!("{}/*/title_*", dir.display())).paths()?;
+        assert_eq!(paths, vec![dir.join("b/title_3.mrc")]);
+        assert!(TableRef::Path(format!("{}/x*", dir.display()))
+            .paths()
+            .is_err());
+
+        assert_eq!(
+            TableRef::Path(pattern).parts()?,
+            vec![
+                TablePart::File(dir.join("title_1.mrc")),
+                TablePart::File(dir.join("title_2.mrc"))
+            ]
+        );
+        assert_eq!(
+            TableRef::Name("stdin".to_string()).parts()?,
+            vec![TablePart::Stdin]
+        );
+        std::fs::remove_dir_all(&dir)
+    }
+}
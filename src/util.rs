@@ -14,6 +14,24 @@ impl std::ops::Not for TriStateBool {
         }
     }
 }
+// the numeric value of a field, surrounding whitespace is ignored
+// only plain decimal numbers count, no exponents, inf or nan
+pub fn parse_number(slice: &[u8]) -> Option<f64> {
+    let s = std::str::from_utf8(slice).ok()?.trim();
+    if !s.bytes().all(|b| b.is_ascii_digit() || b"+-.".contains(&b)) {
+        return None;
+    }
+    s.parse::<f64>().ok()
+}
+
+// numbers compare by value, everything else byte by byte
+pub fn compare_values(a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+    match (parse_number(a), parse_number(b)) {
+        (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or_else(|| a.cmp(b)),
+        _ => a.cmp(b),
+    }
+}
+
 pub fn parse_usize5(slice: &[u8]) -> usize {
     unsafe {
         let n0 = *(slice.get_unchecked(0)) as usize;
@@ -34,7 +52,7 @@ pub fn parse_usize4(slice: &[u8]) -> usize {
         let n2 = *(slice.get_unchecked(2)) as usize;
         let n3 = *(slice.get_unchecked(3)) as usize;
         let zero = b'0' as usize;
-        n0 * 1000 + n1 * 100 + n2 * 10 + n3 - (1000 * zero + 100 * zero + 10 * zero + 1 * zero)
+        n0 * 1000 + n1 * 100 + n2 * 10 + n3 - (1000 * zero + 100 * zero + 10 * zero + zero)
     }
 }
 
@@ -44,7 +62,7 @@ pub fn parse_usize3(slice: &[u8]) -> usize {
         let n1 = *(slice.get_unchecked(1)) as usize;
         let n2 = *(slice.get_unchecked(2)) as usize;
         let zero = b'0' as usize;
-        n0 * 100 + n1 * 10 + n2 - (100 * zero + 10 * zero + 1 * zero)
+        n0 * 100 + n1 * 10 + n2 - (100 * zero + 10 * zero + zero)
     }
 }
 